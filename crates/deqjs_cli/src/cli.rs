@@ -1,12 +1,18 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand, ValueEnum, builder::{Styles, styling::{AnsiColor, Effects}}, crate_description, crate_name, crate_version};
+use clap::{Args, Parser, Subcommand, ValueEnum, builder::{Styles, styling::{AnsiColor, Effects}}, crate_description, crate_name, crate_version};
 use clap_complete::Shell;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum DecompileModeCli {
     Pseudo,
     Disasm,
+    /// Pseudo code with each statement's instructions as trailing comments
+    PseudoAnnotated,
+    /// Disassembly as JSON: an array of functions with their instructions
+    Json,
+    /// Control-flow graph of each function as Graphviz DOT
+    Cfg,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
@@ -42,33 +48,187 @@ pub enum TopLevel {
         #[arg(value_enum)]
         shell: Shell,
     },
+    /// Generate a roff man page
+    Man,
+}
+
+#[derive(Args)]
+pub struct DecompileArgs {
+    /// Output mode
+    #[arg(long, value_enum, default_value_t = DecompileModeCli::Pseudo)]
+    pub mode: DecompileModeCli,
+
+    /// Select bytecode version (default: auto-detect)
+    #[arg(long, value_enum, default_value_t = DecompileVersionCli::Auto)]
+    pub version: DecompileVersionCli,
+
+    /// Read the file as bytecode version N, ignoring its version byte (1 = legacy layout)
+    #[arg(long, value_name = "N", conflicts_with = "version")]
+    pub version_byte: Option<u8>,
+
+    /// Continue with a warning when the version byte is close to, but not exactly, a supported one
+    #[arg(long, default_value_t = false)]
+    pub lenient_version: bool,
+
+    /// Give human readable names to anonymous functions / closures, and undo
+    /// control-flow flattening (`while (true) switch (state)` dispatchers)
+    #[arg(long, default_value_t = false)]
+    pub deobfuscate: bool,
+
+    /// Apply simple output optimizations to reduce generated pseudo code size
+    #[arg(long, default_value_t = false)]
+    pub optimize: bool,
+
+    /// Don't emit discarded side-effecting expressions (e.g. unused call results) as statements
+    #[arg(long, default_value_t = false)]
+    pub hide_dropped_exprs: bool,
+
+    /// Emit the first assignment to each local as a `let`/`const` declaration
+    #[arg(long, default_value_t = false)]
+    pub declare_locals: bool,
+
+    /// Render object properties in JS enumeration order (integer keys first) instead of serialized order
+    #[arg(long, default_value_t = false)]
+    pub normalize_property_order: bool,
+
+    /// Truncate the output once it reaches N bytes
+    #[arg(long, value_name = "N")]
+    pub max_output_bytes: Option<usize>,
+
+    /// Post-process pseudo output so it parses as JS (placeholders stubbed, gotos commented out)
+    #[arg(long, default_value_t = false)]
+    pub emit_runnable: bool,
+
+    /// Give up structuring a function after this many milliseconds and emit its disassembly instead
+    #[arg(long, value_name = "MS")]
+    pub timeout: Option<u64>,
+
+    /// In disasm mode, list undecodable bytes as `.byte 0xNN` and keep going instead of failing
+    #[arg(long, default_value_t = false)]
+    pub recover: bool,
+
+    /// In disasm mode, end each instruction with the stack depth before and after it
+    #[arg(long, default_value_t = false)]
+    pub show_stack: bool,
+}
+
+/// The bytecode file a listing subcommand reads.
+#[derive(Args)]
+pub struct InputArgs {
+    /// Path to the QuickJS bytecode file (`-` for stdin)
+    pub path: PathBuf,
+
+    /// Select bytecode version (default: auto-detect)
+    #[arg(long, value_enum, default_value_t = DecompileVersionCli::Auto)]
+    pub version: DecompileVersionCli,
+
+    /// Read the file as bytecode version N, ignoring its version byte (1 = legacy layout)
+    #[arg(long, value_name = "N", conflicts_with = "version")]
+    pub version_byte: Option<u8>,
+
+    /// Accept a version byte close to, but not exactly, a supported one
+    #[arg(long, default_value_t = false)]
+    pub lenient_version: bool,
 }
 
 #[derive(Subcommand)]
 pub enum DecompileCommand {
     /// Decompiles a QuickJS bytecode file
     File {
-        /// Path to the QuickJS bytecode file
+        /// Path to the QuickJS bytecode file (`-` for stdin)
         path: PathBuf,
 
-        /// Output mode
-        #[arg(long, value_enum, default_value_t = DecompileModeCli::Pseudo)]
-        mode: DecompileModeCli,
+        #[command(flatten)]
+        args: DecompileArgs,
 
-        /// Select bytecode version (default: auto-detect)
-        #[arg(long, value_enum, default_value_t = DecompileVersionCli::Auto)]
-        version: DecompileVersionCli,
+        /// Only output the Nth function in traversal order (entry function is 0)
+        #[arg(long, value_name = "N")]
+        function_index: Option<usize>,
 
-        /// Give human readable names to anonymous functions / closures
-        #[arg(long, default_value_t = false)]
-        deobfuscate: bool,
+        /// Only output one function, selected by index or by name as shown in `decompile list`
+        #[arg(long, value_name = "INDEX|NAME", conflicts_with = "function_index")]
+        function: Option<String>,
 
-        /// Apply simple output optimizations to reduce generated pseudo code size
+        /// Check each function's stack effects across branches and print any underflow or depth mismatch to stderr
         #[arg(long, default_value_t = false)]
-        optimize: bool,
+        validate: bool,
+
+        /// Output file path, creating missing parent directories (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Write each function to its own `<name>.js` file in this directory
+        #[arg(long, value_name = "DIR", conflicts_with = "output")]
+        output_dir: Option<PathBuf>,
+    },
+    /// Decompiles a byte array from C source, such as the output of `qjsc -c`
+    CArray {
+        /// Path to the C source file (`-` for stdin)
+        path: PathBuf,
+
+        /// Name of the array to decompile (default: the first one)
+        #[arg(long, value_name = "NAME")]
+        symbol: Option<String>,
+
+        #[command(flatten)]
+        args: DecompileArgs,
 
-        /// Output file path (default: stdout)
-        #[arg(long)]
+        /// Output file path, creating missing parent directories (default: stdout)
+        #[arg(short, long)]
         output: Option<PathBuf>,
-    }
+    },
+    /// Decompiles every file in a directory or matching a glob pattern
+    Batch {
+        /// Directory (searched recursively for `.jsc` and `.bin` files) or glob
+        /// pattern, e.g. `out/**/*.jsc`
+        pattern: String,
+
+        #[command(flatten)]
+        args: DecompileArgs,
+
+        /// Mirror outputs into this directory instead of writing `<file>.js` next to each input
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+    },
+    /// Lists the strings in a QuickJS bytecode file (atoms and string constants), one per line
+    Strings {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Prefix each string with where it was found, e.g. `atom 230` or `function 1 (f) cpool[0]`
+        #[arg(long, default_value_t = false)]
+        locations: bool,
+    },
+    /// Checks that a QuickJS bytecode file parses and decodes, without output
+    Check {
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Lists each function's constant pool, the operands of `push_const` and `fclosure`
+    Cpool {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Only list one function's pool, selected by index or by name as shown in `decompile list`
+        #[arg(long, value_name = "INDEX|NAME")]
+        function: Option<String>,
+    },
+    /// Lists the atoms (strings and symbols) defined in a QuickJS bytecode file
+    Atoms {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Print the table as a JSON array instead of one tab-separated line per atom
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Lists every function with its index, name, and header sizes, entry function first
+    List {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Print the list as a JSON array instead of one tab-separated line per function
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
 }
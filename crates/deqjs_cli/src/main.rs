@@ -1,6 +1,10 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
 use clap::{CommandFactory, Parser};
 
-use crate::cli::{Cli, TopLevel, DecompileCommand, DecompileModeCli, DecompileVersionCli};
+use crate::cli::{Cli, TopLevel, DecompileArgs, DecompileCommand, DecompileModeCli, DecompileVersionCli, InputArgs};
 
 mod cli;
 
@@ -11,50 +15,257 @@ fn main() {
         Some(TopLevel::Decompile { command }) => match command {
             DecompileCommand::File {
                 path,
-                mode,
-                version,
-                deobfuscate,
-                optimize,
+                args,
+                function_index,
+                function,
+                validate,
                 output,
+                output_dir,
             } => {
-                let mode = match mode {
-                    DecompileModeCli::Pseudo => deqjs_lib::DecompileMode::Pseudo,
-                    DecompileModeCli::Disasm => deqjs_lib::DecompileMode::Disasm,
-                };
-                let version = match version {
-                    DecompileVersionCli::Auto => deqjs_lib::DecompileVersion::Auto,
-                    DecompileVersionCli::Current => deqjs_lib::DecompileVersion::Current,
-                    DecompileVersionCli::Legacy => deqjs_lib::DecompileVersion::Legacy,
+                let bytes = match read_input(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("failed to read {path:?}: {e}");
+                        std::process::exit(1);
+                    }
                 };
-                match std::fs::read(&path) {
-                    Ok(bytes) => match deqjs_lib::decompile_with_options(
-                        &bytes,
-                        deqjs_lib::DecompileOptions {
-                            mode,
-                            version,
-                            deobfuscate,
-                            optimize,
-                        },
-                    ) {
-                        Ok(out) => {
-                            if let Some(output_path) = output {
-                                if let Err(e) = std::fs::write(&output_path, &out) {
-                                    eprintln!("failed to write to {output_path:?}: {e}");
-                                    std::process::exit(1);
-                                }
-                            } else {
-                                print!("{out}");
-                            }
+                let function_index = match function {
+                    Some(selector) => match deqjs_lib::select_function(&bytes, lib_options(&args), &selector) {
+                        Ok(index) => Some(index),
+                        Err(e) => {
+                            eprintln!("{}: {e}", path.display());
+                            std::process::exit(1);
                         }
+                    },
+                    None => function_index,
+                };
+                let options = deqjs_lib::DecompileOptions {
+                    function_index,
+                    ..lib_options(&args)
+                };
+                if validate {
+                    print_stack_warnings(&path, &bytes, options);
+                }
+                if let Some(dir) = output_dir {
+                    match deqjs_lib::decompile_functions(&bytes, options) {
+                        Ok(funcs) => write_function_files(&dir, &funcs),
                         Err(e) => {
                             eprintln!("decompile error: {e}");
                             std::process::exit(1);
                         }
-                    },
+                    }
+                    return;
+                }
+                match deqjs_lib::decompile_with_options(&bytes, options) {
+                    Ok(out) => {
+                        if let Some(output_path) = output {
+                            write_output(&output_path, &out);
+                        } else {
+                            print!("{out}");
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("decompile error: {e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            DecompileCommand::CArray { path, symbol, args, output } => {
+                let src = match read_input(&path) {
+                    Ok(src) => String::from_utf8_lossy(&src).into_owned(),
+                    Err(e) => {
+                        eprintln!("failed to read {path:?}: {e}");
+                        std::process::exit(1);
+                    }
+                };
+                let out = deqjs_lib::c_array_bytes(&src, symbol.as_deref())
+                    .and_then(|bytes| deqjs_lib::decompile_with_options(&bytes, lib_options(&args)));
+                match out {
+                    Ok(out) => {
+                        if let Some(output_path) = output {
+                            write_output(&output_path, &out);
+                        } else {
+                            print!("{out}");
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("decompile error: {e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            DecompileCommand::Batch { pattern, args, output_dir } => {
+                let options = lib_options(&args);
+                let (base, files) = match batch_inputs(&pattern) {
+                    Ok(found) => found,
+                    Err(e) => {
+                        eprintln!("invalid pattern {pattern:?}: {e}");
+                        std::process::exit(1);
+                    }
+                };
+                let mut ok = 0usize;
+                let mut failures: BTreeMap<&'static str, usize> = BTreeMap::new();
+                for file in &files {
+                    let bytes = match std::fs::read(file) {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            eprintln!("{}: failed to read: {e}", file.display());
+                            *failures.entry("io").or_default() += 1;
+                            continue;
+                        }
+                    };
+                    let out = match deqjs_lib::decompile_with_options(&bytes, options) {
+                        Ok(out) => out,
+                        Err(e) => {
+                            eprintln!("{}: {e}", file.display());
+                            *failures.entry(failure_kind(&e)).or_default() += 1;
+                            continue;
+                        }
+                    };
+                    let dest = batch_output_path(file, &base, output_dir.as_deref());
+                    if let Some(parent) = dest.parent()
+                        && let Err(e) = std::fs::create_dir_all(parent)
+                    {
+                        eprintln!("failed to create {parent:?}: {e}");
+                        *failures.entry("io").or_default() += 1;
+                        continue;
+                    }
+                    if let Err(e) = std::fs::write(&dest, &out) {
+                        eprintln!("failed to write to {dest:?}: {e}");
+                        *failures.entry("io").or_default() += 1;
+                        continue;
+                    }
+                    ok += 1;
+                }
+                let failed: usize = failures.values().sum();
+                println!("{} files: {ok} ok, {failed} failed", files.len());
+                for (kind, count) in &failures {
+                    println!("  {kind}: {count}");
+                }
+                if failed > 0 {
+                    std::process::exit(1);
+                }
+            }
+            DecompileCommand::Strings { input: InputArgs { path, version, version_byte, lenient_version }, locations } => {
+                let bytes = match read_input(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("failed to read {path:?}: {e}");
+                        std::process::exit(1);
+                    }
+                };
+                let program = match deqjs_lib::DecodedProgram::parse(&bytes, lib_version(version, version_byte), lenient_version) {
+                    Ok(program) => program,
+                    Err(e) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                };
+                for s in program.strings() {
+                    // one string per line (tab-separated from its location), so
+                    // line breaks and tabs inside one are escaped
+                    let text = s.text.replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t");
+                    if locations {
+                        println!("{}\t{text}", s.location);
+                    } else {
+                        println!("{text}");
+                    }
+                }
+            }
+            DecompileCommand::Check { input: InputArgs { path, version, version_byte, lenient_version } } => {
+                let bytes = match read_input(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("failed to read {path:?}: {e}");
+                        std::process::exit(1);
+                    }
+                };
+                match deqjs_lib::check(&bytes, lib_version(version, version_byte), lenient_version) {
+                    Ok(count) => println!("{}: ok ({count} functions)", path.display()),
+                    Err(e) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                }
+            }
+            DecompileCommand::Cpool { input: InputArgs { path, version, version_byte, lenient_version }, function } => {
+                let bytes = match read_input(&path) {
+                    Ok(bytes) => bytes,
                     Err(e) => {
                         eprintln!("failed to read {path:?}: {e}");
                         std::process::exit(1);
                     }
+                };
+                let version = lib_version(version, version_byte);
+                let selected = function
+                    .map(|selector| deqjs_lib::select_function(&bytes, deqjs_lib::DecompileOptions { version, lenient_version, ..Default::default() }, &selector))
+                    .transpose();
+                let pools = selected.and_then(|selected| Ok((selected, deqjs_lib::constant_pools(&bytes, version, lenient_version)?)));
+                let (selected, pools) = match pools {
+                    Ok(found) => found,
+                    Err(e) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                };
+                for f in pools.iter().filter(|f| selected.is_none_or(|index| index == f.index)) {
+                    println!("function {} ({}):", f.index, f.name);
+                    for (i, c) in f.constants.iter().enumerate() {
+                        println!("  {i}\t{c}");
+                    }
+                }
+            }
+            DecompileCommand::Atoms { input: InputArgs { path, version, version_byte, lenient_version }, json } => {
+                let bytes = match read_input(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("failed to read {path:?}: {e}");
+                        std::process::exit(1);
+                    }
+                };
+                let atoms = match deqjs_lib::atoms(&bytes, lib_version(version, version_byte), lenient_version) {
+                    Ok(atoms) => atoms,
+                    Err(e) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                };
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&atoms).unwrap_or_default());
+                } else {
+                    for a in &atoms {
+                        let (kind, text) = match &a.atom {
+                            deqjs_lib::AtomRepr::String(s) => ("string", s.clone()),
+                            deqjs_lib::AtomRepr::Symbol { desc, .. } => ("symbol", desc.clone()),
+                            other => ("raw", other.to_string()),
+                        };
+                        // same escaping as `strings`, to keep one atom per line
+                        let text = text.replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t");
+                        println!("{}\t{kind}\t{text}", a.index);
+                    }
+                }
+            }
+            DecompileCommand::List { input: InputArgs { path, version, version_byte, lenient_version }, json } => {
+                let bytes = match read_input(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("failed to read {path:?}: {e}");
+                        std::process::exit(1);
+                    }
+                };
+                let funcs = match deqjs_lib::list_functions(&bytes, lib_version(version, version_byte), lenient_version) {
+                    Ok(funcs) => funcs,
+                    Err(e) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                };
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&funcs).unwrap_or_default());
+                } else {
+                    for f in &funcs {
+                        println!("{}\t{}\t{}\t{}\t{}\t{}", f.index, f.name, f.arg_count, f.var_count, f.stack_size, f.byte_code_len);
+                    }
                 }
             }
         },
@@ -63,8 +274,183 @@ fn main() {
             let bin_name = cmd.get_name().to_string();
             clap_complete::generate(shell, &mut cmd, bin_name, &mut std::io::stdout());
         }
+        Some(TopLevel::Man) => {
+            if let Err(e) = clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout()) {
+                eprintln!("failed to render man page: {e}");
+                std::process::exit(1);
+            }
+        }
         None => {
             Cli::command().print_help().unwrap();
         }
     }
 }
+
+fn lib_options(args: &DecompileArgs) -> deqjs_lib::DecompileOptions {
+    let mode = match args.mode {
+        DecompileModeCli::Pseudo => deqjs_lib::DecompileMode::Pseudo,
+        DecompileModeCli::Disasm => deqjs_lib::DecompileMode::Disasm,
+        DecompileModeCli::PseudoAnnotated => deqjs_lib::DecompileMode::PseudoAnnotated,
+        DecompileModeCli::Json => deqjs_lib::DecompileMode::Json,
+        DecompileModeCli::Cfg => deqjs_lib::DecompileMode::Cfg,
+    };
+    deqjs_lib::DecompileOptions {
+        mode,
+        version: lib_version(args.version, args.version_byte),
+        deobfuscate: args.deobfuscate,
+        optimize: args.optimize,
+        emit_dropped_exprs: !args.hide_dropped_exprs,
+        declare_locals: args.declare_locals,
+        function_index: None,
+        lenient_version: args.lenient_version,
+        normalize_property_order: args.normalize_property_order,
+        max_output_bytes: args.max_output_bytes,
+        emit_runnable: args.emit_runnable,
+        function_timeout: args.timeout.map(std::time::Duration::from_millis),
+        recover: args.recover,
+        show_stack: args.show_stack,
+    }
+}
+
+/// Writes a command's output to `path`, creating its parent directories, and
+/// exits on failure.
+fn write_output(path: &Path, out: &str) {
+    if let Some(parent) = path.parent()
+        && let Err(e) = std::fs::create_dir_all(parent)
+    {
+        eprintln!("failed to create {parent:?}: {e}");
+        std::process::exit(1);
+    }
+    if let Err(e) = std::fs::write(path, out) {
+        eprintln!("failed to write to {path:?}: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Prints `validate_stack`'s warnings for each selected function to stderr.
+fn print_stack_warnings(path: &Path, bytes: &[u8], options: deqjs_lib::DecompileOptions) {
+    let program = match deqjs_lib::DecodedProgram::parse(bytes, options.version, options.lenient_version) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}: stack validation skipped: {e}", path.display());
+            return;
+        }
+    };
+    for (index, b) in program.functions().into_iter().enumerate() {
+        if options.function_index.is_some_and(|selected| selected != index) {
+            continue;
+        }
+        for warning in deqjs_lib::validate_stack(program.instructions(index).unwrap_or_default()) {
+            eprintln!("{}: function {index} ({}): {warning}", path.display(), b.func_name);
+        }
+    }
+}
+
+/// Reads a whole input file, or standard input for `-`.
+fn read_input(path: &Path) -> std::io::Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        std::fs::read(path)
+    }
+}
+
+fn lib_version(version: DecompileVersionCli, version_byte: Option<u8>) -> deqjs_lib::DecompileVersion {
+    if let Some(v) = version_byte {
+        return deqjs_lib::DecompileVersion::Forced(v);
+    }
+    match version {
+        DecompileVersionCli::Auto => deqjs_lib::DecompileVersion::Auto,
+        DecompileVersionCli::Current => deqjs_lib::DecompileVersion::Current,
+        DecompileVersionCli::Legacy => deqjs_lib::DecompileVersion::Legacy,
+    }
+}
+
+fn function_file_stem(f: &deqjs_lib::DecompiledFunction) -> String {
+    let stem: String = f
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '-' { c } else { '_' })
+        .collect();
+    if f.anonymous || stem.trim_matches('_').is_empty() {
+        format!("function_{}", f.index)
+    } else {
+        stem
+    }
+}
+
+fn write_function_files(dir: &Path, funcs: &[deqjs_lib::DecompiledFunction]) {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("failed to create {dir:?}: {e}");
+        std::process::exit(1);
+    }
+    let mut used = HashSet::new();
+    for f in funcs {
+        let mut stem = function_file_stem(f);
+        if !used.insert(stem.clone()) {
+            stem = format!("{stem}_{}", f.index);
+            used.insert(stem.clone());
+        }
+        let file = dir.join(format!("{stem}.js"));
+        if let Err(e) = std::fs::write(&file, &f.text) {
+            eprintln!("failed to write to {file:?}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Expands a batch pattern into input files, along with the directory that
+/// output paths are made relative to when mirroring into `--output-dir`.
+/// A directory is searched recursively for `BYTECODE_EXTENSIONS` files;
+/// anything else is a glob.
+fn batch_inputs(pattern: &str) -> Result<(PathBuf, Vec<PathBuf>), glob::PatternError> {
+    let is_dir = Path::new(pattern).is_dir();
+    let (base, pattern) = if is_dir {
+        (PathBuf::from(pattern), format!("{}/**/*", glob::Pattern::escape(pattern)))
+    } else {
+        // the literal directory prefix of the pattern
+        let base: PathBuf = Path::new(pattern)
+            .components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+            .collect();
+        let base = if base.as_os_str() == pattern { base.parent().map(Path::to_path_buf).unwrap_or_default() } else { base };
+        (base, pattern.to_string())
+    };
+    let mut files: Vec<PathBuf> = glob::glob(&pattern)?
+        .filter_map(Result::ok)
+        .filter(|p| p.is_file())
+        // skips the `.js` files an earlier run wrote, among others
+        .filter(|p| !is_dir || p.extension().is_some_and(|ext| BYTECODE_EXTENSIONS.iter().any(|e| ext == *e)))
+        .collect();
+    files.sort();
+    Ok((base, files))
+}
+
+/// What a directory search picks up.
+const BYTECODE_EXTENSIONS: [&str; 2] = ["jsc", "bin"];
+
+fn batch_output_path(file: &Path, base: &Path, output_dir: Option<&Path>) -> PathBuf {
+    let mut dest = match output_dir {
+        Some(dir) => dir.join(file.strip_prefix(base).unwrap_or(file)),
+        None => file.to_path_buf(),
+    };
+    if dest.extension().is_some_and(|ext| ext == "js") {
+        dest.set_extension("decompiled.js");
+    } else {
+        dest.set_extension("js");
+    }
+    dest
+}
+
+fn failure_kind(e: &deqjs_lib::DeqjsError) -> &'static str {
+    use deqjs_lib::DeqjsError;
+    match e {
+        DeqjsError::InFunction { source, .. } => failure_kind(source),
+        DeqjsError::Eof { .. } | DeqjsError::TruncatedOpcode { .. } => "truncated",
+        DeqjsError::InvalidVersion(_) => "unsupported version",
+        DeqjsError::FunctionIndexOutOfRange { .. } => "bad function index",
+        _ => "malformed bytecode",
+    }
+}
@@ -0,0 +1,97 @@
+//! The `deqjs_cli` binary on the library's fixtures.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn fixture(name: &str) -> String {
+    format!("{}/../deqjs_lib/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Runs `deqjs_cli decompile <args>`, feeding it `stdin`.
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_deqjs_cli"))
+        .arg("decompile")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// `run`'s stdout. Panics with stderr if it fails.
+fn deqjs(args: &[&str], stdin: &[u8]) -> String {
+    let output = run(args, stdin);
+    assert!(output.status.success(), "deqjs_cli decompile {args:?}: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn c_array_picks_the_named_symbol() {
+    let out = deqjs(&["c-array", &fixture("new_call.c"), "--symbol", "qjsc_new_call"], b"");
+    assert!(out.contains("function f() {\n  L0:\n  // line 1\n  return new Date(0);\n}"), "{out}");
+}
+
+#[test]
+fn dash_reads_bytecode_from_stdin() {
+    let piped = deqjs(&["file", "-"], include_bytes!("../../deqjs_lib/tests/fixtures/params.jsc"));
+    assert_eq!(piped, deqjs(&["file", &fixture("params.jsc")], b""));
+    assert!(piped.contains("function f(a, b) {"), "{piped}");
+}
+
+#[test]
+fn list_puts_the_entry_function_first() {
+    let out = deqjs(&["list", &fixture("params.jsc")], b"");
+    // index, name, arg_count, var_count, stack_size, bytecode length
+    let rows: Vec<Vec<&str>> = out.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][..2], ["0", "<eval>"]);
+    assert_eq!(rows[1..].iter().map(|row| &row[..4]).collect::<Vec<_>>(), [["1", "f", "2", "0"], ["2", "g", "2", "2"]]);
+
+    let json: serde_json::Value = serde_json::from_str(&deqjs(&["list", &fixture("params.jsc"), "--json"], b"")).unwrap();
+    assert_eq!(json[0]["name"], "<eval>");
+    assert_eq!(json[2]["byte_code_len"], 21);
+}
+
+#[test]
+fn function_selects_one_by_index_or_name() {
+    let second = deqjs(&["file", &fixture("params.jsc"), "--function", "1"], b"");
+    assert_eq!(second, "function f(a, b) {\n  L0:\n  // line 1\n  return a + b;\n}\n");
+    assert_eq!(deqjs(&["file", &fixture("params.jsc"), "--function", "f"], b""), second);
+
+    let missing = run(&["file", &fixture("params.jsc"), "--function", "nope"], b"");
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("no function named \"nope\""));
+}
+
+#[test]
+fn atoms_lists_the_custom_atoms() {
+    let out = deqjs(&["atoms", &fixture("locals.jsc")], b"");
+    let total = out.lines().find(|line| line.ends_with("\ttotal")).unwrap_or_else(|| panic!("{out}"));
+    let (index, kind) = total.split_once('\t').unwrap();
+    assert_eq!(kind, "string\ttotal");
+
+    let json: serde_json::Value = serde_json::from_str(&deqjs(&["atoms", &fixture("locals.jsc"), "--json"], b"")).unwrap();
+    let entry = json.as_array().unwrap().iter().find(|entry| entry["atom"]["String"] == "total").unwrap();
+    assert_eq!(entry["index"].to_string(), index);
+}
+
+#[test]
+fn cpool_lists_each_function_flat() {
+    let out = deqjs(&["cpool", &fixture("bigint.jsc")], b"");
+    // the nested function is named, not expanded
+    assert_eq!(out, "function 0 (<eval>):\n  0\t<function:f>\nfunction 1 (f):\n  0\t123456789012345678901234567890n\n");
+    assert_eq!(deqjs(&["cpool", &fixture("bigint.jsc"), "--function", "f"], b""), "function 1 (f):\n  0\t123456789012345678901234567890n\n");
+}
+
+#[test]
+fn listing_subcommands_accept_lenient_version() {
+    // one version below the supported one
+    let mut bytecode = include_bytes!("../../deqjs_lib/tests/fixtures/params.jsc").to_vec();
+    bytecode[0] -= 1;
+    for command in ["strings", "check", "cpool", "atoms", "list"] {
+        assert!(!run(&[command, "-"], &bytecode).status.success(), "{command}");
+        assert!(!deqjs(&[command, "-", "--lenient-version"], &bytecode).is_empty(), "{command}");
+    }
+}
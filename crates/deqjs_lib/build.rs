@@ -10,7 +10,7 @@ fn workspace_root(crate_dir: &Path) -> PathBuf {
         .to_path_buf()
 }
 
-fn take_until_paren_close<'a>(s: &'a str) -> Option<&'a str> {
+fn take_until_paren_close(s: &str) -> Option<&str> {
     let s = s.trim();
     let j = s.find(')')?;
     Some(s[..j].trim())
@@ -111,8 +111,8 @@ fn main() {
 
     let mut out = String::new();
 
-    out.push_str("#[allow(non_camel_case_types)]\n");
-    out.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq)]\n");
+    out.push_str("#[allow(non_camel_case_types, clippy::upper_case_acronyms)]\n");
+    out.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]\n");
     out.push_str("pub enum OpFmt {\n");
     for f in &fmts {
         out.push_str(&format!("    {},\n", f.to_ascii_uppercase()));
@@ -1,4 +1,7 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use byteorder::{ByteOrder, LittleEndian};
 use serde::{Deserialize, Serialize};
@@ -8,10 +11,20 @@ mod tables {
     include!(concat!(env!("OUT_DIR"), "/quickjs_tables.rs"));
 }
 
+/// The operand format of an opcode, as found in [`Instr::fmt`].
+pub use tables::OpFmt;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DecompileMode {
     Pseudo,
     Disasm,
+    /// Pseudo code with the instructions behind each statement appended as
+    /// comments.
+    PseudoAnnotated,
+    /// The disassembly as a JSON array with one object per function.
+    Json,
+    /// The control-flow graph of each function in Graphviz DOT.
+    Cfg,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -19,6 +32,18 @@ pub enum DecompileVersion {
     Auto,
     Current,
     Legacy,
+    /// Read the file as this bytecode version regardless of its version
+    /// byte: 1 selects the legacy layout, anything else the current one.
+    Forced(u8),
+}
+
+impl DecompileVersion {
+    fn forced(self) -> Option<u8> {
+        match self {
+            DecompileVersion::Forced(v) => Some(v),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -27,6 +52,33 @@ pub struct DecompileOptions {
     pub version: DecompileVersion,
     pub deobfuscate: bool,
     pub optimize: bool,
+    pub emit_dropped_exprs: bool,
+    pub declare_locals: bool,
+    pub function_index: Option<usize>,
+    /// Accept a version byte close to the expected one, with a warning
+    /// comment in the output, instead of failing with `InvalidVersion`.
+    pub lenient_version: bool,
+    /// Render object properties in JS enumeration order (array indices
+    /// ascending, then strings, then symbols) rather than the serialized
+    /// order. The serialized order is the object's shape order, i.e. creation
+    /// order, which QuickJS doesn't re-sort for integer keys.
+    pub normalize_property_order: bool,
+    /// Stop once the output reaches this many bytes, ending it with an
+    /// `// output truncated at N bytes` marker. JSON output instead stops
+    /// before the first function that doesn't fit, without a marker.
+    pub max_output_bytes: Option<usize>,
+    /// Post-process pseudo output so that it at least parses as JS: see
+    /// `runnable_output`.
+    pub emit_runnable: bool,
+    /// Wall-clock budget for decoding and structuring one function; a
+    /// function that runs over falls back to its disassembly.
+    pub function_timeout: Option<Duration>,
+    /// In disasm mode, list a byte that doesn't decode as `.byte 0xNN` and
+    /// carry on from the next byte instead of failing the function.
+    pub recover: bool,
+    /// In disasm mode, end each instruction with the stack depth before and
+    /// after it, as worked out by `stack_depths`.
+    pub show_stack: bool,
 }
 
 impl Default for DecompileOptions {
@@ -36,14 +88,24 @@ impl Default for DecompileOptions {
             version: DecompileVersion::Auto,
             deobfuscate: false,
             optimize: false,
+            emit_dropped_exprs: true,
+            declare_locals: false,
+            function_index: None,
+            lenient_version: false,
+            normalize_property_order: false,
+            max_output_bytes: None,
+            emit_runnable: false,
+            function_timeout: None,
+            recover: false,
+            show_stack: false,
         }
     }
 }
 
 #[derive(Debug, Error)]
 pub enum DeqjsError {
-    #[error("unexpected end of input")]
-    Eof,
+    #[error("unexpected end of input at offset {pos} (needed {needed} bytes)")]
+    Eof { pos: usize, needed: usize },
 
     #[error("invalid QuickJS bytecode version: {0}")]
     InvalidVersion(u8),
@@ -54,17 +116,44 @@ pub enum DeqjsError {
     #[error("invalid sleb128")]
     InvalidSleb128,
 
+    #[error("leb128 value at offset {pos} overflows 32 bits")]
+    InvalidLeb128 { pos: usize },
+
     #[error("invalid opcode: 0x{0:02x}")]
     InvalidOpcode(u8),
 
     #[error("truncated opcode at pc={pc} (opcode size={size}, remaining={remaining})")]
     TruncatedOpcode { pc: usize, size: usize, remaining: usize },
 
+    #[error("opcode {name} at pc={pc} doesn't match its stack effect: {reason}")]
+    StackEffectMismatch { pc: usize, name: &'static str, reason: String },
+
     #[error("invalid atom index: {0}")]
     InvalidAtomIndex(u32),
 
     #[error("invalid constant pool index: {0}")]
     InvalidConstIndex(u32),
+
+    #[error("invalid object reference: {index} ({count} objects read so far)")]
+    InvalidObjectReference { index: u32, count: u32 },
+
+    #[error("function index {index} out of range ({count} functions)")]
+    FunctionIndexOutOfRange { index: usize, count: usize },
+
+    #[error("no function named {name:?}")]
+    FunctionNotFound { name: String },
+
+    #[error("{} functions named {name:?} (indices {}); select one by index", .indices.len(), .indices.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", "))]
+    AmbiguousFunction { name: String, indices: Vec<usize> },
+
+    #[error("no byte array{} in C source", .symbol.as_ref().map(|s| format!(" named {s}")).unwrap_or_default())]
+    CArrayNotFound { symbol: Option<String> },
+
+    #[error("C array {symbol}: {text:?} is not a byte value")]
+    CArrayElement { symbol: String, text: String },
+
+    #[error("function {index} ({name}): {source}")]
+    InFunction { index: usize, name: String, source: Box<DeqjsError> },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -91,7 +180,7 @@ impl fmt::Display for AtomRepr {
             }
             AtomRepr::String(s) => write!(f, "{s}"),
             AtomRepr::Symbol { typ, desc } => write!(f, "<sym:{}:{desc}>", typ),
-            AtomRepr::TaggedInt(v) => write!(f, "<int:{}>", v),
+            AtomRepr::TaggedInt(v) => write!(f, "{v}"),
             AtomRepr::Raw(v) => write!(f, "<atom:{}>", v),
         }
     }
@@ -103,17 +192,33 @@ pub enum Value {
     Undefined,
     Bool(bool),
     Int32(i32),
+    /// An int tag whose value doesn't fit in 32 bits. QuickJS never writes
+    /// one, but the encoding has room for it.
+    Int64(i64),
     Float64(f64),
     String(String),
     Array(Vec<Value>),
     Object(Vec<(AtomRepr, Value)>),
-    Module { name: AtomRepr, func_obj: Box<Value> },
+    Module {
+        name: AtomRepr,
+        req_modules: Vec<ReqModuleEntry>,
+        imports: Vec<ImportEntry>,
+        func_obj: Box<Value>,
+    },
     RegExp { pattern: String, bytecode: String },
     BigInt { bytes: Vec<u8> },
     Symbol { atom: AtomRepr },
     ArrayBuffer { bytes: Vec<u8> },
     TypedArray { kind: u8, len: u32, offset: u32, buffer: Box<Value> },
     Date { value: Box<Value> },
+    /// A Map's entries and a Set's values, in insertion order.
+    Map(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+    /// A primitive wrapper object such as `new Number(5)`.
+    BoxedPrimitive(Box<Value>),
+    /// Another occurrence of the object registered `n`th while reading
+    /// (shared or cyclic structure).
+    Reference(usize),
     Function(FunctionBytecode),
     Unsupported { tag: u8 },
 }
@@ -125,42 +230,304 @@ impl fmt::Display for Value {
             Value::Undefined => write!(f, "undefined"),
             Value::Bool(b) => write!(f, "{b}"),
             Value::Int32(v) => write!(f, "{v}"),
+            Value::Int64(v) => write!(f, "{v}"),
             Value::Float64(v) => write!(f, "{v}"),
-            Value::String(s) => write!(f, "\"{}\"", s),
+            Value::String(s) => write!(f, "{}", js_string_literal(s)),
             Value::Array(v) => write!(f, "<array:{}>", v.len()),
             Value::Object(v) => write!(f, "<object:{}>", v.len()),
+            Value::Map(v) => write!(f, "<map:{}>", v.len()),
+            Value::Set(v) => write!(f, "<set:{}>", v.len()),
             Value::Module { name, .. } => write!(f, "<module:{}>", name),
-            Value::RegExp { pattern, .. } => write!(f, "<regexp:{pattern}>") ,
-            Value::BigInt { bytes } => write!(f, "<bigint:{} bytes>", bytes.len()),
-            Value::Symbol { atom } => write!(f, "<symbol:{atom}>"),
+            Value::RegExp { pattern, bytecode } => write!(f, "/{pattern}/{}", regexp_flags(&latin1_bytes(bytecode))),
+            Value::BigInt { bytes } => write!(f, "{}n", bigint_to_decimal(bytes)),
+            Value::Symbol { atom } => write!(f, "{}", symbol_expr(atom)),
             Value::ArrayBuffer { bytes } => write!(f, "<arraybuffer:{} bytes>", bytes.len()),
-            Value::TypedArray { kind, len, .. } => write!(f, "<typedarray:{kind} len={len}>") ,
-            Value::Date { .. } => write!(f, "<date>"),
+            Value::TypedArray { kind, len, .. } => match typed_array_name(*kind) {
+                "" => write!(f, "<unknown-typedarray:{kind} len={len}>"),
+                name => write!(f, "<{name} len={len}>"),
+            },
+            Value::Date { value } => match **value {
+                Value::Float64(ms) => match iso_timestamp(ms) {
+                    Some(ts) => write!(f, "Date({ts})"),
+                    None => write!(f, "Date(Invalid Date)"),
+                },
+                _ => write!(f, "<date>"),
+            },
+            Value::BoxedPrimitive(v) => match **v {
+                Value::Int32(_) | Value::Int64(_) | Value::Float64(_) => write!(f, "new Number({v})"),
+                Value::String(_) => write!(f, "new String({v})"),
+                Value::Bool(_) => write!(f, "new Boolean({v})"),
+                _ => write!(f, "Object({v})"),
+            },
+            Value::Reference(n) => write!(f, "<ref:{n}>"),
             Value::Function(bc) => write!(f, "<function:{}>", bc.func_name),
             Value::Unsupported { tag } => write!(f, "<tag:{}>", tag),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A Date's time value (milliseconds since the epoch) the way
+/// `toISOString` spells it, or None for an invalid date. Years outside
+/// 0..=9999 take the expanded `+YYYYYY`/`-YYYYYY` form.
+fn iso_timestamp(ms: f64) -> Option<String> {
+    // the ECMAScript time value range, +-100,000,000 days
+    if !ms.is_finite() || ms.abs() > 8.64e15 {
+        return None;
+    }
+    let ms = ms.trunc() as i64;
+    let days = ms.div_euclid(86_400_000);
+    let ms_of_day = ms.rem_euclid(86_400_000);
+    // civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let year = if (0..=9999).contains(&year) {
+        format!("{year:04}")
+    } else {
+        format!("{}{:06}", if year < 0 { '-' } else { '+' }, year.abs())
+    };
+    Some(format!(
+        "{year}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    ))
+}
+
+/// The constructor a serialized typed array's kind byte stands for: its class
+/// id's offset from `JS_CLASS_UINT8C_ARRAY`, in the current class order (with
+/// `Float16Array`). Unknown kinds give an empty string.
+pub fn typed_array_name(kind: u8) -> &'static str {
+    match kind {
+        0 => "Uint8ClampedArray",
+        1 => "Int8Array",
+        2 => "Uint8Array",
+        3 => "Int16Array",
+        4 => "Uint16Array",
+        5 => "Int32Array",
+        6 => "Uint32Array",
+        7 => "BigInt64Array",
+        8 => "BigUint64Array",
+        9 => "Float16Array",
+        10 => "Float32Array",
+        11 => "Float64Array",
+        _ => "",
+    }
+}
+
+/// Structural equality for parsed values. Floats compare bitwise except that
+/// any NaN equals any NaN, so `-0.0` and `0.0` stay distinct.
+pub fn value_structurally_eq(a: &Value, b: &Value) -> bool {
+    fn all_eq(a: &[Value], b: &[Value]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| value_structurally_eq(x, y))
+    }
+    match (a, b) {
+        (Value::Null, Value::Null) | (Value::Undefined, Value::Undefined) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Int32(a), Value::Int32(b)) => a == b,
+        (Value::Int64(a), Value::Int64(b)) => a == b,
+        (Value::Float64(a), Value::Float64(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Array(a), Value::Array(b)) | (Value::Set(a), Value::Set(b)) => all_eq(a, b),
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && value_structurally_eq(va, vb))
+        }
+        (Value::Map(a), Value::Map(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|((ka, va), (kb, vb))| value_structurally_eq(ka, kb) && value_structurally_eq(va, vb))
+        }
+        (
+            Value::Module { name: na, req_modules: ra, imports: ia, func_obj: fa },
+            Value::Module { name: nb, req_modules: rb, imports: ib, func_obj: fb },
+        ) => {
+            na == nb
+                && ia == ib
+                && ra == rb
+                && value_structurally_eq(fa, fb)
+        }
+        (Value::RegExp { pattern: pa, bytecode: ba }, Value::RegExp { pattern: pb, bytecode: bb }) => pa == pb && ba == bb,
+        (Value::BigInt { bytes: a }, Value::BigInt { bytes: b }) => a == b,
+        (Value::Symbol { atom: a }, Value::Symbol { atom: b }) => a == b,
+        (Value::Reference(a), Value::Reference(b)) => a == b,
+        (Value::ArrayBuffer { bytes: a }, Value::ArrayBuffer { bytes: b }) => a == b,
+        (
+            Value::TypedArray { kind: ka, len: la, offset: oa, buffer: ba },
+            Value::TypedArray { kind: kb, len: lb, offset: ob, buffer: bb },
+        ) => ka == kb && la == lb && oa == ob && value_structurally_eq(ba, bb),
+        (Value::Date { value: a }, Value::Date { value: b }) => value_structurally_eq(a, b),
+        (Value::BoxedPrimitive(a), Value::BoxedPrimitive(b)) => value_structurally_eq(a, b),
+        (Value::Function(a), Value::Function(b)) => {
+            a.func_name == b.func_name
+                && a.flags == b.flags
+                && a.func_flags == b.func_flags
+                && a.is_strict_mode == b.is_strict_mode
+                && a.arg_count == b.arg_count
+                && a.var_count == b.var_count
+                && a.defined_arg_count == b.defined_arg_count
+                && a.stack_size == b.stack_size
+                && a.var_ref_count == b.var_ref_count
+                && a.closure_var_count == b.closure_var_count
+                && a.cpool_count == b.cpool_count
+                && a.byte_code_len == b.byte_code_len
+                && a.locals == b.locals
+                && a.closure_vars == b.closure_vars
+                && all_eq(&a.cpool, &b.cpool)
+                && a.bytecode == b.bytecode
+                && a.debug == b.debug
+        }
+        (Value::Unsupported { tag: a }, Value::Unsupported { tag: b }) => a == b,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReqModuleEntry {
+    pub module_name: AtomRepr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportEntry {
+    pub var_idx: u32,
+    pub import_name: AtomRepr,
+    pub req_module_idx: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VarDef {
     pub name: AtomRepr,
     pub scope_level: u32,
     pub scope_next: u32,
+    /// Low bit first: the 4-bit `JSVarKindEnum`, then `is_const`,
+    /// `is_lexical` and `is_captured`. See [`VarDef::kind`].
     pub flags: u8,
     pub var_ref_idx: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// What declared a variable: `JSVarKindEnum`, with a plain variable split by
+/// its `is_const`/`is_lexical` bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VarKind {
+    /// `var`, a parameter, or one of the compiler's own locals.
+    Var,
+    Let,
+    Const,
+    /// A function declared in a block.
+    FunctionDecl,
+    /// An async or generator function declared in a block.
+    NewFunctionDecl,
+    /// A `catch` clause's binding.
+    Catch,
+    /// A function expression's own name.
+    FunctionName,
+    PrivateField,
+    PrivateMethod,
+    PrivateGetter,
+    PrivateSetter,
+    PrivateGetterSetter,
+    Unknown(u8),
+}
+
+impl VarDef {
+    pub fn kind(&self) -> VarKind {
+        match self.flags & 0xf {
+            0 if self.is_const() => VarKind::Const,
+            0 if self.is_lexical() => VarKind::Let,
+            0 => VarKind::Var,
+            1 => VarKind::FunctionDecl,
+            2 => VarKind::NewFunctionDecl,
+            3 => VarKind::Catch,
+            4 => VarKind::FunctionName,
+            5 => VarKind::PrivateField,
+            6 => VarKind::PrivateMethod,
+            7 => VarKind::PrivateGetter,
+            8 => VarKind::PrivateSetter,
+            9 => VarKind::PrivateGetterSetter,
+            k => VarKind::Unknown(k),
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        self.flags & 0x10 != 0
+    }
+
+    pub fn is_lexical(&self) -> bool {
+        self.flags & 0x20 != 0
+    }
+
+    pub fn is_captured(&self) -> bool {
+        self.flags & 0x40 != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ClosureVar {
     pub name: AtomRepr,
     pub var_idx: u32,
     pub flags: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FunctionKind {
+    Normal,
+    Generator,
+    Async,
+    AsyncGenerator,
+}
+
+/// The current format's function flags word, low bit first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionFlags {
+    pub has_prototype: bool,
+    pub has_simple_parameter_list: bool,
+    pub is_derived_class_constructor: bool,
+    pub need_home_object: bool,
+    pub kind: FunctionKind,
+    pub new_target_allowed: bool,
+    pub super_call_allowed: bool,
+    pub super_allowed: bool,
+    pub arguments_allowed: bool,
+    pub backtrace_barrier: bool,
+    pub has_debug: bool,
+}
+
+impl FunctionFlags {
+    pub fn from_bits(flags: u16) -> Self {
+        let bit = |n: u16| flags & (1 << n) != 0;
+        FunctionFlags {
+            has_prototype: bit(0),
+            has_simple_parameter_list: bit(1),
+            is_derived_class_constructor: bit(2),
+            need_home_object: bit(3),
+            kind: match (flags >> 4) & 3 {
+                1 => FunctionKind::Generator,
+                2 => FunctionKind::Async,
+                3 => FunctionKind::AsyncGenerator,
+                _ => FunctionKind::Normal,
+            },
+            new_target_allowed: bit(6),
+            super_call_allowed: bit(7),
+            super_allowed: bit(8),
+            arguments_allowed: bit(9),
+            backtrace_barrier: bit(10),
+            has_debug: bit(11),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionBytecode {
     pub func_name: AtomRepr,
+    /// Raw function flags word; its bit layout depends on the bytecode version.
+    pub flags: u16,
+    /// `flags` decoded, for the current format only: the legacy layout isn't
+    /// known for certain.
+    pub func_flags: Option<FunctionFlags>,
     pub is_strict_mode: bool,
     pub arg_count: u16,
     pub var_count: u16,
@@ -174,81 +541,174 @@ pub struct FunctionBytecode {
     pub closure_vars: Vec<ClosureVar>,
     pub cpool: Vec<Value>,
     pub bytecode: Vec<u8>,
+    /// Present when the function was compiled with debug info (current
+    /// format only).
+    pub debug: Option<DebugInfo>,
+    /// `loc_name`'s name for each local, worked out on first use.
+    #[serde(skip)]
+    local_names: OnceLock<Vec<String>>,
+}
+
+/// A function's source position info, stored after its bytecode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugInfo {
+    pub filename: AtomRepr,
+    /// Line and column of the function's start, 1-based.
+    pub line_num: u32,
+    pub col_num: u32,
+    /// `(pc, line)` for pc 0 and for every pc where the line changes, in pc
+    /// order; the line holds up to the next entry.
+    pub pc2line: Vec<(usize, u32)>,
+}
+
+impl DebugInfo {
+    /// The source line of the instruction at `pc`.
+    pub fn line_at(&self, pc: usize) -> u32 {
+        let i = self.pc2line.partition_point(|&(start, _)| start <= pc);
+        if i == 0 { self.line_num } else { self.pc2line[i - 1].1 }
+    }
+}
+
+// pc2line encoding (PC2LINE_* in quickjs.c): a nonzero op packs a small pc
+// and line delta, 0 is followed by a leb128 pc delta and a zigzag sleb128
+// line delta; either way a zigzag sleb128 column delta follows.
+const PC2LINE_BASE: i64 = -1;
+const PC2LINE_RANGE: u32 = 5;
+const PC2LINE_OP_FIRST: u32 = 1;
+
+/// Decodes a pc2line table. Like QuickJS's `find_line_num`, a malformed
+/// tail is ignored rather than failing the function.
+fn decode_pc2line(buf: &[u8], line_num: u32) -> Vec<(usize, u32)> {
+    let mut r = Reader::new(buf);
+    let mut out = vec![(0, line_num)];
+    let (mut pc, mut line) = (0usize, line_num as i64);
+    while r.pos < buf.len() {
+        let entry = (|| -> Result<(u32, i64), DeqjsError> {
+            let op = r.get_u8()? as u32;
+            let delta = if op == 0 {
+                let pc_delta = r.get_leb128_u32()?;
+                (pc_delta, r.get_sleb128_i64()?)
+            } else {
+                let op = op - PC2LINE_OP_FIRST;
+                (op / PC2LINE_RANGE, (op % PC2LINE_RANGE) as i64 + PC2LINE_BASE)
+            };
+            let _col_delta = r.get_leb128_u32()?;
+            Ok(delta)
+        })();
+        let Ok((pc_delta, line_delta)) = entry else { break };
+        pc += pc_delta as usize;
+        line += line_delta;
+        let line = line.clamp(0, u32::MAX as i64) as u32;
+        if out.last().is_some_and(|&(_, last)| last != line) {
+            out.push((pc, line));
+        }
+    }
+    out
 }
 
 struct Reader<'a> {
     buf: &'a [u8],
     pos: usize,
+    /// Objects read so far, numbered in the order BC_TAG_OBJECT_REFERENCE
+    /// indexes them: each registers as its tag is read, before its contents.
+    objects: u32,
 }
 
 impl<'a> Reader<'a> {
     fn new(buf: &'a [u8]) -> Self {
-        Self { buf, pos: 0 }
+        Self { buf, pos: 0, objects: 0 }
+    }
+
+    fn add_object(&mut self) {
+        self.objects += 1;
+    }
+
+    /// A back-reference to an object read earlier in the same value.
+    fn get_object_ref(&mut self) -> Result<Value, DeqjsError> {
+        let idx = self.get_leb128_u32()?;
+        if idx >= self.objects {
+            return Err(DeqjsError::InvalidObjectReference { index: idx, count: self.objects });
+        }
+        Ok(Value::Reference(idx as usize))
     }
 
     fn peek_u8(&self) -> Option<u8> {
         self.buf.get(self.pos).copied()
     }
 
+    /// Snapshot of the read position for speculative parsing; pass it to
+    /// `restore` to roll back after a failed attempt.
+    fn checkpoint(&self) -> usize {
+        self.pos
+    }
+
+    fn restore(&mut self, pos: usize) {
+        self.pos = pos.min(self.buf.len());
+    }
+
     fn remaining(&self) -> usize {
         self.buf.len().saturating_sub(self.pos)
     }
 
-    fn get_u8(&mut self) -> Result<u8, DeqjsError> {
-        if self.remaining() < 1 {
-            return Err(DeqjsError::Eof);
+    /// An element count read from the input, rejected when the rest of the
+    /// buffer couldn't hold that many elements of at least a byte each, so a
+    /// forged count can't size an allocation.
+    fn bounded_count(&self, count: usize) -> Result<usize, DeqjsError> {
+        self.need(count)?;
+        Ok(count)
+    }
+
+    fn need(&self, n: usize) -> Result<(), DeqjsError> {
+        if self.remaining() < n {
+            return Err(DeqjsError::Eof { pos: self.pos, needed: n });
         }
+        Ok(())
+    }
+
+    fn get_u8(&mut self) -> Result<u8, DeqjsError> {
+        self.need(1)?;
         let v = self.buf[self.pos];
         self.pos += 1;
         Ok(v)
     }
 
     fn get_u16(&mut self) -> Result<u16, DeqjsError> {
-        if self.remaining() < 2 {
-            return Err(DeqjsError::Eof);
-        }
+        self.need(2)?;
         let v = LittleEndian::read_u16(&self.buf[self.pos..self.pos + 2]);
         self.pos += 2;
         Ok(v)
     }
 
     fn get_u32(&mut self) -> Result<u32, DeqjsError> {
-        if self.remaining() < 4 {
-            return Err(DeqjsError::Eof);
-        }
+        self.need(4)?;
         let v = LittleEndian::read_u32(&self.buf[self.pos..self.pos + 4]);
         self.pos += 4;
         Ok(v)
     }
 
     fn get_u64(&mut self) -> Result<u64, DeqjsError> {
-        if self.remaining() < 8 {
-            return Err(DeqjsError::Eof);
-        }
+        self.need(8)?;
         let v = LittleEndian::read_u64(&self.buf[self.pos..self.pos + 8]);
         self.pos += 8;
         Ok(v)
     }
 
     fn get_f64(&mut self) -> Result<f64, DeqjsError> {
-        if self.remaining() < 8 {
-            return Err(DeqjsError::Eof);
-        }
+        self.need(8)?;
         let v = LittleEndian::read_f64(&self.buf[self.pos..self.pos + 8]);
         self.pos += 8;
         Ok(v)
     }
 
     fn get_bytes(&mut self, n: usize) -> Result<&'a [u8], DeqjsError> {
-        if self.remaining() < n {
-            return Err(DeqjsError::Eof);
-        }
+        self.need(n)?;
         let s = &self.buf[self.pos..self.pos + n];
         self.pos += n;
         Ok(s)
     }
 
     fn get_leb128_u32(&mut self) -> Result<u32, DeqjsError> {
+        let start = self.pos;
         let mut result: u32 = 0;
         let mut shift: u32 = 0;
         loop {
@@ -259,35 +719,34 @@ impl<'a> Reader<'a> {
             }
             shift += 7;
             if shift >= 32 {
-                return Err(DeqjsError::Eof);
+                return Err(DeqjsError::InvalidLeb128 { pos: start });
             }
         }
     }
 
-    fn get_sleb128_i32(&mut self) -> Result<i32, DeqjsError> {
-        let mut result: i64 = 0;
+    /// A signed LEB128 the way QuickJS writes one: zigzag-encoded, so 0, -1,
+    /// 1, -2, ... are stored as 0, 1, 2, 3, ... with the sign in the low bit.
+    fn get_sleb128_i64(&mut self) -> Result<i64, DeqjsError> {
+        let mut result: u64 = 0;
         let mut shift: u32 = 0;
-        let mut byte: u8;
         loop {
-            byte = self.get_u8()?;
-            result |= ((byte & 0x7f) as i64) << shift;
-            shift += 7;
+            let byte = self.get_u8()?;
+            result |= ((byte & 0x7f) as u64) << shift;
             if (byte & 0x80) == 0 {
                 break;
             }
+            shift += 7;
             if shift >= 64 {
                 return Err(DeqjsError::InvalidSleb128);
             }
         }
-        if shift < 64 && (byte & 0x40) != 0 {
-            result |= (!0i64) << shift;
-        }
-        Ok(result as i32)
+        Ok((result >> 1) as i64 ^ -((result & 1) as i64))
     }
 }
 
 #[derive(Debug, Clone)]
 struct AtomTable {
+    version: u8,
     first_atom: u32,
     idx_to_atom: Vec<AtomRepr>,
 }
@@ -301,6 +760,10 @@ impl AtomTable {
         if idx == 0 {
             return Ok(AtomRepr::Null);
         }
+        // JS_ATOM_TAG_INT: integer atoms are stored as themselves
+        if idx & (1 << 31) != 0 {
+            return Ok(AtomRepr::TaggedInt(idx & !(1 << 31)));
+        }
         if idx < self.first_atom {
             return Ok(AtomRepr::Builtin(idx));
         }
@@ -360,6 +823,12 @@ const BC_TAG_OBJECT_REFERENCE_V1: u8 = 21;
 const BC_VERSION: u8 = 23;
 const BC_VERSION_V1: u8 = 1;
 
+// Neighbouring releases mostly renumber opcodes, so the container layout
+// usually still parses.
+fn is_close_version(actual: u8, expected: u8) -> bool {
+    actual.abs_diff(expected) <= 3
+}
+
 const LEGACY_V1_ATOMS: &[&str] = &[
     "null",
     "false",
@@ -580,28 +1049,93 @@ fn read_qjs_string(r: &mut Reader<'_>) -> Result<String, DeqjsError> {
     let len = (len_flags >> 1) as usize;
     if is_wide {
         let bytes = r.get_bytes(len * 2)?;
-        let mut out = String::new();
-        for i in 0..len {
-            let c = LittleEndian::read_u16(&bytes[i * 2..i * 2 + 2]);
-            out.push(char::from_u32(c as u32).unwrap_or('\u{FFFD}'));
-        }
-        Ok(out)
+        // astral characters are stored as surrogate pairs; only an unpaired
+        // surrogate becomes U+FFFD
+        let units = bytes.chunks_exact(2).map(LittleEndian::read_u16);
+        Ok(char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect())
     } else {
+        // narrow strings are Latin-1, which also keeps binary payloads such
+        // as regexp bytecode byte-for-byte
         let bytes = r.get_bytes(len)?;
-        Ok(String::from_utf8_lossy(bytes).to_string())
+        Ok(bytes.iter().map(|&b| b as char).collect())
     }
 }
 
-fn read_atom_table(r: &mut Reader<'_>) -> Result<AtomTable, DeqjsError> {
-    let version = r.get_u8()?;
-    if version != BC_VERSION {
-        return Err(DeqjsError::InvalidVersion(version));
+/// A symbol value as the expression that produces it: `Symbol.iterator` for a
+/// well-known symbol, `Symbol.for("k")` for a registered one and
+/// `Symbol("desc")` otherwise.
+fn symbol_expr(atom: &AtomRepr) -> String {
+    // JS_ATOM_TYPE_GLOBAL_SYMBOL
+    const GLOBAL_SYMBOL: u8 = 2;
+    match atom {
+        AtomRepr::Builtin(_) if atom.to_string().starts_with("Symbol.") => atom.to_string(),
+        AtomRepr::Builtin(_) => format!("Symbol({})", js_string_literal(&atom.to_string())),
+        AtomRepr::Symbol { typ: GLOBAL_SYMBOL, desc } => format!("Symbol.for({})", js_string_literal(desc)),
+        AtomRepr::Symbol { desc, .. } if desc.is_empty() => "Symbol()".to_string(),
+        AtomRepr::Symbol { desc, .. } | AtomRepr::String(desc) => format!("Symbol({})", js_string_literal(desc)),
+        _ => format!("Symbol({atom})"),
+    }
+}
+
+/// The decimal digits of a BigInt serialized as QuickJS does: little-endian
+/// two's complement, with no bytes at all for zero.
+pub fn bigint_to_decimal(bytes: &[u8]) -> String {
+    let negative = bytes.last().is_some_and(|b| b & 0x80 != 0);
+    // magnitude, most significant byte first
+    let mut mag: Vec<u8> = bytes.iter().rev().copied().collect();
+    if negative {
+        let mut carry = true;
+        for b in mag.iter_mut().rev() {
+            let (v, c) = (!*b).overflowing_add(carry as u8);
+            *b = v;
+            carry = c;
+        }
+    }
+    let mut digits = Vec::new();
+    while mag.iter().any(|&b| b != 0) {
+        let mut rem = 0u32;
+        for b in mag.iter_mut() {
+            let cur = (rem << 8) | *b as u32;
+            *b = (cur / 10) as u8;
+            rem = cur % 10;
+        }
+        digits.push(b'0' + rem as u8);
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
     }
+    if negative {
+        digits.push(b'-');
+    }
+    digits.iter().rev().map(|&d| d as char).collect()
+}
+
+/// The flags of a compiled regexp, read from the leading flags byte of its
+/// QuickJS lre bytecode, in the order `RegExp.prototype.flags` lists them.
+pub fn regexp_flags(bytecode: &[u8]) -> String {
+    // LRE_FLAG_* bits
+    const FLAGS: &[(u8, char)] = &[(1 << 6, 'd'), (1 << 0, 'g'), (1 << 1, 'i'), (1 << 2, 'm'), (1 << 3, 's'), (1 << 4, 'u'), (1 << 5, 'y')];
+    let bits = bytecode.first().copied().unwrap_or(0);
+    FLAGS.iter().filter(|(bit, _)| bits & bit != 0).map(|(_, c)| *c).collect()
+}
+
+// The bytes of a narrow string, as read_qjs_string decodes them.
+fn latin1_bytes(s: &str) -> Vec<u8> {
+    s.chars().map(|c| c as u32 as u8).collect()
+}
+
+fn read_atom_table(r: &mut Reader<'_>, forced: Option<u8>, lenient: bool) -> Result<AtomTable, DeqjsError> {
+    let version = r.get_u8()?;
+    let version = match forced {
+        Some(v) => v,
+        None if version == BC_VERSION || (lenient && is_close_version(version, BC_VERSION)) => version,
+        None => return Err(DeqjsError::InvalidVersion(version)),
+    };
 
     let count = r.get_leb128_u32()? as usize;
     let first_atom = AtomTable::builtin_end_atom_id();
 
-    let mut idx_to_atom = Vec::with_capacity(count);
+    let mut idx_to_atom = Vec::with_capacity(r.bounded_count(count)?);
     for _ in 0..count {
         let typ = r.get_u8()?;
         if typ == 0 {
@@ -617,7 +1151,7 @@ fn read_atom_table(r: &mut Reader<'_>) -> Result<AtomTable, DeqjsError> {
         }
     }
 
-    Ok(AtomTable { first_atom, idx_to_atom })
+    Ok(AtomTable { version, first_atom, idx_to_atom })
 }
 
 #[derive(Debug, Clone)]
@@ -632,6 +1166,7 @@ impl AtomTableV1 {
         //   id == 0 => null
         //   id >= 1 => atoms[id-1]
         AtomTable {
+            version: BC_VERSION_V1,
             first_atom: 1,
             idx_to_atom: self.atoms.iter().cloned().map(AtomRepr::String).collect(),
         }
@@ -651,14 +1186,14 @@ impl AtomTableV1 {
     }
 }
 
-fn read_atom_table_v1(r: &mut Reader<'_>) -> Result<AtomTableV1, DeqjsError> {
+fn read_atom_table_v1(r: &mut Reader<'_>, forced: Option<u8>, lenient: bool) -> Result<AtomTableV1, DeqjsError> {
     let version = r.get_u8()?;
-    if version != BC_VERSION_V1 {
+    if forced.is_none() && version != BC_VERSION_V1 && !(lenient && is_close_version(version, BC_VERSION_V1)) {
         return Err(DeqjsError::InvalidVersion(version));
     }
 
     let count = r.get_leb128_u32()? as usize;
-    let mut atoms: Vec<String> = Vec::with_capacity(LEGACY_V1_ATOMS.len() + count);
+    let mut atoms: Vec<String> = Vec::with_capacity(LEGACY_V1_ATOMS.len() + r.bounded_count(count)?);
     for &s in LEGACY_V1_ATOMS {
         atoms.push(s.to_string());
     }
@@ -668,6 +1203,11 @@ fn read_atom_table_v1(r: &mut Reader<'_>) -> Result<AtomTableV1, DeqjsError> {
     Ok(AtomTableV1 { atoms })
 }
 
+/// The value of an int tag: `Int32` whenever it fits.
+fn int_value(v: i64) -> Value {
+    i32::try_from(v).map_or(Value::Int64(v), Value::Int32)
+}
+
 fn read_value_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<Value, DeqjsError> {
     let tag = r.get_u8()?;
     match tag {
@@ -675,12 +1215,13 @@ fn read_value_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<Value, Deqjs
         BC_TAG_UNDEFINED => Ok(Value::Undefined),
         BC_TAG_BOOL_FALSE => Ok(Value::Bool(false)),
         BC_TAG_BOOL_TRUE => Ok(Value::Bool(true)),
-        BC_TAG_INT32 => Ok(Value::Int32(r.get_sleb128_i32()?)),
+        BC_TAG_INT32 => Ok(int_value(r.get_sleb128_i64()?)),
         BC_TAG_FLOAT64 => Ok(Value::Float64(r.get_f64()?)),
         BC_TAG_STRING => Ok(Value::String(read_qjs_string(r)?)),
         BC_TAG_OBJECT => {
+            r.add_object();
             let prop_count = r.get_leb128_u32()? as usize;
-            let mut props = Vec::with_capacity(prop_count);
+            let mut props = Vec::with_capacity(r.bounded_count(prop_count)?);
             for _ in 0..prop_count {
                 let name = atoms.read_atom_id(r)?;
                 let val = read_value_v1(r, atoms)?;
@@ -689,8 +1230,9 @@ fn read_value_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<Value, Deqjs
             Ok(Value::Object(props))
         }
         BC_TAG_ARRAY | BC_TAG_TEMPLATE_OBJECT_V1 => {
+            r.add_object();
             let len = r.get_leb128_u32()? as usize;
-            let mut items = Vec::with_capacity(len);
+            let mut items = Vec::with_capacity(r.bounded_count(len)?);
             for _ in 0..len {
                 items.push(read_value_v1(r, atoms)?);
             }
@@ -704,8 +1246,10 @@ fn read_value_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<Value, Deqjs
             let name = atoms.read_atom_id(r)?;
 
             let req_count = r.get_leb128_u32()? as usize;
+            let mut req_modules = Vec::with_capacity(r.bounded_count(req_count)?);
             for _ in 0..req_count {
-                let _ = atoms.read_atom_id(r)?;
+                let module_name = atoms.read_atom_id(r)?;
+                req_modules.push(ReqModuleEntry { module_name });
             }
 
             let export_count = r.get_leb128_u32()? as usize;
@@ -726,16 +1270,19 @@ fn read_value_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<Value, Deqjs
             }
 
             let import_count = r.get_leb128_u32()? as usize;
+            let mut imports = Vec::with_capacity(r.bounded_count(import_count)?);
             for _ in 0..import_count {
-                let _ = r.get_leb128_u32()?;
-                let _ = atoms.read_atom_id(r)?;
-                let _ = r.get_leb128_u32()?;
+                let var_idx = r.get_leb128_u32()?;
+                let import_name = atoms.read_atom_id(r)?;
+                let req_module_idx = r.get_leb128_u32()?;
+                imports.push(ImportEntry { var_idx, import_name, req_module_idx });
             }
 
             let func_obj = read_value_v1(r, atoms)?;
-            Ok(Value::Module { name, func_obj: Box::new(func_obj) })
+            Ok(Value::Module { name, req_modules, imports, func_obj: Box::new(func_obj) })
         }
         BC_TAG_TYPED_ARRAY_V1 => {
+            r.add_object();
             let kind = r.get_u8()?;
             let len = r.get_leb128_u32()?;
             let offset = r.get_leb128_u32()?;
@@ -743,28 +1290,29 @@ fn read_value_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<Value, Deqjs
             Ok(Value::TypedArray { kind, len, offset, buffer: Box::new(buffer) })
         }
         BC_TAG_ARRAY_BUFFER_V1 => {
+            r.add_object();
             let byte_length = r.get_leb128_u32()? as usize;
             let bytes = r.get_bytes(byte_length)?.to_vec();
             Ok(Value::ArrayBuffer { bytes })
         }
         BC_TAG_SHARED_ARRAY_BUFFER_V1 => {
+            r.add_object();
             // EvilDecompiler reads: leb128 len + u64 ptr. We skip it.
             let _len = r.get_leb128_u32()?;
             let _ptr = r.get_u64()?;
             Ok(Value::Unsupported { tag })
         }
         BC_TAG_DATE_V1 => {
+            r.add_object();
             let v = read_value_v1(r, atoms)?;
             Ok(Value::Date { value: Box::new(v) })
         }
         BC_TAG_OBJECT_VALUE_V1 => {
-            // Wrapped value
-            read_value_v1(r, atoms)
-        }
-        BC_TAG_OBJECT_REFERENCE_V1 => {
-            let _idx = r.get_leb128_u32()?;
-            Ok(Value::Unsupported { tag })
+            r.add_object();
+            let v = read_value_v1(r, atoms)?;
+            Ok(Value::BoxedPrimitive(Box::new(v)))
         }
+        BC_TAG_OBJECT_REFERENCE_V1 => r.get_object_ref(),
         other => Err(DeqjsError::UnsupportedTag(other)),
     }
 }
@@ -785,7 +1333,7 @@ fn read_function_bytecode_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<
     let byte_code_len = r.get_leb128_u32()?;
     let local_count = r.get_leb128_u32()?;
 
-    let mut locals = Vec::with_capacity(local_count as usize);
+    let mut locals = Vec::with_capacity(r.bounded_count(local_count as usize)?);
     for _ in 0..local_count {
         let name = atoms.read_atom_id(r)?;
         let scope_level = r.get_leb128_u32()?;
@@ -800,7 +1348,7 @@ fn read_function_bytecode_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<
         });
     }
 
-    let mut closure_vars = Vec::with_capacity(closure_var_count as usize);
+    let mut closure_vars = Vec::with_capacity(r.bounded_count(closure_var_count as usize)?);
     for _ in 0..closure_var_count {
         let name = atoms.read_atom_id(r)?;
         let var_idx = r.get_leb128_u32()?;
@@ -812,21 +1360,32 @@ fn read_function_bytecode_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<
 
     // Debug info is present when flag.HasDebug != 0.
     // EvilDecompiler uses a bitfield type; we approximate with high bit check.
+    // Since the bit is a guess, a debug block that doesn't parse is treated
+    // as absent rather than failing the whole file.
     let has_debug = (flags & 0x8000) != 0;
     if has_debug {
-        let _file = atoms.read_atom_id(r)?;
-        let _line = r.get_leb128_u32()?;
-        let map_len = r.get_leb128_u32()? as usize;
-        let _map = r.get_bytes(map_len)?;
+        let start = r.checkpoint();
+        let skipped = (|| -> Result<(), DeqjsError> {
+            let _file = atoms.read_atom_id(r)?;
+            let _line = r.get_leb128_u32()?;
+            let map_len = r.get_leb128_u32()? as usize;
+            let _map = r.get_bytes(map_len)?;
+            Ok(())
+        })();
+        if skipped.is_err() {
+            r.restore(start);
+        }
     }
 
-    let mut cpool = Vec::with_capacity(cpool_count as usize);
+    let mut cpool = Vec::with_capacity(r.bounded_count(cpool_count as usize)?);
     for _ in 0..cpool_count {
         cpool.push(read_value_v1(r, atoms)?);
     }
 
     Ok(FunctionBytecode {
         func_name,
+        flags,
+        func_flags: None,
         is_strict_mode: false,
         arg_count,
         var_count,
@@ -840,6 +1399,8 @@ fn read_function_bytecode_v1(r: &mut Reader<'_>, atoms: &AtomTableV1) -> Result<
         closure_vars,
         cpool,
         bytecode,
+        debug: None,
+        local_names: OnceLock::new(),
     })
 }
 
@@ -850,12 +1411,13 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
         BC_TAG_UNDEFINED => Ok(Value::Undefined),
         BC_TAG_BOOL_FALSE => Ok(Value::Bool(false)),
         BC_TAG_BOOL_TRUE => Ok(Value::Bool(true)),
-        BC_TAG_INT32 => Ok(Value::Int32(r.get_sleb128_i32()?)),
+        BC_TAG_INT32 => Ok(int_value(r.get_sleb128_i64()?)),
         BC_TAG_FLOAT64 => Ok(Value::Float64(r.get_f64()?)),
         BC_TAG_STRING => Ok(Value::String(read_qjs_string(r)?)),
         BC_TAG_OBJECT => {
+            r.add_object();
             let prop_count = r.get_leb128_u32()? as usize;
-            let mut props = Vec::with_capacity(prop_count);
+            let mut props = Vec::with_capacity(r.bounded_count(prop_count)?);
             for _ in 0..prop_count {
                 let name = atoms.read_atom(r)?;
                 let val = read_value(r, atoms)?;
@@ -864,8 +1426,9 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
             Ok(Value::Object(props))
         }
         BC_TAG_ARRAY | BC_TAG_TEMPLATE_OBJECT => {
+            r.add_object();
             let len = r.get_leb128_u32()? as usize;
-            let mut items = Vec::with_capacity(len);
+            let mut items = Vec::with_capacity(r.bounded_count(len)?);
             for _ in 0..len {
                 items.push(read_value(r, atoms)?);
             }
@@ -875,6 +1438,7 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
             Ok(Value::Array(items))
         }
         BC_TAG_REGEXP => {
+            r.add_object();
             let pattern = read_qjs_string(r)?;
             let bc = read_qjs_string(r)?;
             Ok(Value::RegExp { pattern, bytecode: bc })
@@ -889,12 +1453,14 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
             Ok(Value::Symbol { atom: a })
         }
         BC_TAG_ARRAY_BUFFER => {
+            r.add_object();
             let byte_length = r.get_leb128_u32()? as usize;
             let _max_byte_length = r.get_leb128_u32()?;
             let bytes = r.get_bytes(byte_length)?.to_vec();
             Ok(Value::ArrayBuffer { bytes })
         }
         BC_TAG_TYPED_ARRAY => {
+            r.add_object();
             let kind = r.get_u8()?;
             let len = r.get_leb128_u32()?;
             let offset = r.get_leb128_u32()?;
@@ -902,14 +1468,17 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
             Ok(Value::TypedArray { kind, len, offset, buffer: Box::new(buffer) })
         }
         BC_TAG_DATE => {
+            r.add_object();
             let v = read_value(r, atoms)?;
             Ok(Value::Date { value: Box::new(v) })
         }
         BC_TAG_MODULE => {
             let name = atoms.read_atom(r)?;
             let req_count = r.get_leb128_u32()? as usize;
+            let mut req_modules = Vec::with_capacity(r.bounded_count(req_count)?);
             for _ in 0..req_count {
-                let _ = atoms.read_atom(r)?;
+                let module_name = atoms.read_atom(r)?;
+                req_modules.push(ReqModuleEntry { module_name });
             }
             let export_count = r.get_leb128_u32()? as usize;
             for _ in 0..export_count {
@@ -927,25 +1496,46 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
                 let _ = r.get_leb128_u32()?;
             }
             let import_count = r.get_leb128_u32()? as usize;
+            let mut imports = Vec::with_capacity(r.bounded_count(import_count)?);
             for _ in 0..import_count {
-                let _ = r.get_leb128_u32()?;
-                let _ = atoms.read_atom(r)?;
-                let _ = r.get_leb128_u32()?;
+                let var_idx = r.get_leb128_u32()?;
+                let import_name = atoms.read_atom(r)?;
+                let req_module_idx = r.get_leb128_u32()?;
+                imports.push(ImportEntry { var_idx, import_name, req_module_idx });
             }
             let _has_tla = r.get_u8()?;
             let func_obj = read_value(r, atoms)?;
-            Ok(Value::Module { name, func_obj: Box::new(func_obj) })
+            Ok(Value::Module { name, req_modules, imports, func_obj: Box::new(func_obj) })
         }
         BC_TAG_FUNCTION_BYTECODE => Ok(Value::Function(read_function_bytecode(r, atoms)?)),
+        BC_TAG_OBJECT_VALUE => {
+            r.add_object();
+            let v = read_value(r, atoms)?;
+            Ok(Value::BoxedPrimitive(Box::new(v)))
+        }
+        BC_TAG_MAP => {
+            r.add_object();
+            let count = r.get_leb128_u32()? as usize;
+            let mut entries = Vec::with_capacity(r.bounded_count(count)?);
+            for _ in 0..count {
+                let key = read_value(r, atoms)?;
+                let val = read_value(r, atoms)?;
+                entries.push((key, val));
+            }
+            Ok(Value::Map(entries))
+        }
+        BC_TAG_OBJECT_REFERENCE => r.get_object_ref(),
+        BC_TAG_SET => {
+            r.add_object();
+            let count = r.get_leb128_u32()? as usize;
+            let mut items = Vec::with_capacity(r.bounded_count(count)?);
+            for _ in 0..count {
+                items.push(read_value(r, atoms)?);
+            }
+            Ok(Value::Set(items))
+        }
         other => {
-            if matches!(
-                other,
-                BC_TAG_SHARED_ARRAY_BUFFER
-                    | BC_TAG_OBJECT_VALUE
-                    | BC_TAG_OBJECT_REFERENCE
-                    | BC_TAG_MAP
-                    | BC_TAG_SET
-            ) {
+            if other == BC_TAG_SHARED_ARRAY_BUFFER {
                 return Err(DeqjsError::UnsupportedTag(other));
             }
             Ok(Value::Unsupported { tag: other })
@@ -954,7 +1544,7 @@ fn read_value(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Value, DeqjsError
 }
 
 fn read_function_bytecode(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<FunctionBytecode, DeqjsError> {
-    let _flags = r.get_u16()?;
+    let flags = r.get_u16()?;
     let is_strict_mode = r.get_u8()? != 0;
     let func_name = atoms.read_atom(r)?;
     let arg_count = r.get_leb128_u32()? as u16;
@@ -967,7 +1557,7 @@ fn read_function_bytecode(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Funct
     let byte_code_len = r.get_leb128_u32()?;
     let local_count = r.get_leb128_u32()?;
 
-    let mut locals = Vec::with_capacity(local_count as usize);
+    let mut locals = Vec::with_capacity(r.bounded_count(local_count as usize)?);
     for _ in 0..local_count {
         let name = atoms.read_atom(r)?;
         let scope_level = r.get_leb128_u32()?;
@@ -984,7 +1574,7 @@ fn read_function_bytecode(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Funct
         });
     }
 
-    let mut closure_vars = Vec::with_capacity(closure_var_count as usize);
+    let mut closure_vars = Vec::with_capacity(r.bounded_count(closure_var_count as usize)?);
     for _ in 0..closure_var_count {
         let name = atoms.read_atom(r)?;
         let var_idx = r.get_leb128_u32()?;
@@ -992,15 +1582,31 @@ fn read_function_bytecode(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Funct
         closure_vars.push(ClosureVar { name, var_idx, flags });
     }
 
-    let mut cpool = Vec::with_capacity(cpool_count as usize);
+    let mut cpool = Vec::with_capacity(r.bounded_count(cpool_count as usize)?);
     for _ in 0..cpool_count {
         cpool.push(read_value(r, atoms)?);
     }
 
     let bytecode = r.get_bytes(byte_code_len as usize)?.to_vec();
 
+    let debug = if FunctionFlags::from_bits(flags).has_debug {
+        let filename = atoms.read_atom(r)?;
+        let line_num = r.get_leb128_u32()?;
+        let col_num = r.get_leb128_u32()?;
+        let pc2line_len = r.get_leb128_u32()? as usize;
+        let pc2line = decode_pc2line(r.get_bytes(pc2line_len)?, line_num);
+        // the source text, when the writer was asked to keep it
+        let source_len = r.get_leb128_u32()? as usize;
+        r.get_bytes(source_len)?;
+        Some(DebugInfo { filename, line_num, col_num, pc2line })
+    } else {
+        None
+    };
+
     Ok(FunctionBytecode {
         func_name,
+        flags,
+        func_flags: Some(FunctionFlags::from_bits(flags)),
         is_strict_mode,
         arg_count,
         var_count,
@@ -1014,6 +1620,8 @@ fn read_function_bytecode(r: &mut Reader<'_>, atoms: &AtomTable) -> Result<Funct
         closure_vars,
         cpool,
         bytecode,
+        debug,
+        local_names: OnceLock::new(),
     })
 }
 
@@ -1027,9 +1635,66 @@ fn opcode_info(op: u8) -> Option<&'static tables::OpInfo> {
     tables::OPCODE_INFO.get(idx)
 }
 
-fn opcode_stack_effect(op: u8) -> Option<(u8, u8)> {
-    let i = opcode_info(op)?;
-    Some((i.n_pop, i.n_push))
+/// An opcode of the current bytecode format, as defined in `quickjs-opcode.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OpcodeMeta {
+    pub value: u8,
+    pub name: &'static str,
+    /// Including the opcode byte.
+    pub size: u8,
+    /// For the call family, only the fixed part: the argument count comes
+    /// from the operand or the name.
+    pub n_pop: u8,
+    pub n_push: u8,
+    pub fmt: OpFmt,
+}
+
+impl OpcodeMeta {
+    fn new(value: u8, info: &tables::OpInfo) -> Self {
+        OpcodeMeta { value, name: info.name, size: info.size, n_pop: info.n_pop, n_push: info.n_push, fmt: info.fmt }
+    }
+}
+
+/// Looks up an opcode of the current format by its byte value.
+pub fn opcode_by_value(op: u8) -> Option<OpcodeMeta> {
+    opcode_info(op).map(|info| OpcodeMeta::new(op, info))
+}
+
+/// Looks up an opcode of the current format by its name, such as `get_loc`
+/// or `push_i32`. The compiler-only temporary opcodes never appear in
+/// bytecode files, so they aren't found.
+pub fn opcode_by_name(name: &str) -> Option<OpcodeMeta> {
+    let temps = tables::OP_TEMP_START..tables::OP_TEMP_START + tables::OP_TEMP_COUNT;
+    let idx = tables::OPCODE_INFO.iter().enumerate().position(|(idx, info)| info.name == name && !temps.contains(&idx))?;
+    let value = if idx < tables::OP_TEMP_START { idx } else { idx - tables::OP_TEMP_COUNT };
+    u8::try_from(value).ok().map(|value| OpcodeMeta::new(value, &tables::OPCODE_INFO[idx]))
+}
+
+// The table's n_pop only covers the fixed part of the NPop family; the argument
+// count comes from the operand (npop, npop_u16) or the opcode name (npopx).
+fn stack_effect(ins: &Instr) -> (usize, usize) {
+    let argc = match (&ins.operand, ins.fmt) {
+        (Some(Operand::NPop(n)), _) | (Some(Operand::NPopU16(n, _)), _) => *n as usize,
+        (None, tables::OpFmt::NPOPX) => npopx_argc(ins.name).unwrap_or(0),
+        _ => 0,
+    };
+    (ins.n_pop as usize + argc, ins.n_push as usize)
+}
+
+fn npopx_argc(name: &str) -> Option<usize> {
+    name.strip_prefix("call").and_then(|n| n.parse().ok())
+}
+
+// npop and npop_u16 carry their pop count in the operand, which
+// `stack_effect` relies on; every other format takes it from the table.
+fn check_stack_effect(ins: &Instr) -> Result<(), DeqjsError> {
+    let reason = match (ins.fmt, &ins.operand) {
+        (tables::OpFmt::NPOP, Some(Operand::NPop(_))) | (tables::OpFmt::NPOP_U16, Some(Operand::NPopU16(..))) => return Ok(()),
+        (tables::OpFmt::NPOP | tables::OpFmt::NPOP_U16, operand) => format!("fmt {} but operand {operand:?}", fmt_name(ins.fmt)),
+        (_, Some(Operand::NPop(_) | Operand::NPopU16(..))) => format!("fmt {} but a variable pop count", fmt_name(ins.fmt)),
+        _ => return Ok(()),
+    };
+    Err(DeqjsError::StackEffectMismatch { pc: ins.pc, name: ins.name, reason })
 }
 
 fn fmt_name(fmt: tables::OpFmt) -> &'static str {
@@ -1067,12 +1732,116 @@ fn fmt_name(fmt: tables::OpFmt) -> &'static str {
     }
 }
 
+fn disasm_line(atoms: &AtomTable, ins: &Instr) -> String {
+    if ins.name == BAD_BYTE {
+        return format!("{:05} {} 0x{:02x}", ins.pc, BAD_BYTE, ins.op);
+    }
+    let mut line = format!("{:05} {:<18}", ins.pc, ins.name);
+    match &ins.operand {
+        None => {}
+        Some(Operand::U8(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::I8(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::U16(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::I16(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::U32(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::I32(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::U32x2(a, b)) => line.push_str(&format!("       {}, {}", a, b)),
+        Some(Operand::Label(rel)) => line.push_str(&format!("       {}", rel)),
+        Some(Operand::LabelAbs(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::LabelU16(a, b)) => line.push_str(&format!("       {}, {}", a, b)),
+        Some(Operand::Const(idx)) => line.push_str(&format!("       {}", idx)),
+        Some(Operand::Atom(idx)) => {
+            let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
+            line.push_str(&format!("       {} ; {}", idx, a));
+        }
+        Some(Operand::AtomU8(idx, v)) => {
+            let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
+            line.push_str(&format!("       {}, {} ; {}", idx, v, a));
+        }
+        Some(Operand::AtomU16(idx, v)) => {
+            let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
+            line.push_str(&format!("       {}, {} ; {}", idx, v, a));
+        }
+        Some(Operand::AtomLabelU8(idx, rel, v)) => {
+            let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
+            line.push_str(&format!("       {}, {}, {} ; {}", idx, rel, v, a));
+        }
+        Some(Operand::AtomLabelU16(idx, rel, v)) => {
+            let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
+            line.push_str(&format!("       {}, {}, {} ; {}", idx, rel, v, a));
+        }
+        Some(Operand::NPop(v)) => line.push_str(&format!("       {}", v)),
+        Some(Operand::NPopU16(a, b)) => line.push_str(&format!("       {}, {}", a, b)),
+    }
+
+    if matches!(
+        ins.fmt,
+        tables::OpFmt::NONE_INT | tables::OpFmt::NONE_LOC | tables::OpFmt::NONE_ARG | tables::OpFmt::NONE_VAR_REF | tables::OpFmt::NPOPX
+    ) {
+        line.push_str(&format!("       <fmt:{}>", fmt_name(ins.fmt)));
+    }
+    line
+}
+
+/// One instruction of `DecompileMode::Json` output, with the atom its
+/// operand names (if any) resolved.
+#[derive(Serialize)]
+struct JsonInstr<'a> {
+    pc: usize,
+    op: u8,
+    name: &'static str,
+    operand: &'a Option<Operand>,
+    n_pop: u8,
+    n_push: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    atom: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonFunction<'a> {
+    index: usize,
+    name: &'a str,
+    args: u16,
+    vars: u16,
+    strict: bool,
+    instructions: Vec<JsonInstr<'a>>,
+}
+
+fn disassemble_function_to_json(b: &FunctionBytecode, atoms: &AtomTable, instrs: &[Instr], func_name: &str, index: usize) -> String {
+    let instructions = instrs
+        .iter()
+        .map(|ins| JsonInstr {
+            pc: ins.pc,
+            op: ins.op,
+            name: ins.name,
+            operand: &ins.operand,
+            n_pop: ins.n_pop,
+            n_push: ins.n_push,
+            atom: match ins.operand {
+                Some(
+                    Operand::Atom(idx)
+                    | Operand::AtomU8(idx, _)
+                    | Operand::AtomU16(idx, _)
+                    | Operand::AtomLabelU8(idx, _, _)
+                    | Operand::AtomLabelU16(idx, _, _),
+                ) => Some(atoms.resolve_idx(idx).unwrap_or(AtomRepr::Raw(idx)).to_string()),
+                _ => None,
+            },
+        })
+        .collect();
+    let func = JsonFunction { index, name: func_name, args: b.arg_count, vars: b.var_count, strict: b.is_strict_mode, instructions };
+    // plain data with string keys, which always serializes
+    serde_json::to_string(&func).unwrap_or_default()
+}
+
 fn disassemble_function_with_atoms_and_instrs(
     b: &FunctionBytecode,
     atoms: &AtomTable,
     instrs: &[Instr],
     func_name: &str,
+    show_stack: bool,
 ) -> Result<String, DeqjsError> {
+    let depths = if show_stack { Some(stack_depths(instrs)) } else { None };
     let mut out = String::new();
     out.push_str(&format!(
         "function {} (args={}, vars={}, strict={})\n",
@@ -1080,200 +1849,477 @@ fn disassemble_function_with_atoms_and_instrs(
     ));
     out.push_str("bytecode:\n");
 
+    let mut last_line = None;
     for ins in instrs {
-        out.push_str(&format!("{:05} {:<18}", ins.pc, ins.name));
-        match &ins.operand {
-            None => {}
-            Some(Operand::U8(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::I8(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::U16(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::I16(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::U32(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::I32(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::U32x2(a, b)) => out.push_str(&format!("       {}, {}", a, b)),
-            Some(Operand::Label(rel)) => out.push_str(&format!("       {}", rel)),
-            Some(Operand::LabelAbs(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::LabelU16(a, b)) => out.push_str(&format!("       {}, {}", a, b)),
-            Some(Operand::Const(idx)) => out.push_str(&format!("       {}", idx)),
-            Some(Operand::Atom(idx)) => {
-                let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
-                out.push_str(&format!("       {} ; {}", idx, a));
-            }
-            Some(Operand::AtomU8(idx, v)) => {
-                let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
-                out.push_str(&format!("       {}, {} ; {}", idx, v, a));
-            }
-            Some(Operand::AtomU16(idx, v)) => {
-                let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
-                out.push_str(&format!("       {}, {} ; {}", idx, v, a));
-            }
-            Some(Operand::AtomLabelU8(idx, rel, v)) => {
-                let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
-                out.push_str(&format!("       {}, {}, {} ; {}", idx, rel, v, a));
-            }
-            Some(Operand::AtomLabelU16(idx, rel, v)) => {
-                let a = atoms.resolve_idx(*idx).unwrap_or(AtomRepr::Raw(*idx));
-                out.push_str(&format!("       {}, {}, {} ; {}", idx, rel, v, a));
-            }
-            Some(Operand::NPop(v)) => out.push_str(&format!("       {}", v)),
-            Some(Operand::NPopU16(a, b)) => out.push_str(&format!("       {}, {}", a, b)),
-        }
-
-        if matches!(
-            ins.fmt,
-            tables::OpFmt::NONE_INT | tables::OpFmt::NONE_LOC | tables::OpFmt::NONE_ARG | tables::OpFmt::NONE_VAR_REF | tables::OpFmt::NPOPX
-        ) {
-            out.push_str(&format!("       <fmt:{}>", fmt_name(ins.fmt)));
+        let line = b.debug.as_ref().map(|debug| debug.line_at(ins.pc));
+        if line.is_some() && line != last_line {
+            out.push_str(&format!("// line {}\n", line.unwrap_or_default()));
+            last_line = line;
+        }
+        out.push_str(&disasm_line(atoms, ins));
+        if let Some(depths) = &depths {
+            let (pop, push) = stack_effect(ins);
+            match depths.get(&ins.pc) {
+                Some(&depth) if depth < pop as i64 => {
+                    out.push_str(&format!("  // stack {depth} -> {} (underflow)", depth - pop as i64 + push as i64))
+                }
+                Some(&depth) => out.push_str(&format!("  // stack {depth} -> {}", depth - pop as i64 + push as i64)),
+                None => out.push_str("  // stack: unreachable"),
+            }
         }
-
         out.push('\n');
     }
 
     Ok(out)
 }
 
-fn decode_instructions_v1(b: &FunctionBytecode) -> Result<Vec<Instr>, DeqjsError> {
-    let mut out = Vec::new();
-    let mut pc: usize = 0;
-    while pc < b.bytecode.len() {
-        let op = b.bytecode[pc];
-        let info = opcode_info_v1(op).ok_or(DeqjsError::InvalidOpcode(op))?;
-        let size = info.size as usize;
-        if b.bytecode.len() - pc < size {
-            return Err(DeqjsError::TruncatedOpcode { pc, size, remaining: b.bytecode.len() - pc });
-        }
-        let args = &b.bytecode[pc + 1..pc + size];
-
-        let operand = match info.fmt {
-            OpFmtV1::None | OpFmtV1::NoneInt | OpFmtV1::NoneLoc | OpFmtV1::NoneArg | OpFmtV1::NoneVarRef | OpFmtV1::NPopX => None,
-            OpFmtV1::U8 => Some(Operand::U8(args[0])),
-            OpFmtV1::I8 => Some(Operand::I8(args[0] as i8)),
-            OpFmtV1::U16 | OpFmtV1::Loc | OpFmtV1::Arg | OpFmtV1::VarRef => Some(Operand::U16(LittleEndian::read_u16(args))),
-            OpFmtV1::NPop => Some(Operand::NPop(LittleEndian::read_u16(args))),
-            OpFmtV1::NPopU16 => Some(Operand::NPopU16(LittleEndian::read_u16(args), LittleEndian::read_u16(&args[2..]))),
-            OpFmtV1::I16 => Some(Operand::I16(LittleEndian::read_u16(args) as i16)),
-            OpFmtV1::Label8 => Some(Operand::Label(args[0] as i8 as i32)),
-            OpFmtV1::Label16 => Some(Operand::Label(LittleEndian::read_u16(args) as i16 as i32)),
-            OpFmtV1::I32 => Some(Operand::I32(LittleEndian::read_i32(args))),
-            OpFmtV1::U32 => Some(Operand::U32(LittleEndian::read_u32(args))),
-            OpFmtV1::Label => Some(Operand::LabelAbs(LittleEndian::read_u32(args))),
-            OpFmtV1::LabelU16 => Some(Operand::LabelU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
-            OpFmtV1::Const8 => Some(Operand::Const(args[0] as u32)),
-            OpFmtV1::Const => Some(Operand::Const(LittleEndian::read_u32(args))),
-            OpFmtV1::Atom => Some(Operand::Atom(LittleEndian::read_u32(args))),
-            OpFmtV1::AtomU8 => Some(Operand::AtomU8(LittleEndian::read_u32(args), args[4])),
-            OpFmtV1::AtomU16 => Some(Operand::AtomU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
-            OpFmtV1::AtomLabelU8 => Some(Operand::AtomLabelU8(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), args[8])),
-            OpFmtV1::AtomLabelU16 => Some(Operand::AtomLabelU16(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), LittleEndian::read_u16(&args[8..]))),
-            OpFmtV1::Loc8 => Some(Operand::U8(args[0])),
-        };
-
-        out.push(Instr {
-            pc,
-            op,
-            name: info.name,
-            size: info.size,
-            fmt: v1_fmt_to_current(info.fmt),
-            operand,
-            n_pop: info.n_pop,
-            n_push: info.n_push,
-        });
+/// A problem [`validate_stack`] found with the stack effects of a function's
+/// instructions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum StackWarning {
+    /// The instruction at `pc` pops `pops` values with only `depth` on the stack.
+    Underflow { pc: usize, depth: i64, pops: usize },
+    /// Two paths reach `pc` with different depths: `expected` along the first
+    /// one walked, `found` along another.
+    Mismatch { pc: usize, expected: i64, found: i64 },
+}
 
-        pc += size;
+impl StackWarning {
+    pub fn pc(&self) -> usize {
+        match self {
+            StackWarning::Underflow { pc, .. } | StackWarning::Mismatch { pc, .. } => *pc,
+        }
     }
-    Ok(out)
 }
 
-fn collect_functions<'a>(v: &'a Value, out: &mut Vec<&'a FunctionBytecode>) {
-    match v {
-        Value::Function(b) => {
-            out.push(b);
-            for c in &b.cpool {
-                collect_functions(c, out);
-            }
-        }
-        Value::Array(items) => {
-            for it in items {
-                collect_functions(it, out);
-            }
-        }
-        Value::Object(props) => {
-            for (_k, val) in props {
-                collect_functions(val, out);
-            }
+impl fmt::Display for StackWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackWarning::Underflow { pc, depth, pops } => write!(f, "pc {pc}: stack underflow (pops {pops} at depth {depth})"),
+            StackWarning::Mismatch { pc, expected, found } => write!(f, "pc {pc}: stack depth {found} at a join that was already reached with depth {expected}"),
         }
-        Value::Module { func_obj, .. } => collect_functions(func_obj, out),
-        Value::TypedArray { buffer, .. } => collect_functions(buffer, out),
-        Value::Date { value } => collect_functions(value, out),
-        _ => {}
     }
 }
 
-fn module_entry_function<'a>(v: &'a Value) -> Option<&'a FunctionBytecode> {
-    match v {
-        Value::Module { func_obj, .. } => match func_obj.as_ref() {
-            Value::Function(b) => Some(b),
-            _ => None,
-        },
-        _ => None,
-    }
+/// Walks the stack across every path through `instrs`, as `stack_depths`
+/// does, and reports each underflow and each join whose incoming depths
+/// disagree, in pc order. Either one points at malformed bytecode or at a
+/// wrong stack effect in the opcode table.
+pub fn validate_stack(instrs: &[Instr]) -> Vec<StackWarning> {
+    let mut warnings = walk_stack(instrs).1;
+    warnings.sort_by_key(StackWarning::pc);
+    warnings
 }
 
-fn collect_functions_entry_first<'a>(v: &'a Value) -> Vec<&'a FunctionBytecode> {
-    let mut funcs = Vec::new();
-    collect_functions(v, &mut funcs);
-    if let Some(entry) = module_entry_function(v) {
-        funcs.retain(|f| !std::ptr::eq(*f, entry));
-        funcs.insert(0, entry);
-    }
-    funcs
+/// The stack depth before each reachable instruction, by pc.
+fn stack_depths(instrs: &[Instr]) -> HashMap<usize, i64> {
+    walk_stack(instrs).0
 }
 
-fn display_func_name(options: DecompileOptions, b: &FunctionBytecode, idx: usize) -> String {
-    if options.deobfuscate && matches!(b.func_name, AtomRepr::Null) {
-        format!("closure_{idx}")
-    } else {
-        let name = b.func_name.to_string();
-        if name.starts_with("<atom:") && name.ends_with(">") {
-            if let Some(num_str) = name.strip_prefix("<atom:").and_then(|s| s.strip_suffix(">")) {
-                if let Ok(num) = num_str.parse::<u32>() {
-                    return format!("atom_{}", num);
-                }
+/// Finds the depth before each reachable instruction the way QuickJS's
+/// `compute_stack_size` does: each branch target takes the depth at the
+/// branch, and the innermost `catch` (or `for_of_start`) is tracked so that
+/// `nip_catch` can drop back to it. Unlike QuickJS this doesn't stop at an
+/// underflow, which shows up as a negative depth further on.
+fn walk_stack(instrs: &[Instr]) -> (HashMap<usize, i64>, Vec<StackWarning>) {
+    let by_pc: HashMap<usize, usize> = instrs.iter().enumerate().map(|(i, ins)| (ins.pc, i)).collect();
+    let mut warnings = Vec::new();
+    // the depth before the instruction, and the pc of the innermost catch
+    let mut seen: HashMap<usize, (i64, Option<usize>)> = HashMap::new();
+    let mut work: Vec<(usize, (i64, Option<usize>))> = instrs.first().map(|first| (first.pc, (0, None))).into_iter().collect();
+    // the depth just below a catch offset entry
+    let catch_level = |seen: &HashMap<usize, (i64, Option<usize>)>, pc: usize| {
+        let level = seen[&pc].0;
+        if instrs[by_pc[&pc]].name == "catch" { level } else { level + 1 }
+    };
+    while let Some((pc, state)) = work.pop() {
+        let Some(&i) = by_pc.get(&pc) else { continue };
+        if let Some(&(expected, _)) = seen.get(&pc) {
+            let warning = StackWarning::Mismatch { pc, expected, found: state.0 };
+            if expected != state.0 && !warnings.contains(&warning) {
+                warnings.push(warning);
             }
+            continue;
+        }
+        seen.insert(pc, state);
+        let ins = &instrs[i];
+        let (mut depth, mut catch_pos) = state;
+        let (pop, push) = stack_effect(ins);
+        if depth < pop as i64 {
+            warnings.push(StackWarning::Underflow { pc, depth, pops: pop });
+        }
+        depth += push as i64 - pop as i64;
+        let target = label_target(ins);
+        match ins.name {
+            "tail_call" | "tail_call_method" | "return" | "return_undef" | "return_async" | "throw" | "throw_error" | "ret" => continue,
+            "goto" | "goto8" | "goto16" => {
+                if let Some(t) = target {
+                    work.push((t, (depth, catch_pos)));
+                }
+                continue;
+            }
+            "if_true" | "if_false" | "if_true8" | "if_false8" => {
+                if let Some(t) = target {
+                    work.push((t, (depth, catch_pos)));
+                }
+            }
+            "gosub" | "with_get_var" | "with_delete_var" => {
+                if let Some(t) = target {
+                    work.push((t, (depth + 1, catch_pos)));
+                }
+            }
+            "with_make_ref" | "with_get_ref" | "with_get_ref_undef" => {
+                if let Some(t) = target {
+                    work.push((t, (depth + 2, catch_pos)));
+                }
+            }
+            "with_put_var" => {
+                if let Some(t) = target {
+                    work.push((t, (depth - 1, catch_pos)));
+                }
+            }
+            "catch" => {
+                if let Some(t) = target {
+                    work.push((t, (depth, catch_pos)));
+                }
+                catch_pos = Some(pc);
+            }
+            "for_of_start" | "for_await_of_start" => catch_pos = Some(pc),
+            // dropping the catch offset entry leaves its try block
+            "drop" | "nip" | "nip1" | "iterator_close" => {
+                let level = match ins.name {
+                    "drop" => depth,
+                    "iterator_close" => depth + 2,
+                    _ => depth - 1,
+                };
+                if let Some(c) = catch_pos
+                    && catch_level(&seen, c) == level
+                {
+                    catch_pos = seen[&c].1;
+                }
+            }
+            "nip_catch" => {
+                if let Some(c) = catch_pos {
+                    depth = catch_level(&seen, c) + 1;
+                    catch_pos = seen[&c].1;
+                }
+            }
+            _ => {}
+        }
+        work.push((pc + ins.size as usize, (depth, catch_pos)));
+    }
+    (seen.into_iter().map(|(pc, (depth, _))| (pc, depth)).collect(), warnings)
+}
+
+/// Name of the pseudo-instruction `decode_instructions_recovering` emits for
+/// a byte that doesn't decode.
+const BAD_BYTE: &str = ".byte";
+
+/// Decodes like `decode_instructions`, but a byte `decode_one` rejects
+/// (unknown opcode, or an operand running past the end) becomes a one-byte
+/// `.byte` pseudo-instruction and decoding resumes right after it.
+fn decode_instructions_recovering(b: &FunctionBytecode, decode_one: fn(&[u8], usize) -> Result<Instr, DeqjsError>) -> Vec<Instr> {
+    let mut out = Vec::new();
+    let mut pc: usize = 0;
+    while pc < b.bytecode.len() {
+        let ins = decode_one(&b.bytecode, pc).unwrap_or(Instr {
+            pc,
+            op: b.bytecode[pc],
+            name: BAD_BYTE,
+            size: 1,
+            fmt: tables::OpFmt::NONE,
+            operand: None,
+            n_pop: 0,
+            n_push: 0,
+        });
+        pc += ins.size as usize;
+        out.push(ins);
+    }
+    out
+}
+
+fn decode_instructions_v1(b: &FunctionBytecode) -> Result<Vec<Instr>, DeqjsError> {
+    let mut out = Vec::new();
+    let mut pc: usize = 0;
+    while pc < b.bytecode.len() {
+        let ins = decode_instr_v1(&b.bytecode, pc)?;
+        pc += ins.size as usize;
+        out.push(ins);
+    }
+    Ok(out)
+}
+
+fn decode_instr_v1(code: &[u8], pc: usize) -> Result<Instr, DeqjsError> {
+    let op = *code.get(pc).ok_or(DeqjsError::Eof { pos: pc, needed: 1 })?;
+    let info = opcode_info_v1(op).ok_or(DeqjsError::InvalidOpcode(op))?;
+    let size = info.size as usize;
+    if code.len() - pc < size {
+        return Err(DeqjsError::TruncatedOpcode { pc, size, remaining: code.len() - pc });
+    }
+    let args = &code[pc + 1..pc + size];
+
+    let operand = match info.fmt {
+        OpFmtV1::None | OpFmtV1::NoneInt | OpFmtV1::NoneLoc | OpFmtV1::NoneArg | OpFmtV1::NoneVarRef | OpFmtV1::NPopX => None,
+        OpFmtV1::U8 => Some(Operand::U8(args[0])),
+        OpFmtV1::I8 => Some(Operand::I8(args[0] as i8)),
+        OpFmtV1::U16 | OpFmtV1::Loc | OpFmtV1::Arg | OpFmtV1::VarRef => Some(Operand::U16(LittleEndian::read_u16(args))),
+        OpFmtV1::NPop => Some(Operand::NPop(LittleEndian::read_u16(args))),
+        OpFmtV1::NPopU16 => Some(Operand::NPopU16(LittleEndian::read_u16(args), LittleEndian::read_u16(&args[2..]))),
+        OpFmtV1::I16 => Some(Operand::I16(LittleEndian::read_u16(args) as i16)),
+        OpFmtV1::Label8 => Some(Operand::Label(args[0] as i8 as i32)),
+        OpFmtV1::Label16 => Some(Operand::Label(LittleEndian::read_u16(args) as i16 as i32)),
+        OpFmtV1::I32 => Some(Operand::I32(LittleEndian::read_i32(args))),
+        OpFmtV1::U32 => Some(Operand::U32(LittleEndian::read_u32(args))),
+        OpFmtV1::Label => Some(Operand::LabelAbs(LittleEndian::read_u32(args))),
+        OpFmtV1::LabelU16 => Some(Operand::LabelU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
+        OpFmtV1::Const8 => Some(Operand::Const(args[0] as u32)),
+        OpFmtV1::Const => Some(Operand::Const(LittleEndian::read_u32(args))),
+        OpFmtV1::Atom => Some(Operand::Atom(LittleEndian::read_u32(args))),
+        OpFmtV1::AtomU8 => Some(Operand::AtomU8(LittleEndian::read_u32(args), args[4])),
+        OpFmtV1::AtomU16 => Some(Operand::AtomU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
+        OpFmtV1::AtomLabelU8 => Some(Operand::AtomLabelU8(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), args[8])),
+        OpFmtV1::AtomLabelU16 => Some(Operand::AtomLabelU16(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), LittleEndian::read_u16(&args[8..]))),
+        OpFmtV1::Loc8 => Some(Operand::U8(args[0])),
+    };
+
+    let ins = Instr {
+        pc,
+        op,
+        name: info.name,
+        size: info.size,
+        fmt: v1_fmt_to_current(info.fmt),
+        operand,
+        n_pop: info.n_pop,
+        n_push: info.n_push,
+    };
+    if cfg!(debug_assertions) {
+        check_stack_effect(&ins)?;
+    }
+    Ok(ins)
+}
+
+fn collect_functions<'a>(v: &'a Value, out: &mut Vec<&'a FunctionBytecode>) {
+    match v {
+        Value::Function(b) => {
+            out.push(b);
+            for c in &b.cpool {
+                collect_functions(c, out);
+            }
+        }
+        Value::Array(items) | Value::Set(items) => {
+            for it in items {
+                collect_functions(it, out);
+            }
+        }
+        Value::Object(props) => {
+            for (_k, val) in props {
+                collect_functions(val, out);
+            }
+        }
+        Value::Map(entries) => {
+            for (k, val) in entries {
+                collect_functions(k, out);
+                collect_functions(val, out);
+            }
+        }
+        Value::Module { func_obj, .. } => collect_functions(func_obj, out),
+        Value::TypedArray { buffer, .. } => collect_functions(buffer, out),
+        Value::Date { value } | Value::BoxedPrimitive(value) => collect_functions(value, out),
+        _ => {}
+    }
+}
+
+fn module_entry_function(v: &Value) -> Option<&FunctionBytecode> {
+    match v {
+        Value::Module { func_obj, .. } => match func_obj.as_ref() {
+            Value::Function(b) => Some(b),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn collect_functions_entry_first(v: &Value) -> Vec<&FunctionBytecode> {
+    let mut funcs = Vec::new();
+    collect_functions(v, &mut funcs);
+    if let Some(entry) = module_entry_function(v) {
+        funcs.retain(|f| !std::ptr::eq(*f, entry));
+        funcs.insert(0, entry);
+    }
+    funcs
+}
+
+/// The array index an object key stands for, if any.
+fn array_index_key(k: &AtomRepr) -> Option<u32> {
+    match k {
+        AtomRepr::TaggedInt(v) => Some(*v),
+        AtomRepr::String(s) => s.parse::<u32>().ok().filter(|n| *n != u32::MAX && n.to_string() == *s),
+        _ => None,
+    }
+}
+
+/// Object properties in the order to render them; see
+/// `DecompileOptions::normalize_property_order`.
+fn ordered_props(props: &[(AtomRepr, Value)], normalize: bool) -> Vec<&(AtomRepr, Value)> {
+    let mut out: Vec<_> = props.iter().collect();
+    if normalize {
+        // stable, so strings and symbols each keep their creation order
+        out.sort_by_key(|(k, _)| match (array_index_key(k), k) {
+            (Some(i), _) => (0, i),
+            (None, AtomRepr::Symbol { .. }) => (2, 0),
+            (None, _) => (1, 0),
+        });
+    }
+    out
+}
+
+/// A plain data value as a JS literal, recursing into arrays and objects;
+/// anything else renders as its `Display` form.
+fn value_literal(v: &Value, normalize: bool) -> String {
+    match v {
+        Value::Array(items) => format!("[{}]", items.iter().map(|it| value_literal(it, normalize)).collect::<Vec<_>>().join(", ")),
+        Value::Object(props) if props.is_empty() => "{}".to_string(),
+        Value::Object(props) => {
+            let items: Vec<String> = ordered_props(props, normalize)
+                .into_iter()
+                .map(|(k, val)| {
+                    let key = match k {
+                        AtomRepr::Symbol { .. } => format!("[{}]", symbol_expr(k)),
+                        _ => prop_key(&k.to_string()),
+                    };
+                    format!("{key}: {}", value_literal(val, normalize))
+                })
+                .collect();
+            format!("{{ {} }}", items.join(", "))
+        }
+        _ => v.to_string(),
+    }
+}
+
+fn module_import_decls(v: &Value) -> String {
+    let (req_modules, imports, func_obj) = match v {
+        Value::Module { req_modules, imports, func_obj, .. } => (req_modules, imports, func_obj),
+        _ => return String::new(),
+    };
+    let mut out = String::new();
+    for imp in imports {
+        let Some(req) = req_modules.get(imp.req_module_idx as usize) else {
+            out.push_str(&format!("// import with invalid module index {}\n", imp.req_module_idx));
+            continue;
+        };
+        let local = match func_obj.as_ref() {
+            Value::Function(b) => var_ref_name(b, imp.var_idx as u16),
+            _ => format!("var_ref{}", imp.var_idx),
+        };
+        let imported = imp.import_name.to_string();
+        let clause = if imported == "*" {
+            format!("* as {local}")
+        } else if imported == "default" {
+            local
+        } else if imported == local {
+            format!("{{ {imported} }}")
+        } else {
+            format!("{{ {imported} as {local} }}")
+        };
+        out.push_str(&format!("import {clause} from {};\n", js_string_literal(&req.module_name.to_string())));
+    }
+    out
+}
+
+fn display_func_name(options: DecompileOptions, b: &FunctionBytecode, idx: usize) -> String {
+    if options.deobfuscate && matches!(b.func_name, AtomRepr::Null) {
+        format!("closure_{idx}")
+    } else {
+        let name = b.func_name.to_string();
+        if name.starts_with("<atom:")
+            && name.ends_with(">")
+            && let Some(num_str) = name.strip_prefix("<atom:").and_then(|s| s.strip_suffix(">"))
+            && let Ok(num) = num_str.parse::<u32>()
+        {
+            return format!("atom_{}", num);
         }
         name
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct DecompiledFunction {
+    pub index: usize,
+    pub name: String,
+    pub anonymous: bool,
+    pub text: String,
+}
+
 fn decompile_functions_with(
     funcs: &[&FunctionBytecode],
     options: DecompileOptions,
     atoms: &AtomTable,
-    mut decode: impl FnMut(&FunctionBytecode) -> Result<Vec<Instr>, DeqjsError>,
-) -> Result<String, DeqjsError> {
-    let mut out = String::new();
+    mut decode: impl FnMut(usize, &FunctionBytecode) -> Result<Vec<Instr>, DeqjsError>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<DecompiledFunction>, DeqjsError> {
+    if let Some(index) = options.function_index
+        && index >= funcs.len()
+    {
+        return Err(DeqjsError::FunctionIndexOutOfRange { index, count: funcs.len() });
+    }
+    let total = if options.function_index.is_some() { 1 } else { funcs.len() };
+    let mut done = 0;
+    let mut written = 0;
+    let mut out = Vec::new();
     for (idx, b) in funcs.iter().copied().enumerate() {
-        let instrs = decode(b)?;
+        if options.function_index.is_some_and(|index| index != idx) {
+            continue;
+        }
+        let instrs = decode(idx, b)?;
         let func_name = display_func_name(options, b, idx);
         let s = match options.mode {
-            DecompileMode::Pseudo => match pseudo_decompile_from_instrs(b, atoms, &instrs, &func_name, options.optimize, options.deobfuscate) {
+            DecompileMode::Pseudo | DecompileMode::PseudoAnnotated => match pseudo_decompile_from_instrs(b, atoms, &instrs, &func_name, options) {
                 Ok(s) => s,
                 Err(e) => format!("// Pseudo decompilation error: {}\n", e),
             },
-            DecompileMode::Disasm => disassemble_function_with_atoms_and_instrs(b, atoms, &instrs, &func_name)?,
+            DecompileMode::Disasm => disassemble_function_with_atoms_and_instrs(b, atoms, &instrs, &func_name, options.show_stack)?,
+            DecompileMode::Json => disassemble_function_to_json(b, atoms, &instrs, &func_name, idx),
+            DecompileMode::Cfg => cfg_dot(&func_name, &instrs, |ins| disasm_line(atoms, ins)),
         };
+        done += 1;
+        progress(done, total);
         if s.trim().is_empty() {
             continue;
         }
-        if !out.is_empty() {
-            out.push('\n');
+        // a JSON array can only lose whole elements, and has nowhere for a marker
+        if options.mode == DecompileMode::Json && options.max_output_bytes.is_some_and(|max| written + s.len() > max) {
+            break;
+        }
+        let truncated = options.max_output_bytes.and_then(|max| truncate_output(&s, max.saturating_sub(written), max));
+        written += s.len() + 1;
+        let stop = truncated.is_some();
+        out.push(DecompiledFunction {
+            index: idx,
+            name: func_name,
+            anonymous: matches!(b.func_name, AtomRepr::Null),
+            text: truncated.unwrap_or(s),
+        });
+        if stop {
+            break;
         }
-        out.push_str(&s);
     }
     Ok(out)
 }
 
-#[derive(Debug, Clone)]
+/// `text` cut down to the whole lines within `remaining` bytes plus the
+/// truncation marker, if it doesn't fit.
+fn truncate_output(text: &str, remaining: usize, max: usize) -> Option<String> {
+    if text.len() <= remaining {
+        return None;
+    }
+    let end = text.as_bytes()[..remaining].iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+    let mut out = text[..end].to_string();
+    out.push_str(&format!("// output truncated at {max} bytes\n"));
+    Some(out)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Operand {
     U8(u8),
     I8(i8),
@@ -1295,7 +2341,7 @@ pub enum Operand {
     NPopU16(u16, u16),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct Instr {
     pub pc: usize,
     pub op: u8,
@@ -1640,53 +2686,82 @@ fn decode_instructions(b: &FunctionBytecode) -> Result<Vec<Instr>, DeqjsError> {
     let mut out = Vec::new();
     let mut pc: usize = 0;
     while pc < b.bytecode.len() {
-        let op = b.bytecode[pc];
-        let info = opcode_info(op).ok_or(DeqjsError::InvalidOpcode(op))?;
-        let size = info.size as usize;
-        if b.bytecode.len() - pc < size {
-            return Err(DeqjsError::TruncatedOpcode { pc, size, remaining: b.bytecode.len() - pc });
-        }
-        let args = &b.bytecode[pc + 1..pc + size];
-        let operand = match info.fmt {
-            tables::OpFmt::NONE | tables::OpFmt::NONE_INT | tables::OpFmt::NONE_LOC | tables::OpFmt::NONE_ARG | tables::OpFmt::NONE_VAR_REF => None,
-            tables::OpFmt::U8 => Some(Operand::U8(args[0])),
-            tables::OpFmt::I8 => Some(Operand::I8(args[0] as i8)),
-            tables::OpFmt::U16 | tables::OpFmt::LOC | tables::OpFmt::ARG | tables::OpFmt::VAR_REF => Some(Operand::U16(LittleEndian::read_u16(args))),
-            tables::OpFmt::NPOP => Some(Operand::NPop(LittleEndian::read_u16(args))),
-            tables::OpFmt::NPOP_U16 => Some(Operand::NPopU16(LittleEndian::read_u16(args), LittleEndian::read_u16(&args[2..]))),
-            tables::OpFmt::I16 => Some(Operand::I16(LittleEndian::read_u16(args) as i16)),
-            tables::OpFmt::LABEL8 => Some(Operand::Label(args[0] as i8 as i32)),
-            tables::OpFmt::LABEL16 => Some(Operand::Label(LittleEndian::read_u16(args) as i16 as i32)),
-            tables::OpFmt::I32 => Some(Operand::I32(LittleEndian::read_i32(args))),
-            tables::OpFmt::U32 => Some(Operand::U32(LittleEndian::read_u32(args))),
-            tables::OpFmt::U32X2 => Some(Operand::U32x2(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]))),
-            tables::OpFmt::LABEL => Some(Operand::LabelAbs(LittleEndian::read_u32(args))),
-            tables::OpFmt::LABEL_U16 => Some(Operand::LabelU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
-            tables::OpFmt::CONST8 => Some(Operand::Const(args[0] as u32)),
-            tables::OpFmt::CONST => Some(Operand::Const(LittleEndian::read_u32(args))),
-            tables::OpFmt::ATOM => Some(Operand::Atom(LittleEndian::read_u32(args))),
-            tables::OpFmt::ATOM_U8 => Some(Operand::AtomU8(LittleEndian::read_u32(args), args[4])),
-            tables::OpFmt::ATOM_U16 => Some(Operand::AtomU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
-            tables::OpFmt::ATOM_LABEL_U8 => Some(Operand::AtomLabelU8(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), args[8])),
-            tables::OpFmt::ATOM_LABEL_U16 => Some(Operand::AtomLabelU16(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), LittleEndian::read_u16(&args[8..]))),
-            tables::OpFmt::LOC8 => Some(Operand::U8(args[0])),
-            tables::OpFmt::NPOPX => None,
-        };
+        let ins = decode_instr(&b.bytecode, pc)?;
+        pc += ins.size as usize;
+        out.push(ins);
+    }
+    Ok(out)
+}
 
-        out.push(Instr {
-            pc,
-            op,
-            name: info.name,
-            size: info.size,
-            fmt: info.fmt,
-            operand,
-            n_pop: info.n_pop,
-            n_push: info.n_push,
-        });
+fn decode_instr(code: &[u8], pc: usize) -> Result<Instr, DeqjsError> {
+    let op = *code.get(pc).ok_or(DeqjsError::Eof { pos: pc, needed: 1 })?;
+    let info = opcode_info(op).ok_or(DeqjsError::InvalidOpcode(op))?;
+    let size = info.size as usize;
+    if code.len() - pc < size {
+        return Err(DeqjsError::TruncatedOpcode { pc, size, remaining: code.len() - pc });
+    }
+    let args = &code[pc + 1..pc + size];
+    let operand = match info.fmt {
+        tables::OpFmt::NONE | tables::OpFmt::NONE_INT | tables::OpFmt::NONE_LOC | tables::OpFmt::NONE_ARG | tables::OpFmt::NONE_VAR_REF => None,
+        tables::OpFmt::U8 => Some(Operand::U8(args[0])),
+        tables::OpFmt::I8 => Some(Operand::I8(args[0] as i8)),
+        tables::OpFmt::U16 | tables::OpFmt::LOC | tables::OpFmt::ARG | tables::OpFmt::VAR_REF => Some(Operand::U16(LittleEndian::read_u16(args))),
+        tables::OpFmt::NPOP => Some(Operand::NPop(LittleEndian::read_u16(args))),
+        tables::OpFmt::NPOP_U16 => Some(Operand::NPopU16(LittleEndian::read_u16(args), LittleEndian::read_u16(&args[2..]))),
+        tables::OpFmt::I16 => Some(Operand::I16(LittleEndian::read_u16(args) as i16)),
+        tables::OpFmt::LABEL8 => Some(Operand::Label(args[0] as i8 as i32)),
+        tables::OpFmt::LABEL16 => Some(Operand::Label(LittleEndian::read_u16(args) as i16 as i32)),
+        tables::OpFmt::I32 => Some(Operand::I32(LittleEndian::read_i32(args))),
+        tables::OpFmt::U32 => Some(Operand::U32(LittleEndian::read_u32(args))),
+        tables::OpFmt::U32X2 => Some(Operand::U32x2(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]))),
+        tables::OpFmt::LABEL => Some(Operand::LabelAbs(LittleEndian::read_u32(args))),
+        tables::OpFmt::LABEL_U16 => Some(Operand::LabelU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
+        tables::OpFmt::CONST8 => Some(Operand::Const(args[0] as u32)),
+        tables::OpFmt::CONST => Some(Operand::Const(LittleEndian::read_u32(args))),
+        tables::OpFmt::ATOM => Some(Operand::Atom(LittleEndian::read_u32(args))),
+        tables::OpFmt::ATOM_U8 => Some(Operand::AtomU8(LittleEndian::read_u32(args), args[4])),
+        tables::OpFmt::ATOM_U16 => Some(Operand::AtomU16(LittleEndian::read_u32(args), LittleEndian::read_u16(&args[4..]))),
+        tables::OpFmt::ATOM_LABEL_U8 => Some(Operand::AtomLabelU8(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), args[8])),
+        tables::OpFmt::ATOM_LABEL_U16 => Some(Operand::AtomLabelU16(LittleEndian::read_u32(args), LittleEndian::read_u32(&args[4..]), LittleEndian::read_u16(&args[8..]))),
+        tables::OpFmt::LOC8 => Some(Operand::U8(args[0])),
+        tables::OpFmt::NPOPX => None,
+    };
 
-        pc += size;
+    let ins = Instr {
+        pc,
+        op,
+        name: info.name,
+        size: info.size,
+        fmt: info.fmt,
+        operand,
+        n_pop: info.n_pop,
+        n_push: info.n_push,
+    };
+    if cfg!(debug_assertions) {
+        check_stack_effect(&ins)?;
+    }
+    Ok(ins)
+}
+
+/// Decodes the single instruction at `pc` in a function's raw bytecode
+/// (`FunctionBytecode::bytecode`), for on-demand disassembly. `Auto` and
+/// `Current` use the current opcode table; the raw code carries no version
+/// byte to detect from.
+pub fn decode_instruction_at(bytecode: &[u8], pc: usize, version: DecompileVersion) -> Result<Instr, DeqjsError> {
+    match version {
+        DecompileVersion::Legacy | DecompileVersion::Forced(BC_VERSION_V1) => decode_instr_v1(bytecode, pc),
+        _ => decode_instr(bytecode, pc),
+    }
+}
+
+/// Decodes a whole function's instruction stream, choosing the opcode table
+/// as [`decode_instruction_at`] does. Pass the version [`parse`] reported
+/// (`ParsedProgram::version`) for functions taken from a parsed file.
+pub fn disassemble_function(b: &FunctionBytecode, version: DecompileVersion) -> Result<Vec<Instr>, DeqjsError> {
+    match version {
+        DecompileVersion::Legacy | DecompileVersion::Forced(BC_VERSION_V1) => decode_instructions_v1(b),
+        _ => decode_instructions(b),
     }
-    Ok(out)
 }
 
 fn label_target(i: &Instr) -> Option<usize> {
@@ -1721,52 +2796,49 @@ pub struct BasicBlock {
     pub start_pc: usize,
     pub instrs: Vec<Instr>,
     pub succs: Vec<usize>,
+    /// Start pcs of the blocks that flow into this one, in block order.
+    pub preds: Vec<usize>,
 }
 
-fn build_cfg(instrs: &[Instr]) -> Vec<BasicBlock> {
-    use std::collections::{BTreeSet, HashMap};
+/// Splits a function's instructions (from [`disassemble_function`]) into
+/// basic blocks, with the edges between them in `succs` and `preds`.
+pub fn build_cfg(instrs: &[Instr]) -> Vec<BasicBlock> {
+    use std::collections::BTreeSet;
 
     let mut leaders: BTreeSet<usize> = BTreeSet::new();
     if let Some(first) = instrs.first() {
         leaders.insert(first.pc);
     }
     for (idx, ins) in instrs.iter().enumerate() {
-        if let Some(t) = label_target(ins) {
+        // A with_* label only skips the outer-scope fallback lookup that
+        // follows it, so it doesn't split the expression into blocks.
+        if !ins.name.starts_with("with_")
+            && let Some(t) = label_target(ins)
+        {
             leaders.insert(t);
             if let Some(next) = instrs.get(idx + 1) {
                 leaders.insert(next.pc);
             }
         }
-        if ins.name == "return" || ins.name == "return_undef" || ins.name == "throw" {
-            if let Some(next) = instrs.get(idx + 1) {
-                leaders.insert(next.pc);
-            }
+        if (ins.name == "return" || ins.name == "return_undef" || ins.name == "throw")
+            && let Some(next) = instrs.get(idx + 1)
+        {
+            leaders.insert(next.pc);
         }
     }
 
+    // Blocks follow instruction order rather than pc order, so a pass that
+    // moves code around (unflatten_dispatchers) keeps its layout.
     let mut leader_to_block: HashMap<usize, usize> = HashMap::new();
     let mut blocks: Vec<BasicBlock> = Vec::new();
-    let leader_list: Vec<usize> = leaders.into_iter().collect();
-    for (bi, &pc) in leader_list.iter().enumerate() {
-        leader_to_block.insert(pc, bi);
-        blocks.push(BasicBlock { start_pc: pc, instrs: Vec::new(), succs: Vec::new() });
-    }
-
-    let mut pc_to_block: HashMap<usize, usize> = HashMap::new();
-    for (bi, b) in blocks.iter().enumerate() {
-        pc_to_block.insert(b.start_pc, bi);
-    }
-    let mut current_block = 0usize;
-    let mut next_leader_idx = 1usize;
-    let mut next_leader = leader_list.get(next_leader_idx).copied();
-
     for ins in instrs.iter().cloned() {
-        if Some(ins.pc) == next_leader {
-            current_block = pc_to_block[&ins.pc];
-            next_leader_idx += 1;
-            next_leader = leader_list.get(next_leader_idx).copied();
+        if blocks.is_empty() || leaders.contains(&ins.pc) {
+            leader_to_block.insert(ins.pc, blocks.len());
+            blocks.push(BasicBlock { start_pc: ins.pc, instrs: Vec::new(), succs: Vec::new(), preds: Vec::new() });
+        }
+        if let Some(blk) = blocks.last_mut() {
+            blk.instrs.push(ins);
         }
-        blocks[current_block].instrs.push(ins);
     }
 
     for bi in 0..blocks.len() {
@@ -1774,16 +2846,16 @@ fn build_cfg(instrs: &[Instr]) -> Vec<BasicBlock> {
         let mut succs = Vec::new();
         if let Some(last) = last {
             if last.name == "goto" || last.name == "goto8" || last.name == "goto16" {
-                if let Some(t) = label_target(&last) {
-                    if let Some(&bti) = leader_to_block.get(&t) {
-                        succs.push(blocks[bti].start_pc);
-                    }
+                if let Some(t) = label_target(&last)
+                    && let Some(&bti) = leader_to_block.get(&t)
+                {
+                    succs.push(blocks[bti].start_pc);
                 }
             } else if last.name == "if_false" || last.name == "if_true" || last.name == "if_false8" || last.name == "if_true8" {
-                if let Some(t) = label_target(&last) {
-                    if let Some(&bti) = leader_to_block.get(&t) {
-                        succs.push(blocks[bti].start_pc);
-                    }
+                if let Some(t) = label_target(&last)
+                    && let Some(&bti) = leader_to_block.get(&t)
+                {
+                    succs.push(blocks[bti].start_pc);
                 }
                 if let Some(next_block) = blocks.get(bi + 1) {
                     succs.push(next_block.start_pc);
@@ -1798,19 +2870,171 @@ fn build_cfg(instrs: &[Instr]) -> Vec<BasicBlock> {
         blocks[bi].succs = succs;
     }
 
+    for bi in 0..blocks.len() {
+        let from = blocks[bi].start_pc;
+        for succ in blocks[bi].succs.clone() {
+            if let Some(&si) = leader_to_block.get(&succ)
+                && !blocks[si].preds.contains(&from)
+            {
+                blocks[si].preds.push(from);
+            }
+        }
+    }
+
     blocks
 }
 
+/// Renders the basic blocks of `instrs` as a Graphviz DOT digraph. Atom
+/// operands show only the builtin names, since the atom table isn't at hand.
+pub fn cfg_to_dot(instrs: &[Instr]) -> String {
+    let atoms = AtomTable { version: 0, first_atom: AtomTable::builtin_end_atom_id(), idx_to_atom: Vec::new() };
+    cfg_dot("cfg", instrs, |ins| disasm_line(&atoms, ins))
+}
+
+fn cfg_dot(name: &str, instrs: &[Instr], line: impl Fn(&Instr) -> String) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut out = format!("digraph \"{}\" {{\n", escape(name));
+    out.push_str("  node [shape=box, fontname=monospace];\n");
+    for blk in build_cfg(instrs) {
+        let label: String = blk.instrs.iter().map(|ins| format!("{}\\l", escape(line(ins).trim_end()))).collect();
+        out.push_str(&format!("  b{} [label=\"{}\"];\n", blk.start_pc, label));
+        // a conditional block lists its jump target first, then the fallthrough
+        let cond = blk.instrs.last().filter(|ins| ins.name.starts_with("if_")).map(|ins| ins.name.starts_with("if_true"));
+        for (i, succ) in blk.succs.iter().enumerate() {
+            let attrs = match cond {
+                Some(jump_if_true) if (i == 0) == jump_if_true => " [color=green, label=\"true\"]",
+                Some(_) => " [color=red, label=\"false\"]",
+                None => "",
+            };
+            out.push_str(&format!("  b{} -> b{}{};\n", blk.start_pc, succ, attrs));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+// a destructured parameter has no name of its own
 fn arg_name(b: &FunctionBytecode, idx: u16) -> String {
-    if (idx as usize) < b.locals.len() {
-        b.locals[idx as usize].name.to_string()
-    } else {
-        format!("arg{}", idx)
+    match b.locals.get(idx as usize) {
+        Some(v) if !matches!(v.name, AtomRepr::Null) => v.name.to_string(),
+        _ => format!("arg{}", idx),
+    }
+}
+
+fn param_list(b: &FunctionBytecode, atoms: &AtomTable) -> String {
+    param_list_with_defaults(b, atoms, &HashMap::new())
+}
+
+/// The parameter list, with `defaults` (see `take_param_defaults`) keyed by
+/// argument index.
+fn param_list_with_defaults(b: &FunctionBytecode, atoms: &AtomTable, defaults: &HashMap<u16, String>) -> String {
+    let rest = rest_param(b, atoms);
+    (0..b.arg_count)
+        .map(|i| match defaults.get(&i) {
+            _ if Some(i) == rest => format!("...{}", arg_name(b, i)),
+            Some(default) => format!("{} = {default}", arg_name(b, i)),
+            None => arg_name(b, i),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Lifts parameter defaults out of the prologue. Parameters from
+/// `defined_arg_count` on may have one, which QuickJS applies as
+/// `if (!(x === undefined)) goto L; x = default; L:`; each such check is
+/// dropped and its default returned for the signature.
+fn take_param_defaults(b: &FunctionBytecode, mut stmts: Vec<Stmt>) -> (Vec<Stmt>, HashMap<u16, String>) {
+    let mut defaults = HashMap::new();
+    for i in b.defined_arg_count..b.arg_count {
+        let name = arg_name(b, i);
+        let check = format!("{name} === undefined");
+        let found = stmts.iter().enumerate().find_map(|(j, s)| match s.unannotated() {
+            Stmt::CondGoto { cond, if_false: true, target } if cond.text == check => {
+                let k = (j + 1..stmts.len()).find(|&k| !matches!(stmts[k], Stmt::Label(_)))?;
+                match (stmts[k].unannotated(), stmts.get(k + 1)) {
+                    (Stmt::Assign(lhs, default), Some(Stmt::Label(end))) if *lhs == name && end == target => Some((j, k, default.clone())),
+                    _ => None,
+                }
+            }
+            _ => None,
+        });
+        if let Some((j, k, default)) = found {
+            remove_stmt(&mut stmts, k);
+            stmts.remove(j);
+            // the checked value is still on the stack where the paths join,
+            // for the copy into the body's own scope
+            if let Some(Stmt::Assign(_, rhs)) = stmts.get_mut(k).map(Stmt::unannotated_mut)
+                && rhs == "<rhs>"
+            {
+                *rhs = name;
+            }
+            defaults.insert(i, default);
+        }
+    }
+    (stmts, defaults)
+}
+
+/// The argument index of a rest parameter: QuickJS collects it with a
+/// `rest <first>` that is stored straight into that argument, after any
+/// destructuring of the parameters before it.
+fn rest_param(b: &FunctionBytecode, atoms: &AtomTable) -> Option<u16> {
+    let version = if atoms.version == BC_VERSION_V1 { DecompileVersion::Legacy } else { DecompileVersion::Current };
+    let mut pc = 0;
+    while let Ok(ins) = decode_instruction_at(&b.bytecode, pc, version) {
+        if let Instr { name: "rest", operand: Some(Operand::U16(first)), .. } = ins {
+            return Some(first);
+        }
+        pc += ins.size as usize;
     }
+    None
+}
+
+/// QuickJS doesn't serialize whether a function is an arrow, so infer it
+/// from the current-format flags: a normal-kind function without a
+/// prototype that isn't a constructor, and which can't use `new.target`
+/// (arrows at top level) or `super` (methods, accessors and class
+/// initializers all can). Arrows nested in methods inherit `super` and still
+/// render as functions; entry functions (`<eval>`) are never arrows.
+fn is_arrow_function(b: &FunctionBytecode) -> bool {
+    let method_like = |f: FunctionFlags| f.new_target_allowed && f.super_allowed;
+    b.func_flags.is_some_and(|f| !f.has_prototype && !f.is_derived_class_constructor && f.kind == FunctionKind::Normal && !method_like(f))
+        && b.func_name.to_string() != "<eval>"
+}
+
+/// The function QuickJS synthesizes for a class body's (static or instance)
+/// field initializers: anonymous, allowed `super` but not `arguments`, which
+/// sets it apart from methods. Arrows within an initializer match too, but
+/// they only ever define fields on object literals.
+fn is_class_fields_init(b: &FunctionBytecode) -> bool {
+    matches!(b.func_name, AtomRepr::Null)
+        && b.func_flags.is_some_and(|f| !f.has_prototype && f.kind == FunctionKind::Normal && f.super_allowed && !f.arguments_allowed)
+}
+
+fn loc_name(b: &FunctionBytecode, idx: u16) -> String {
+    b.local_names.get_or_init(|| local_names(b)).get(idx as usize).cloned().unwrap_or_else(|| format!("loc{}", idx))
 }
 
-fn loc_name(_b: &FunctionBytecode, idx: u16) -> String {
-    format!("loc{}", idx)
+/// Names the locals after their `VarDef`s, falling back to `locN`. Block
+/// scopes reuse names, and a local can shadow an argument or a captured
+/// variable: all but the first get the local's index appended.
+fn local_names(b: &FunctionBytecode) -> Vec<String> {
+    let name = |v: &VarDef| match &v.name {
+        AtomRepr::Null => None,
+        // `this`, `arguments` and friends are locals too, but can't be assigned
+        a => Some(sanitize_ident(&a.to_string())).filter(|s| s != "_" && s != "arguments" && !is_reserved_word(s)),
+    };
+    let args = b.locals.iter().take(b.arg_count as usize).filter_map(name);
+    let mut taken: HashSet<String> = args.chain((0..b.closure_vars.len()).map(|i| var_ref_name(b, i as u16))).collect();
+    b.locals
+        .iter()
+        .skip(b.arg_count as usize)
+        .enumerate()
+        .map(|(i, v)| match name(v) {
+            Some(base) if taken.insert(base.clone()) => base,
+            Some(base) => format!("{base}_{i}"),
+            None => format!("loc{}", i),
+        })
+        .collect()
 }
 
 fn var_ref_name(b: &FunctionBytecode, idx: u16) -> String {
@@ -1834,9 +3058,16 @@ fn var_ref_name(b: &FunctionBytecode, idx: u16) -> String {
     }
 }
 
+fn cpool_function<'a>(b: &'a FunctionBytecode, e: &Expr) -> Option<&'a FunctionBytecode> {
+    match b.cpool.get(e.closure? as usize) {
+        Some(Value::Function(f)) => Some(f),
+        _ => None,
+    }
+}
+
 fn closure_name(deobfuscate: bool, b: &FunctionBytecode, idx: u16) -> String {
     if let Some(Value::Function(closure)) = b.cpool.get(idx as usize) {
-        display_func_name(DecompileOptions { mode: DecompileMode::Pseudo, version: DecompileVersion::Legacy, deobfuscate, optimize: false }, closure, idx as usize)
+        display_func_name(DecompileOptions { mode: DecompileMode::Pseudo, version: DecompileVersion::Legacy, deobfuscate, ..Default::default() }, closure, idx as usize)
     } else {
         format!("<fclosure{}>", idx)
     }
@@ -1866,57 +3097,712 @@ fn sanitize_ident(s: &str) -> String {
     }
 }
 
-#[derive(Debug, Clone)]
-enum Stmt {
-    Expr(String),
-    Assign(String, String),
-    Return(Option<String>),
-    CondGoto {
-        cond: String,
-        if_false: bool,
-        target: usize,
-    },
-    IfElse {
-        cond: String,
-        then_stmts: Vec<Stmt>,
-        else_stmts: Vec<Stmt>,
-    },
-    While {
-        cond: String,
-        body: Vec<Stmt>,
-    },
-    Goto(usize),
-    Label(usize),
+// A `{...}` stack entry is an object literal still under construction: `object`
+// pushes `{}` and each `define_field`/spread appends to it until it is consumed.
+fn is_object_literal(expr: &str) -> bool {
+    expr.starts_with('{') && expr.ends_with('}')
 }
 
-fn stmts_to_string(stmts: &[Stmt], indent: usize) -> String {
-    let mut out = String::new();
-    let pad = " ".repeat(indent);
-    for s in stmts {
-        match s {
-            Stmt::Expr(e) => out.push_str(&format!("{pad}{e};\n")),
-            Stmt::Assign(lhs, rhs) => out.push_str(&format!("{pad}{lhs} = {rhs};\n")),
-            Stmt::Return(Some(v)) => out.push_str(&format!("{pad}return {v};\n")),
-            Stmt::Return(None) => out.push_str(&format!("{pad}return;\n")),
-            Stmt::CondGoto {
-                cond,
-                if_false,
-                target,
-            } => {
-                if *if_false {
-                    out.push_str(&format!("{pad}if (!{cond}) goto L{target};\n"));
-                } else {
-                    out.push_str(&format!("{pad}if ({cond}) goto L{target};\n"));
-                }
-            }
-            Stmt::Goto(t) => out.push_str(&format!("{pad}goto L{t};\n")),
-            Stmt::Label(pc) => out.push_str(&format!("{pad}L{pc}:\n")),
-            Stmt::IfElse {
-                cond,
-                then_stmts,
-                else_stmts,
+/// A double-quoted JS string literal.
+fn js_string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{2028}' | '\u{2029}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Whether `name` may follow a `.` or stand unquoted as an object literal
+/// key. That takes an IdentifierName, which unlike a binding name may be a
+/// reserved word (`obj.class`, `{ return: 1 }`), but not a leading digit.
+fn is_identifier_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c == '$' || c.is_alphabetic()) && chars.all(|c| c == '_' || c == '$' || c.is_alphanumeric())
+}
+
+const OP_SPECIAL_OBJECT_THIS_FUNC: u8 = 2;
+
+/// What `special_object`'s operand (`OP_SPECIAL_OBJECT_*`) pushes, as an
+/// expression. The running function has no expression of its own:
+/// `OP_SPECIAL_OBJECT_THIS_FUNC` gives a placeholder.
+fn special_object_name(kind: u8) -> Option<&'static str> {
+    Some(match kind {
+        // plain and mapped (sloppy mode, aliasing the parameters)
+        0 | 1 => "arguments",
+        OP_SPECIAL_OBJECT_THIS_FUNC => "<this_func>",
+        3 => "new.target",
+        4 => "<home_object>",
+        // the scope object a direct eval declares its vars in
+        5 => "<var_object>",
+        6 => "import.meta",
+        7 => "{ __proto__: null }",
+        _ => return None,
+    })
+}
+
+/// Whether `name` is a reserved word, which QuickJS's builtin atoms list
+/// first, up to `await`.
+fn is_reserved_word(name: &str) -> bool {
+    tables::BUILTIN_ATOMS.iter().take_while(|a| !a.is_empty()).any(|a| *a == name)
+}
+
+fn is_array_index(name: &str) -> bool {
+    name.parse::<u32>().is_ok_and(|n| n.to_string() == name)
+}
+
+/// The member access for a property name: `.name` for an identifier name,
+/// `[0]` for an array index and `["name"]` otherwise.
+fn member_suffix(name: &str) -> String {
+    if is_identifier_name(name) {
+        format!(".{name}")
+    } else if is_array_index(name) {
+        format!("[{name}]")
+    } else {
+        format!("[{}]", js_string_literal(name))
+    }
+}
+
+/// An object literal key: the name itself when it is an identifier name or
+/// an array index, a string literal otherwise.
+fn prop_key(name: &str) -> String {
+    if is_array_index(name) || is_identifier_name(name) {
+        name.to_string()
+    } else {
+        js_string_literal(name)
+    }
+}
+
+/// The member access for a computed key: `.name` when the key is a string
+/// literal spelling an identifier, `[key]` otherwise (numbers, including
+/// negative ones, stay bracketed).
+fn element_suffix(key: &Expr) -> String {
+    match key.text.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(name) if is_identifier_name(name) => format!(".{name}"),
+        _ => format!("[{}]", key.text),
+    }
+}
+
+/// Stand-ins for an array literal's write position once a spread has made
+/// it a run-time value: at the next free slot, or at the element that
+/// define_array_el just wrote.
+const ARRAY_END: &str = "<array_end>";
+const ARRAY_ELEM: &str = "<array_elem>";
+
+/// Left by for_of_start ahead of the iterable, and the value for_of_next
+/// hands to the top of the loop body, until try_structure_for_of turns the
+/// loop back into `for (x of iterable)`.
+const FOR_OF_START: &str = "<for_of> ";
+const FOR_OF_VALUE: &str = "<for_of_value>";
+
+/// Left by `catch` with the handler's pc at the start of a protected region,
+/// and the thrown value a handler block starts with, until try_structure_try
+/// turns the region back into `try { } catch (e) { } finally { }`.
+const TRY_START: &str = "<try> ";
+const EXCEPTION: &str = "<exception>";
+
+fn object_literal_push(lit: &str, entry: &str) -> String {
+    match lit.strip_suffix(" }") {
+        Some(head) if lit != "{}" => format!("{head}, {entry} }}"),
+        _ => format!("{{ {entry} }}"),
+    }
+}
+
+/// `*`, `async ` or `async *` for a generator/async function, from the
+/// func_kind bits of the current-format function flags.
+fn func_kind_prefix(f: &FunctionBytecode) -> &'static str {
+    match f.func_flags.map(|f| f.kind) {
+        Some(FunctionKind::Generator) => "*",
+        Some(FunctionKind::Async) => "async ",
+        Some(FunctionKind::AsyncGenerator) => "async *",
+        _ => "",
+    }
+}
+
+/// `function`, `function*`, `async function` or `async function*`.
+fn function_keyword(f: &FunctionBytecode) -> String {
+    match func_kind_prefix(f).strip_suffix('*') {
+        Some(prefix) => format!("{prefix}function*"),
+        None => format!("{}function", func_kind_prefix(f)),
+    }
+}
+
+/// Renders a define_method / define_method_computed. `params` and `prefix`
+/// (see `func_kind_prefix`) come from the method's function, when it is
+/// known.
+#[allow(clippy::too_many_arguments)]
+fn define_method_on(
+    stmts: &mut Vec<Stmt>,
+    obj: Expr,
+    name: &str,
+    key: &str,
+    method: &str,
+    flags: u8,
+    params: Option<String>,
+    prefix: &str,
+) -> Expr {
+    // define_method flags: the low two bits select method (0), getter (1) or
+    // setter (2); bit 2 marks the property enumerable (object literals).
+    let kind = flags & 3;
+    if is_object_literal(&obj.text) {
+        let params = params.unwrap_or_default();
+        let name = if name.starts_with('[') { name.to_string() } else { prop_key(name) };
+        let entry = match kind {
+            1 => format!("get {name}() {{ /* {method} */ }}"),
+            2 if params.is_empty() => format!("set {name}(v) {{ /* {method} */ }}"),
+            2 => format!("set {name}({params}) {{ /* {method} */ }}"),
+            _ => format!("{prefix}{name}({params}) {{ /* {method} */ }}"),
+        };
+        return object_literal_push(&obj.text, &entry).into();
+    }
+    match kind {
+        1 | 2 => {
+            let accessor = if kind == 1 { "get" } else { "set" };
+            stmts.push(Stmt::Expr(format!("Object.defineProperty({obj}, {key}, {{ {accessor}: {method} }})")));
+        }
+        _ if name.starts_with('[') => stmts.push(Stmt::Expr(format!("{obj}{name} = {method}"))),
+        _ => stmts.push(Stmt::Expr(format!("{obj}{} = {method}", member_suffix(name)))),
+    }
+    obj
+}
+
+// Conservative: anything that may call, construct, assign or suspend counts,
+// so a `drop` of it must still surface as an expression statement.
+fn has_side_effects(expr: &str) -> bool {
+    if expr.starts_with('<') && expr.ends_with('>') {
+        return false;
+    }
+    let bytes = expr.as_bytes();
+    let call = bytes.iter().enumerate().any(|(i, &c)| {
+        c == b'(' && i > 0 && {
+            let p = bytes[i - 1];
+            p.is_ascii_alphanumeric() || p == b'_' || p == b'$' || p == b')' || p == b']' || p == b'>'
+        }
+    });
+    call || expr.starts_with("new ")
+        || expr.contains(" = ")
+        || expr.contains("++")
+        || expr.contains("--")
+        || expr.starts_with("await ")
+        || expr.starts_with("yield")
+        || expr.starts_with("delete ")
+}
+
+// `dup`/`insert2`/`get_field2` leave a copy of an already evaluated value
+// behind; dropping that copy must not re-emit what the previous statement did.
+fn last_stmt_evaluates(stmts: &[Stmt], v: &Expr) -> bool {
+    let text = match stmts.last() {
+        Some(Stmt::Assign(_, rhs)) if *rhs == v.text => return true,
+        Some(Stmt::Assign(lhs, rhs)) => format!("{lhs} = {rhs}"),
+        Some(Stmt::Expr(e)) if e.ends_with(&format!("= {}", v.text)) => return true,
+        Some(Stmt::Expr(e)) => e.clone(),
+        _ => return false,
+    };
+    text.contains(&format!("{v}.")) || text.contains(&format!("{v}["))
+}
+
+// After `x = f()` the value left on the stack is `x`, not a second call, and
+// after `x = x + 1` it is `x`, not an `x + 1` that would read the new `x`.
+fn assigned_value(rhs: Expr, name: String) -> Expr {
+    if has_side_effects(&rhs.text) || mentions_ident(&rhs.text, &name) { name.into() } else { rhs }
+}
+
+// The same for a store whose value the compiler copied first (`dup`,
+// `insert2`/`insert3`): once `target = rhs` is emitted, the copy on top of the
+// stack reads `target` back where rendering `rhs` again would run it twice or
+// see the target's old value.
+fn reuse_stored(stack: &mut [Expr], target: &str, rhs: &Expr) {
+    if let Some(top) = stack.last_mut()
+        && top.text == rhs.text
+        && !has_side_effects(target)
+        && (has_side_effects(&rhs.text) || rhs.text.contains(target))
+    {
+        *top = target.to_string().into();
+    }
+}
+
+// Operator precedence levels of the ECMAScript grammar; higher binds tighter.
+const PREC_ASSIGN: u8 = 2;
+const PREC_CONDITIONAL: u8 = 3;
+const PREC_NULLISH: u8 = 4;
+const PREC_LOGICAL_OR: u8 = 5;
+const PREC_LOGICAL_AND: u8 = 6;
+const PREC_BIT_OR: u8 = 7;
+const PREC_BIT_XOR: u8 = 8;
+const PREC_BIT_AND: u8 = 9;
+const PREC_EQUALITY: u8 = 10;
+const PREC_RELATIONAL: u8 = 11;
+const PREC_SHIFT: u8 = 12;
+const PREC_ADDITIVE: u8 = 13;
+const PREC_MULTIPLICATIVE: u8 = 14;
+const PREC_EXPONENT: u8 = 15;
+const PREC_UNARY: u8 = 16;
+const PREC_POSTFIX: u8 = 18;
+const PREC_PRIMARY: u8 = 20;
+
+/// A rendered expression on the pseudo decompiler's stack, with the precedence
+/// of its outermost operator so parents only parenthesize when required.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Expr {
+    text: String,
+    prec: u8,
+    /// Set on the base of an optional chain: the next member access or call
+    /// on it renders with `?.`.
+    optional: bool,
+    /// Constant pool index of the function, for a freshly created closure.
+    closure: Option<u16>,
+    /// The array collected by `rest`, on its way into the rest parameter.
+    rest: bool,
+    /// A template literal `concat_template` built, which a further `+` of a
+    /// string extends instead of nesting.
+    template: bool,
+    /// Already evaluated where copy_data_properties spread it, so dropping
+    /// it afterwards emits nothing.
+    consumed: bool,
+}
+
+impl Expr {
+    fn new(text: impl Into<String>, prec: u8) -> Self {
+        Self { text: text.into(), prec, optional: false, closure: None, rest: false, template: false, consumed: false }
+    }
+
+    /// The accessor to append after this expression: `.`, `[` or `(`, or
+    /// their `?.` forms when it starts an optional chain.
+    fn access(&self, plain: &str) -> String {
+        if self.optional {
+            format!("?.{}", plain.strip_prefix('.').unwrap_or(plain))
+        } else {
+            plain.to_string()
+        }
+    }
+
+    /// Renders the expression as an operand of a context binding at `prec`.
+    fn at(&self, prec: u8) -> String {
+        if self.prec < prec {
+            format!("({})", self.text)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+impl From<String> for Expr {
+    fn from(text: String) -> Self {
+        Self::new(text, PREC_PRIMARY)
+    }
+}
+
+impl From<&str> for Expr {
+    fn from(text: &str) -> Self {
+        Self::new(text, PREC_PRIMARY)
+    }
+}
+
+// Plain interpolation is for member/call position, the tightest context.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.at(PREC_POSTFIX))
+    }
+}
+
+// Call arguments and array elements bind just above the comma operator.
+fn join_exprs(items: &[Expr]) -> String {
+    items.iter().map(|e| e.at(PREC_ASSIGN)).collect::<Vec<_>>().join(", ")
+}
+
+/// The top-level elements of `text` when it is exactly one array literal
+/// (`[a, ...b]`). A hole is an empty element; the trailing comma that
+/// follows a final hole yields no element of its own.
+fn array_literal_parts(text: &str) -> Option<Vec<&str>> {
+    let inner = text.strip_prefix('[')?.strip_suffix(']')?;
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in inner.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' | '`' => quote = Some(c),
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => {
+                depth -= 1;
+                if depth < 0 {
+                    return None;
+                }
+            }
+            ',' if depth == 0 => {
+                parts.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 || quote.is_some() {
+        return None;
+    }
+    let last = inner[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    Some(parts)
+}
+
+/// Renders array literal elements, holes included (`[a, , b]`, `[a, ,]`).
+fn array_literal(parts: &[&str]) -> String {
+    let trailing = if parts.last() == Some(&"") { "," } else { "" };
+    format!("[{}{trailing}]", parts.join(", "))
+}
+
+/// `lit` with `item` appended, or a hole for `None`.
+fn array_literal_push(lit: &str, item: Option<&str>) -> Option<String> {
+    let mut parts = array_literal_parts(lit)?;
+    parts.push(item.unwrap_or(""));
+    Some(array_literal(&parts))
+}
+
+/// Argument list for a spread call: the literal's items when possible,
+/// otherwise a single spread of the whole array.
+fn spread_args(args: &Expr) -> String {
+    match array_literal_parts(&args.text) {
+        // holes can't be written in an argument list
+        Some(parts) if !parts.contains(&"") => parts.join(", "),
+        _ => format!("...{}", args.at(PREC_ASSIGN)),
+    }
+}
+
+/// Pure stack permutations: how many entries they take off the top and the
+/// order those entries (indexed bottom-up) are pushed back in.
+fn stack_shuffle(name: &str) -> Option<(usize, &'static [usize])> {
+    Some(match name {
+        "nip1" => (3, &[1, 2]),
+        "dup1" => (2, &[0, 0, 1]),
+        "dup2" => (2, &[0, 1, 0, 1]),
+        "dup3" => (3, &[0, 1, 2, 0, 1, 2]),
+        "insert2" => (2, &[1, 0, 1]),
+        "insert3" => (3, &[2, 0, 1, 2]),
+        "insert4" => (4, &[3, 0, 1, 2, 3]),
+        "perm3" => (3, &[1, 0, 2]),
+        "perm4" => (4, &[2, 0, 1, 3]),
+        "perm5" => (5, &[3, 0, 1, 2, 4]),
+        "swap2" => (4, &[2, 3, 0, 1]),
+        "rot3l" => (3, &[1, 2, 0]),
+        "rot3r" => (3, &[2, 0, 1]),
+        "rot4l" => (4, &[1, 2, 3, 0]),
+        "rot5l" => (5, &[1, 2, 3, 4, 0]),
+        _ => return None,
+    })
+}
+
+fn binary_op(op: &str, prec: u8, lhs: &Expr, rhs: &Expr) -> Expr {
+    // `**` is right-associative and rejects a bare unary base (`-2n ** x`);
+    // everything else here associates left
+    let (lp, rp) = if prec == PREC_EXPONENT { (PREC_UNARY + 1, prec) } else { (prec, prec + 1) };
+    Expr::new(format!("{} {op} {}", lhs.at(lp), rhs.at(rp)), prec)
+}
+
+/// Integer literal; negative values bind like a unary minus, so `(-1).x`
+/// and `(-2n) ** y` keep their parentheses. Never folded with neighbouring
+/// operands, so BigInt and Number literals stay distinct.
+fn int_literal(v: i64, suffix: &str) -> Expr {
+    let prec = if v < 0 { PREC_UNARY } else { PREC_PRIMARY };
+    Expr::new(format!("{v}{suffix}"), prec)
+}
+
+/// The value of a plain decimal number literal such as `5`, `-2` or `0.5`:
+/// the only operands `fold_binary` touches.
+fn number_literal_value(e: &Expr) -> Option<f64> {
+    let digits = e.text.strip_prefix('-').unwrap_or(&e.text);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, "0"));
+    let plain = |s: &str| !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit());
+    // a leading zero would be a legacy octal literal
+    if !plain(int) || !plain(frac) || (int.len() > 1 && int.starts_with('0')) {
+        return None;
+    }
+    e.text.parse().ok()
+}
+
+/// The contents of a double-quoted string literal as `js_string_literal`
+/// writes it, re-escaped for the inside of a template literal; None unless
+/// `text` is exactly one such literal.
+fn template_chars(text: &str) -> Option<String> {
+    let mut chars = text.strip_prefix('"')?.chars().peekable();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.next().is_none().then_some(out),
+            '\\' => match chars.next()? {
+                '"' => out.push('"'),
+                c => {
+                    out.push('\\');
+                    out.push(c);
+                }
+            },
+            '`' => out.push_str("\\`"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// Renders an `add` with a string literal on either side as a template
+/// literal, for `optimize`: `"Hello, " + name + "!"` becomes
+/// `` `Hello, ${name}!` ``. Not exact for objects: a substitution tries
+/// `toString` first where `+` tries `valueOf`.
+fn concat_template(name: &str, lhs: &Expr, rhs: &Expr) -> Option<Expr> {
+    if name != "add" {
+        return None;
+    }
+    let (lhs_chars, rhs_chars) = (template_chars(&lhs.text), template_chars(&rhs.text));
+    if lhs_chars.is_none() && rhs_chars.is_none() && !lhs.template && !rhs.template {
+        return None;
+    }
+    let part = |e: &Expr, chars: Option<String>| match (chars, number_literal_value(e)) {
+        (Some(chars), _) => chars,
+        // as String() has it, which drops the sign of -0
+        (None, Some(0.0)) => "0".to_string(),
+        (None, Some(_)) => e.text.clone(),
+        (None, None) if e.template => e.text[1..e.text.len() - 1].to_string(),
+        (None, None) => format!("${{{}}}", e.text),
+    };
+    let text = format!("`{}{}`", part(lhs, lhs_chars), part(rhs, rhs_chars));
+    Some(Expr { template: true, ..Expr::new(text, PREC_PRIMARY) })
+}
+
+/// Evaluates a binary opcode on two number literals as JS would, for
+/// `optimize`: `2 + 3` becomes `5`. Bitwise operators and shifts go through
+/// ToInt32 (ToUint32 for `>>>`); the rest stay in doubles. A result that
+/// doesn't print back as a plain literal (NaN, Infinity, or one JS would
+/// write with an exponent) is left unfolded.
+fn fold_binary(name: &str, lhs: &Expr, rhs: &Expr) -> Option<Expr> {
+    let (a, b) = (number_literal_value(lhs)?, number_literal_value(rhs)?);
+    let int32 = |v: f64| v.trunc().rem_euclid(4294967296.0) as u32 as i32;
+    let shift = |v: f64| int32(v) as u32 & 31;
+    let v = match name {
+        "add" => a + b,
+        "sub" => a - b,
+        "mul" => a * b,
+        "div" => a / b,
+        "mod" => a % b,
+        "pow" => a.powf(b),
+        "and" => (int32(a) & int32(b)) as f64,
+        "or" => (int32(a) | int32(b)) as f64,
+        "xor" => (int32(a) ^ int32(b)) as f64,
+        "shl" => int32(a).wrapping_shl(shift(b)) as f64,
+        "sar" => (int32(a) >> shift(b)) as f64,
+        "shr" => (int32(a) as u32 >> shift(b)) as f64,
+        "eq" | "strict_eq" => return Some((if a == b { "true" } else { "false" }).into()),
+        "neq" | "strict_neq" => return Some((if a != b { "true" } else { "false" }).into()),
+        "lt" => return Some((if a < b { "true" } else { "false" }).into()),
+        "lte" => return Some((if a <= b { "true" } else { "false" }).into()),
+        "gt" => return Some((if a > b { "true" } else { "false" }).into()),
+        "gte" => return Some((if a >= b { "true" } else { "false" }).into()),
+        _ => return None,
+    };
+    if !v.is_finite() || (v != 0.0 && !(1e-6..1e21).contains(&v.abs())) {
+        return None;
+    }
+    let prec = if v.is_sign_negative() { PREC_UNARY } else { PREC_PRIMARY };
+    Some(Expr::new(v.to_string(), prec))
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Expr(String),
+    /// A function declaration from define_func, which takes no semicolon.
+    Decl(String),
+    Assign(String, String),
+    Return(Option<String>),
+    CondGoto {
+        cond: Expr,
+        if_false: bool,
+        target: usize,
+    },
+    IfElse {
+        cond: Expr,
+        then_stmts: Vec<Stmt>,
+        else_stmts: Vec<Stmt>,
+    },
+    While {
+        cond: Expr,
+        body: Vec<Stmt>,
+    },
+    DoWhile {
+        body: Vec<Stmt>,
+        cond: Expr,
+    },
+    For {
+        /// The assignment that initializes the induction variable.
+        init: Box<Stmt>,
+        cond: Expr,
+        update: String,
+        body: Vec<Stmt>,
+    },
+    With {
+        obj: String,
+        /// The hidden local's initialization folded into `obj`, put back if
+        /// the block is undone.
+        init: Vec<Stmt>,
+        body: Vec<Stmt>,
+    },
+    ForOf {
+        binding: String,
+        iterable: String,
+        body: Vec<Stmt>,
+    },
+    Try {
+        body: Vec<Stmt>,
+        /// The catch clause's binding (None for `catch { }`) and body.
+        catch: Option<(Option<String>, Vec<Stmt>)>,
+        finally: Option<Vec<Stmt>>,
+    },
+    Goto(usize),
+    Label(usize),
+    /// A statement with the source line it starts, when debug info has one
+    /// and it differs from the previous statement's, and the disassembly of
+    /// the instructions that produced it (`DecompileMode::PseudoAnnotated`).
+    Annotated(Box<Stmt>, Option<u32>, Vec<String>),
+}
+
+impl Stmt {
+    fn unannotated(&self) -> &Stmt {
+        match self {
+            Stmt::Annotated(s, ..) => s,
+            s => s,
+        }
+    }
+
+    fn unannotated_mut(&mut self) -> &mut Stmt {
+        match self {
+            Stmt::Annotated(s, ..) => s,
+            s => s,
+        }
+    }
+
+    /// Replaces the statement inside any annotation, which is kept.
+    fn map_unannotated(self, f: impl FnOnce(Stmt) -> Stmt) -> Stmt {
+        match self {
+            Stmt::Annotated(s, source_line, lines) => Stmt::Annotated(Box::new(f(*s)), source_line, lines),
+            s => f(s),
+        }
+    }
+
+    /// Takes over the annotation of `dropped`, a statement removed just
+    /// before this one: its source line, unless this one starts its own, and
+    /// the instructions it was built from.
+    fn absorb(self, dropped: &Stmt) -> Stmt {
+        let Stmt::Annotated(_, dropped_line, dropped_lines) = dropped else { return self };
+        match self {
+            Stmt::Annotated(s, source_line, lines) => {
+                Stmt::Annotated(s, source_line.or(*dropped_line), dropped_lines.iter().cloned().chain(lines).collect())
+            }
+            s => Stmt::Annotated(Box::new(s), *dropped_line, dropped_lines.clone()),
+        }
+    }
+}
+
+/// Hands the annotations of `dropped`, statements removed just before `s`,
+/// on to `s`. Only the plain statements the decompiler annotates take them;
+/// a label passes them on to what follows it.
+fn absorb_dropped(s: Stmt, dropped: &mut Vec<Stmt>) -> Stmt {
+    match s.unannotated() {
+        Stmt::Label(_) => s,
+        Stmt::Expr(_) | Stmt::Decl(_) | Stmt::Assign(..) | Stmt::Return(_) => dropped.drain(..).rev().fold(s, |s, d| s.absorb(&d)),
+        _ => {
+            dropped.clear();
+            s
+        }
+    }
+}
+
+/// Removes `stmts[i]`, handing its annotation on to the statement after it.
+fn remove_stmt(stmts: &mut Vec<Stmt>, i: usize) -> Stmt {
+    let removed = stmts.remove(i);
+    let mut dropped = vec![removed.clone()];
+    for next in &mut stmts[i..] {
+        *next = absorb_dropped(std::mem::replace(next, Stmt::Label(0)), &mut dropped);
+        if dropped.is_empty() {
+            break;
+        }
+    }
+    removed
+}
+
+/// Keeps the statements `keep` accepts, handing the annotations of the
+/// others on to the statements after them.
+fn retain_stmts(stmts: Vec<Stmt>, mut keep: impl FnMut(&Stmt) -> bool) -> Vec<Stmt> {
+    let mut dropped = Vec::new();
+    let mut out = Vec::with_capacity(stmts.len());
+    for s in stmts {
+        if keep(&s) {
+            out.push(absorb_dropped(s, &mut dropped));
+        } else {
+            dropped.push(s);
+        }
+    }
+    out
+}
+
+fn stmts_to_string(stmts: &[Stmt], indent: usize) -> String {
+    let mut out = String::new();
+    let pad = " ".repeat(indent);
+    for s in stmts {
+        match s {
+            // a leading `{` would parse as a block
+            Stmt::Expr(e) if e.starts_with('{') => out.push_str(&format!("{pad}({e});\n")),
+            Stmt::Decl(d) => out.push_str(&format!("{pad}{d}\n")),
+            Stmt::Expr(e) => out.push_str(&format!("{pad}{e};\n")),
+            Stmt::Assign(lhs, rhs) => out.push_str(&format!("{pad}{lhs} = {rhs};\n")),
+            Stmt::Return(Some(v)) => out.push_str(&format!("{pad}return {v};\n")),
+            Stmt::Return(None) => out.push_str(&format!("{pad}return;\n")),
+            Stmt::CondGoto {
+                cond,
+                if_false,
+                target,
             } => {
-                out.push_str(&format!("{pad}if ({cond}) {{\n"));
+                if *if_false {
+                    out.push_str(&format!("{pad}if (!{}) goto L{target};\n", cond.at(PREC_UNARY)));
+                } else {
+                    out.push_str(&format!("{pad}if ({}) goto L{target};\n", cond.text));
+                }
+            }
+            Stmt::Goto(t) => out.push_str(&format!("{pad}goto L{t};\n")),
+            Stmt::Annotated(s, source_line, lines) => {
+                if let Some(n) = source_line {
+                    out.push_str(&format!("{pad}// line {n}\n"));
+                }
+                out.push_str(&stmts_to_string(std::slice::from_ref(s), indent));
+                for line in lines {
+                    out.push_str(&format!("{pad}//   {line}\n"));
+                }
+            }
+            Stmt::Label(pc) => out.push_str(&format!("{pad}L{pc}:\n")),
+            Stmt::IfElse {
+                cond,
+                then_stmts,
+                else_stmts,
+            } => {
+                out.push_str(&format!("{pad}if ({}) {{\n", cond.text));
                 out.push_str(&stmts_to_string(then_stmts, indent + 2));
                 if else_stmts.is_empty() {
                     out.push_str(&format!("{pad}}}\n"));
@@ -1927,91 +3813,1150 @@ fn stmts_to_string(stmts: &[Stmt], indent: usize) -> String {
                 }
             }
             Stmt::While { cond, body } => {
-                out.push_str(&format!("{pad}while ({cond}) {{\n"));
+                out.push_str(&format!("{pad}while ({}) {{\n", cond.text));
+                out.push_str(&stmts_to_string(body, indent + 2));
+                out.push_str(&format!("{pad}}}\n"));
+            }
+            Stmt::DoWhile { body, cond } => {
+                out.push_str(&format!("{pad}do {{\n"));
+                out.push_str(&stmts_to_string(body, indent + 2));
+                out.push_str(&format!("{pad}}} while ({});\n", cond.text));
+            }
+            Stmt::For { init, cond, update, body } => {
+                let init = stmts_to_string(std::slice::from_ref(init), 0);
+                out.push_str(&format!("{pad}for ({}; {}; {update}) {{\n", init.trim_end().trim_end_matches(';'), cond.text));
+                out.push_str(&stmts_to_string(body, indent + 2));
+                out.push_str(&format!("{pad}}}\n"));
+            }
+            Stmt::With { obj, init, body } => {
+                // the folded initialization is annotated on the header
+                for s in init {
+                    if let Stmt::Annotated(_, Some(n), _) = s {
+                        out.push_str(&format!("{pad}// line {n}\n"));
+                    }
+                }
+                out.push_str(&format!("{pad}with ({obj}) {{\n"));
+                for s in init {
+                    if let Stmt::Annotated(_, _, lines) = s {
+                        for line in lines {
+                            out.push_str(&format!("{pad}//   {line}\n"));
+                        }
+                    }
+                }
                 out.push_str(&stmts_to_string(body, indent + 2));
                 out.push_str(&format!("{pad}}}\n"));
             }
+            Stmt::ForOf { binding, iterable, body } => {
+                out.push_str(&format!("{pad}for ({binding} of {iterable}) {{\n"));
+                out.push_str(&stmts_to_string(body, indent + 2));
+                out.push_str(&format!("{pad}}}\n"));
+            }
+            Stmt::Try { body, catch, finally } => {
+                out.push_str(&format!("{pad}try {{\n"));
+                out.push_str(&stmts_to_string(body, indent + 2));
+                match catch {
+                    Some((Some(binding), catch_body)) => {
+                        out.push_str(&format!("{pad}}} catch ({binding}) {{\n"));
+                        out.push_str(&stmts_to_string(catch_body, indent + 2));
+                    }
+                    Some((None, catch_body)) => {
+                        out.push_str(&format!("{pad}}} catch {{\n"));
+                        out.push_str(&stmts_to_string(catch_body, indent + 2));
+                    }
+                    None => {}
+                }
+                if let Some(finally) = finally {
+                    out.push_str(&format!("{pad}}} finally {{\n"));
+                    out.push_str(&stmts_to_string(finally, indent + 2));
+                }
+                out.push_str(&format!("{pad}}}\n"));
+            }
         }
     }
     out
 }
 
-fn optimize_stmts(stmts: &[Stmt]) -> Vec<Stmt> {
+fn mentions_ident(text: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    text.match_indices(name).any(|(at, _)| {
+        !text[..at].ends_with(is_ident) && !text[at + name.len()..].starts_with(is_ident)
+    })
+}
+
+// `locN = expr; return locN;` -> `return expr;`. A captured local is kept,
+// since a closure may still read the value after the function returns.
+fn inline_return_temps(b: &FunctionBytecode, stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let mut out: Vec<Stmt> = Vec::with_capacity(stmts.len());
+    let mut inlined = Vec::new();
+    let mut iter = stmts.into_iter().peekable();
+    while let Some(s) = iter.next() {
+        if let Stmt::Assign(name, rhs) = s.unannotated()
+            && let Some(Stmt::Return(Some(ret))) = iter.peek().map(Stmt::unannotated)
+            && ret == name
+            && local_var(b, name).is_some_and(|v| !v.is_captured())
+        {
+            inlined.push(name.clone());
+            let rhs = rhs.clone();
+            let ret = iter.next().expect("peeked").map_unannotated(|_| Stmt::Return(Some(rhs)));
+            out.push(ret.absorb(&s));
+            continue;
+        }
+        out.push(s);
+    }
+    // drop the lexical TDZ marker of a temporary that's now otherwise unused
+    for name in inlined {
+        let marker = format!("{name} = undefined");
+        let is_marker = |s: &Stmt| matches!(s.unannotated(), Stmt::Expr(e) if *e == marker);
+        let used = out
+            .iter()
+            .any(|s| !is_marker(s) && mentions_ident(&stmts_to_string(std::slice::from_ref(s.unannotated()), 0), &name));
+        if !used {
+            out = retain_stmts(out, |s| !is_marker(s));
+        }
+    }
+    out
+}
+
+/// Labels a jump in `stmts` still refers to, including the textual `gosub L..`.
+fn referenced_labels(stmts: &[Stmt], out: &mut HashSet<usize>) {
+    jump_targets(stmts, &mut |t| {
+        out.insert(t);
+    });
+}
+
+/// Calls `f` with the target of every jump in `stmts`, once per jump.
+fn jump_targets(stmts: &[Stmt], f: &mut impl FnMut(usize)) {
+    walk_stmts(stmts, &mut |s| match s {
+        Stmt::Goto(t) | Stmt::CondGoto { target: t, .. } => f(*t),
+        Stmt::Expr(e) => {
+            if let Some(t) = e.strip_prefix("gosub L").and_then(|t| t.parse().ok()) {
+                f(t);
+            }
+        }
+        _ => {}
+    });
+}
+
+/// Calls `f` with every statement in `stmts` and the bodies nested in them,
+/// annotations looked through.
+fn walk_stmts(stmts: &[Stmt], f: &mut impl FnMut(&Stmt)) {
+    for s in stmts {
+        let s = s.unannotated();
+        f(s);
+        match s {
+            Stmt::IfElse { then_stmts, else_stmts, .. } => {
+                walk_stmts(then_stmts, f);
+                walk_stmts(else_stmts, f);
+            }
+            Stmt::While { body, .. }
+            | Stmt::DoWhile { body, .. }
+            | Stmt::For { body, .. }
+            | Stmt::With { body, .. }
+            | Stmt::ForOf { body, .. } => walk_stmts(body, f),
+            Stmt::Try { body, catch, finally } => {
+                walk_stmts(body, f);
+                if let Some((_, catch_body)) = catch {
+                    walk_stmts(catch_body, f);
+                }
+                if let Some(finally) = finally {
+                    walk_stmts(finally, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Removes labels that aren't in `live`: the targets of rendered jumps plus
+/// those that aren't rendered as jumps (exception handlers, with_* fallbacks)
+/// but still mark where control can arrive.
+fn drop_dead_labels(stmts: Vec<Stmt>, live: &HashSet<usize>) -> Vec<Stmt> {
+    stmts
+        .into_iter()
+        .filter_map(|s| match s {
+            Stmt::Label(pc) if !live.contains(&pc) => None,
+            Stmt::IfElse { cond, then_stmts, else_stmts } => Some(Stmt::IfElse {
+                cond,
+                then_stmts: drop_dead_labels(then_stmts, live),
+                else_stmts: drop_dead_labels(else_stmts, live),
+            }),
+            Stmt::While { cond, body } => Some(Stmt::While { cond, body: drop_dead_labels(body, live) }),
+            Stmt::DoWhile { body, cond } => Some(Stmt::DoWhile { body: drop_dead_labels(body, live), cond }),
+            Stmt::For { init, cond, update, body } => Some(Stmt::For { init, cond, update, body: drop_dead_labels(body, live) }),
+            Stmt::With { obj, init, body } => Some(Stmt::With { obj, init, body: drop_dead_labels(body, live) }),
+            Stmt::ForOf { binding, iterable, body } => Some(Stmt::ForOf { binding, iterable, body: drop_dead_labels(body, live) }),
+            Stmt::Try { body, catch, finally } => Some(Stmt::Try {
+                body: drop_dead_labels(body, live),
+                catch: catch.map(|(binding, catch_body)| (binding, drop_dead_labels(catch_body, live))),
+                finally: finally.map(|finally| drop_dead_labels(finally, live)),
+            }),
+            s => Some(s),
+        })
+        .collect()
+}
+
+/// Retargets every jump whose destination is an unconditional `goto` at that
+/// goto's own destination, following chains (with a cycle guard) so each
+/// branch lands on the final target and the intermediate labels go dead.
+fn thread_jumps(instrs: &[Instr]) -> Vec<Instr> {
+    let is_goto = |i: &Instr| matches!(i.name, "goto" | "goto8" | "goto16");
+    let gotos: HashMap<usize, usize> = instrs
+        .iter()
+        .filter(|i| is_goto(i))
+        .filter_map(|i| Some((i.pc, label_target(i)?)))
+        .collect();
+    instrs
+        .iter()
+        .map(|i| {
+            if !matches!(i.name, "goto" | "goto8" | "goto16" | "if_false" | "if_true" | "if_false8" | "if_true8") {
+                return i.clone();
+            }
+            let Some(mut t) = label_target(i) else { return i.clone() };
+            let mut seen = HashSet::from([i.pc]);
+            while let Some(&next) = gotos.get(&t) {
+                if !seen.insert(t) {
+                    break;
+                }
+                t = next;
+            }
+            let mut i = i.clone();
+            // Label is relative to pc + 1 for every jump width
+            i.operand = Some(Operand::Label(t as i32 - (i.pc + 1) as i32));
+            i
+        })
+        .collect()
+}
+
+/// The int constant a `push_*` instruction pushes.
+fn int_const(i: &Instr) -> Option<i64> {
+    match (i.name, &i.operand) {
+        ("push_minus1", _) => Some(-1),
+        ("push_i8", Some(Operand::I8(v))) => Some(*v as i64),
+        ("push_i16", Some(Operand::I16(v))) => Some(*v as i64),
+        ("push_i32", Some(Operand::I32(v))) => Some(*v as i64),
+        (n, None) => n.strip_prefix("push_")?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The local slots an instruction reads or writes.
+fn loc_slots(i: &Instr) -> Vec<u16> {
+    if i.name == "get_loc0_loc1" {
+        return vec![0, 1];
+    }
+    if !i.name.contains("_loc") {
+        return Vec::new();
+    }
+    // the operand first: the 8 of get_loc8 is its operand width, not a slot
+    match i.operand {
+        Some(Operand::U8(v)) => vec![v as u16],
+        Some(Operand::U16(v)) | Some(Operand::AtomU16(_, v)) => vec![v],
+        _ => i.name.rsplit("_loc").next().and_then(|n| n.parse().ok()).into_iter().collect(),
+    }
+}
+
+/// The slot a `put_loc*` instruction stores to.
+fn put_loc_slot(i: &Instr) -> Option<u16> {
+    if !i.name.starts_with("put_loc") {
+        return None;
+    }
+    loc_slots(i).first().copied()
+}
+
+/// The slot a `get_loc*` instruction reads.
+fn get_loc_slot(i: &Instr) -> Option<u16> {
+    if !i.name.starts_with("get_loc") || i.name == "get_loc0_loc1" {
+        return None;
+    }
+    loc_slots(i).first().copied()
+}
+
+fn is_terminator(i: &Instr) -> bool {
+    matches!(i.name, "goto" | "goto8" | "goto16" | "return" | "return_undef" | "return_async" | "throw" | "throw_error" | "ret")
+}
+
+/// Runs the code from an int push as far as constants alone decide it: int
+/// pushes, dup/drop, stores to and reads of the state local `slot`,
+/// comparisons, branches on their results and plain gotos. Returns the pc
+/// where real code resumes, provided at least one comparison was decided on
+/// the way, which is what makes the path a dispatch.
+fn resolve_dispatch(instrs: &[Instr], by_pc: &HashMap<usize, usize>, start: usize, slot: u16) -> Option<usize> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Known {
+        Int(i64),
+        Bool(bool),
+    }
+
+    let mut stack: Vec<Known> = Vec::new();
+    let mut state: Option<i64> = None;
+    let mut idx = start;
+    let mut dispatched = false;
+    for _ in 0..10_000 {
+        let i = instrs.get(idx)?;
+        let mut next = idx + 1;
+        match i.name {
+            "dup" => stack.push(*stack.last()?),
+            "drop" => {
+                // the switch value pushed before the store point
+                stack.pop();
+            }
+            "nop" => {}
+            "strict_eq" | "strict_neq" | "eq" | "neq" => {
+                let (b, a) = (stack.pop()?, stack.pop()?);
+                let same = a == b;
+                stack.push(Known::Bool(if i.name.ends_with("neq") { !same } else { same }));
+            }
+            "if_false" | "if_false8" | "if_true" | "if_true8" => {
+                let truthy = match stack.pop()? {
+                    Known::Bool(b) => b,
+                    Known::Int(v) => v != 0,
+                };
+                dispatched = true;
+                if truthy == i.name.starts_with("if_true") {
+                    next = *by_pc.get(&label_target(i)?)?;
+                }
+            }
+            "goto" | "goto8" | "goto16" => next = *by_pc.get(&label_target(i)?)?,
+            _ => {
+                if get_loc_slot(i) == Some(slot) {
+                    stack.push(Known::Int(state?));
+                } else if let Some(v) = int_const(i) {
+                    stack.push(Known::Int(v));
+                } else if put_loc_slot(i) == Some(slot)
+                    && let Some(Known::Int(v)) = stack.last().copied()
+                {
+                    stack.pop();
+                    state = Some(v);
+                } else {
+                    return dispatched.then_some(i.pc);
+                }
+            }
+        }
+        idx = next;
+    }
+    None
+}
+
+/// Keeps only the instructions reachable from the entry or from a handler
+/// (catch/gosub/with_* target).
+fn prune_unreachable(instrs: Vec<Instr>) -> Vec<Instr> {
+    let by_pc: HashMap<usize, usize> = instrs.iter().enumerate().map(|(idx, i)| (i.pc, idx)).collect();
+    let is_jump = |i: &Instr| matches!(i.name, "goto" | "goto8" | "goto16" | "if_false" | "if_true" | "if_false8" | "if_true8");
+    let mut work: Vec<usize> = instrs.iter().filter(|i| !is_jump(i)).filter_map(label_target).filter_map(|t| by_pc.get(&t).copied()).collect();
+    if !instrs.is_empty() {
+        work.push(0);
+    }
+    let mut seen = vec![false; instrs.len()];
+    while let Some(idx) = work.pop() {
+        if idx >= instrs.len() || std::mem::replace(&mut seen[idx], true) {
+            continue;
+        }
+        let i = &instrs[idx];
+        if let Some(t) = label_target(i).and_then(|t| by_pc.get(&t)) {
+            work.push(*t);
+        }
+        if !is_terminator(i) {
+            work.push(idx + 1);
+        }
+    }
+    instrs.into_iter().zip(seen).filter_map(|(i, s)| s.then_some(i)).collect()
+}
+
+/// Undoes control-flow flattening, where code is split into the cases of a
+/// `while (true) switch (state)` dispatcher and each case ends by storing the
+/// state of the next. Every constant store whose dispatch can be decided is
+/// replaced by a jump straight to the case it selects; if that leaves the
+/// state local unread, the dispatcher goes dead and the cases are laid out
+/// in the order they run, each falling through to its successor.
+fn unflatten_dispatchers(instrs: Vec<Instr>) -> Vec<Instr> {
+    let by_pc: HashMap<usize, usize> = instrs.iter().enumerate().map(|(idx, i)| (i.pc, idx)).collect();
+    // (slot, pc of the pushed constant, resolved case); the store itself
+    // (`push k; put_loc s` or `push k; dup; put_loc s; drop`) is left for
+    // prune_unreachable once the push becomes a jump
+    let mut stores: Vec<(u16, usize, usize)> = Vec::new();
+    for idx in 0..instrs.len() {
+        if int_const(&instrs[idx]).is_some()
+            && let Some(slot) = instrs[idx + 1..].iter().take(2).find_map(put_loc_slot)
+            && let Some(t) = resolve_dispatch(&instrs, &by_pc, idx, slot)
+        {
+            stores.push((slot, instrs[idx].pc, t));
+        }
+    }
+
+    let mut slots: Vec<u16> = stores.iter().map(|s| s.0).collect();
+    slots.sort_unstable();
+    slots.dedup();
+    let mut out = instrs;
+    let mut changed = false;
+    for slot in slots {
+        let mut next = out.clone();
+        for &(_, pc, t) in stores.iter().filter(|s| s.0 == slot) {
+            if let Some(at) = next.iter().position(|i| i.pc == pc) {
+                next[at] = Instr { name: "goto", n_pop: 0, n_push: 0, operand: Some(Operand::Label(t as i32 - (pc + 1) as i32)), ..next[at].clone() };
+            }
+        }
+        // the TDZ marker of a `let` state goes with its stores
+        let mut next = prune_unreachable(next);
+        next.retain(|i| !(i.name == "set_loc_uninitialized" && loc_slots(i).contains(&slot)));
+        if next.iter().all(|i| !loc_slots(i).contains(&slot)) {
+            out = next;
+            changed = true;
+        }
+    }
+    if !changed {
+        return out;
+    }
+    // cases reached through a trampoline `goto` (an if/else in the case
+    // body) get threaded past it first
+    lay_out_fallthrough(prune_unreachable(thread_jumps(&out)))
+}
+
+/// Reorders runs of code that end in an unconditional transfer so that a
+/// `goto` to the start of a not-yet-placed run is followed by that run, and
+/// drops the `goto` since control now falls through.
+fn lay_out_fallthrough(instrs: Vec<Instr>) -> Vec<Instr> {
+    let targets: HashSet<usize> = instrs.iter().filter(|i| !is_terminator(i)).filter_map(label_target).collect();
+    let mut chunks: Vec<Vec<Instr>> = vec![Vec::new()];
+    for i in instrs {
+        let end = is_terminator(&i);
+        if let Some(c) = chunks.last_mut() {
+            c.push(i);
+        }
+        if end {
+            chunks.push(Vec::new());
+        }
+    }
+    chunks.retain(|c| !c.is_empty());
+
+    let mut placed = vec![false; chunks.len()];
+    let mut out = Vec::new();
+    let mut cur = Some(0);
+    while let Some(ci) = cur.filter(|&ci| ci < chunks.len()) {
+        placed[ci] = true;
+        let chunk = std::mem::take(&mut chunks[ci]);
+        let follow = chunk
+            .last()
+            .filter(|i| matches!(i.name, "goto" | "goto8" | "goto16"))
+            .and_then(label_target)
+            .and_then(|t| (0..chunks.len()).find(|&c| !placed[c] && chunks[c].first().is_some_and(|i| i.pc == t)));
+        let drop_goto = follow.is_some() && chunk.last().is_some_and(|i| !targets.contains(&i.pc));
+        let keep = chunk.len() - drop_goto as usize;
+        out.extend(chunk.into_iter().take(keep));
+        cur = follow.or_else(|| placed.iter().position(|p| !p));
+    }
+    out
+}
+
+fn optimize_stmts(b: &FunctionBytecode, instrs: &[Instr], stmts: &[Stmt]) -> Vec<Stmt> {
+    let stmts = inline_return_temps(b, stmts.to_vec());
     let mut out: Vec<Stmt> = Vec::new();
     let mut i = 0usize;
     while i < stmts.len() {
-        if let Some(Stmt::Goto(t)) = stmts.get(i) {
-            if let (Some(Stmt::Label(lpc)), Some(Stmt::Return(ret))) = (stmts.get(i + 1), stmts.get(i + 2)) {
-                if lpc == t {
-                    out.push(Stmt::Return(ret.clone()));
-                    i += 3;
-                    continue;
+        if let Some(Stmt::Goto(t)) = stmts.get(i)
+            && let (Some(Stmt::Label(lpc)), Some(Stmt::Return(_))) = (stmts.get(i + 1), stmts.get(i + 2).map(Stmt::unannotated))
+            && lpc == t
+        {
+            out.push(stmts[i + 2].clone());
+            i += 3;
+            continue;
+        }
+        out.push(stmts[i].clone());
+        i += 1;
+    }
+
+    let mut out2: Vec<Stmt> = Vec::new();
+    let mut j = 0usize;
+    while j < out.len() {
+        if let (Some(Stmt::Label(a)), Some(Stmt::Label(b))) = (out.get(j), out.get(j + 1))
+            && a == b
+        {
+            j += 1;
+            continue;
+        }
+        out2.push(out[j].clone());
+        j += 1;
+    }
+
+    let mut live: HashSet<usize> = instrs
+        .iter()
+        .filter(|i| !matches!(i.name, "goto" | "goto8" | "goto16" | "if_false" | "if_true" | "if_false8" | "if_true8" | "gosub"))
+        .filter_map(label_target)
+        .collect();
+    referenced_labels(&out2, &mut live);
+    drop_dead_labels(out2, &live)
+}
+
+/// The index of the local `loc_name` renders as `name`.
+fn local_index(b: &FunctionBytecode, name: &str) -> Option<usize> {
+    b.local_names.get_or_init(|| local_names(b)).iter().position(|n| n == name)
+}
+
+fn local_var<'a>(b: &'a FunctionBytecode, name: &str) -> Option<&'a VarDef> {
+    b.locals.get(b.arg_count as usize + local_index(b, name)?)
+}
+
+// Turns the first top-level write of each local into a `var`/`let`/`const`
+// declaration. A local first written inside a structured branch or loop is
+// left alone, since declaring it there would narrow its scope; a `var` one is
+// declared at the top of the function instead.
+fn declare_locals(b: &FunctionBytecode, stmts: &[Stmt]) -> Vec<Stmt> {
+    fn nested_writes(stmts: &[Stmt], seen: &mut HashSet<String>) {
+        for s in stmts {
+            match s.unannotated() {
+                Stmt::Assign(name, _) => {
+                    seen.insert(name.clone());
+                }
+                Stmt::IfElse { then_stmts, else_stmts, .. } => {
+                    nested_writes(then_stmts, seen);
+                    nested_writes(else_stmts, seen);
+                }
+                Stmt::While { body, .. } | Stmt::DoWhile { body, .. } | Stmt::With { body, .. } => nested_writes(body, seen),
+                Stmt::For { init, body, .. } => {
+                    nested_writes(std::slice::from_ref(init), seen);
+                    nested_writes(body, seen);
                 }
+                Stmt::ForOf { binding, body, .. } => {
+                    seen.insert(binding.clone());
+                    nested_writes(body, seen);
+                }
+                Stmt::Try { body, catch, finally } => {
+                    nested_writes(body, seen);
+                    if let Some((binding, catch_body)) = catch {
+                        seen.extend(binding.clone());
+                        nested_writes(catch_body, seen);
+                    }
+                    if let Some(finally) = finally {
+                        nested_writes(finally, seen);
+                    }
+                }
+                _ => {}
             }
         }
-        out.push(stmts[i].clone());
+    }
+
+    // The replacement for a top-level statement, or None to drop it.
+    fn declare(b: &FunctionBytecode, s: &Stmt, later: &[Stmt], seen: &mut HashSet<String>) -> Option<Stmt> {
+        match s {
+            Stmt::Assign(name, rhs) if !seen.contains(name) && local_index(b, name).is_some() => {
+                seen.insert(name.clone());
+                let kw = match local_var(b, name).map(VarDef::kind) {
+                    Some(VarKind::Var) => "var",
+                    Some(VarKind::Const) => "const",
+                    _ => "let",
+                };
+                Some(Stmt::Expr(format!("{kw} {name} = {rhs}")))
+            }
+            // set_loc_uninitialized's TDZ marker is subsumed by the declaration
+            Stmt::Expr(e) if e.strip_suffix(" = undefined").is_some_and(|name| !seen.contains(name) && local_var(b, name).is_some_and(VarDef::is_lexical)) => None,
+            Stmt::IfElse { then_stmts, else_stmts, .. } => {
+                nested_writes(then_stmts, seen);
+                nested_writes(else_stmts, seen);
+                Some(s.clone())
+            }
+            Stmt::While { body, .. } | Stmt::DoWhile { body, .. } | Stmt::With { body, .. } => {
+                nested_writes(body, seen);
+                Some(s.clone())
+            }
+            // a lexical induction variable that isn't used after the loop was
+            // declared in the header
+            Stmt::For { init, cond, update, body }
+                if let Stmt::Assign(name, _) = init.as_ref()
+                    && local_var(b, name).is_some_and(VarDef::is_lexical)
+                    && !mentions_ident(&stmts_to_string(later, 0), name) =>
+            {
+                let init = declare(b, init, &[], seen).unwrap_or_else(|| (**init).clone());
+                nested_writes(body, seen);
+                Some(Stmt::For { init: Box::new(init), cond: cond.clone(), update: update.clone(), body: body.clone() })
+            }
+            Stmt::For { .. } | Stmt::ForOf { .. } | Stmt::Try { .. } => {
+                nested_writes(std::slice::from_ref(s), seen);
+                Some(s.clone())
+            }
+            Stmt::Annotated(inner, source_line, lines) => {
+                Some(Stmt::Annotated(Box::new(declare(b, inner, later, seen)?), *source_line, lines.clone()))
+            }
+            _ => Some(s.clone()),
+        }
+    }
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut out: Vec<Stmt> = Vec::with_capacity(stmts.len());
+    let mut dropped = Vec::new();
+    for (i, s) in stmts.iter().enumerate() {
+        match declare(b, s, &stmts[i + 1..], &mut seen) {
+            Some(s) => out.push(absorb_dropped(s, &mut dropped)),
+            None => dropped.push(s.clone()),
+        }
+    }
+    let declared: HashSet<&str> = out
+        .iter()
+        .filter_map(|s| match s.unannotated() {
+            Stmt::Expr(e) => e.strip_prefix("var ")?.split(" = ").next(),
+            _ => None,
+        })
+        .collect();
+    let hoisted: Vec<String> = (0..b.var_count)
+        .filter(|&i| b.locals.get(b.arg_count as usize + i as usize).is_some_and(|v| v.kind() == VarKind::Var))
+        .map(|i| loc_name(b, i))
+        .filter(|name| seen.contains(name) && !declared.contains(name.as_str()))
+        .collect();
+    if !hoisted.is_empty() {
+        out.insert(0, Stmt::Expr(format!("var {}", hoisted.join(", "))));
+    }
+    out
+}
+
+/// The statements one `with` scope covers, as an inclusive index range, and
+/// the local holding its scope object.
+#[derive(Debug, Clone, Copy)]
+struct WithScope {
+    start: usize,
+    end: usize,
+    slot: u16,
+}
+
+// Wraps each with-scope's statements in a `with` block. A scope ends at its
+// last lookup, so what follows it and only joins up its control flow goes
+// with it: the labels a jump inside targets (the end of an `if` there) and a
+// loop's jump back inside. Nested scopes are handled recursively; a range
+// overlapping an earlier one is left unwrapped.
+fn wrap_with_scopes(b: &FunctionBytecode, stmts: Vec<Stmt>, mut regions: Vec<WithScope>) -> Vec<Stmt> {
+    for r in &mut regions {
+        while r.end + 1 < stmts.len() {
+            let inside = &stmts[r.start..=r.end];
+            let mut targets = HashSet::new();
+            referenced_labels(inside, &mut targets);
+            if let Some(n) = labels_after(&stmts[r.end + 1..]).iter().rposition(|pc| targets.contains(pc)) {
+                r.end += n + 1;
+            } else if let Stmt::Goto(t) = stmts[r.end + 1]
+                && inside.iter().any(|s| matches!(s, Stmt::Label(pc) if *pc == t))
+            {
+                r.end += 1;
+            } else {
+                break;
+            }
+        }
+    }
+    regions.sort_by_key(|r| (r.start, std::cmp::Reverse(r.end)));
+    let mut out = Vec::with_capacity(stmts.len());
+    let mut i = 0usize;
+    for (ri, r) in regions.iter().enumerate() {
+        let (start, end) = (r.start, r.end.min(stmts.len().saturating_sub(1)));
+        if start < i || start > end {
+            continue;
+        }
+        out.extend_from_slice(&stmts[i..start]);
+        let inner = regions[ri + 1..]
+            .iter()
+            .filter(|r| r.start >= start && r.end <= end)
+            .map(|r| WithScope { start: r.start - start, end: r.end - start, slot: r.slot })
+            .collect();
+        let body = wrap_with_scopes(b, stmts[start..=end].to_vec(), inner);
+        let (obj, init) = with_scope_object(&mut out, &loc_name(b, r.slot));
+        out.push(Stmt::With { obj, init, body });
+        i = end + 1;
+    }
+    out.extend_from_slice(&stmts[i.min(stmts.len())..]);
+    out
+}
+
+/// Undoes the `with` blocks a jump still crosses once control flow is
+/// structured, into the body or out of it: their statements go back in
+/// place after the scope object's initialization.
+fn unwrap_crossed_withs(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    // the handler of a try left unstructured counts as a jump target
+    fn targets(stmts: &[Stmt], f: &mut impl FnMut(usize)) {
+        jump_targets(stmts, f);
+        walk_stmts(stmts, &mut |s| {
+            if let Stmt::Expr(e) = s
+                && let Some(t) = e.strip_prefix(TRY_START).and_then(|t| t.parse().ok())
+            {
+                f(t);
+            }
+        });
+    }
+
+    fn unwrap(stmts: Vec<Stmt>, refs: &HashMap<usize, usize>) -> Vec<Stmt> {
+        let mut out = Vec::with_capacity(stmts.len());
+        for s in stmts {
+            match s {
+                Stmt::With { obj, init, body } => {
+                    let body = unwrap(body, refs);
+                    let mut inside: HashMap<usize, usize> = HashMap::new();
+                    targets(&body, &mut |t| *inside.entry(t).or_default() += 1);
+                    let mut defined = HashSet::new();
+                    walk_stmts(&body, &mut |s| {
+                        if let Stmt::Label(pc) = s {
+                            defined.insert(*pc);
+                        }
+                    });
+                    let leaves = inside.keys().any(|t| !defined.contains(t));
+                    let enters = defined.iter().any(|pc| refs.get(pc) != inside.get(pc));
+                    if leaves || enters {
+                        out.extend(init);
+                        out.extend(body);
+                    } else {
+                        out.push(Stmt::With { obj, init, body });
+                    }
+                }
+                Stmt::IfElse { cond, then_stmts, else_stmts } => out.push(Stmt::IfElse {
+                    cond,
+                    then_stmts: unwrap(then_stmts, refs),
+                    else_stmts: unwrap(else_stmts, refs),
+                }),
+                Stmt::While { cond, body } => out.push(Stmt::While { cond, body: unwrap(body, refs) }),
+                Stmt::DoWhile { body, cond } => out.push(Stmt::DoWhile { body: unwrap(body, refs), cond }),
+                Stmt::For { init, cond, update, body } => out.push(Stmt::For { init, cond, update, body: unwrap(body, refs) }),
+                Stmt::ForOf { binding, iterable, body } => out.push(Stmt::ForOf { binding, iterable, body: unwrap(body, refs) }),
+                Stmt::Try { body, catch, finally } => out.push(Stmt::Try {
+                    body: unwrap(body, refs),
+                    catch: catch.map(|(binding, catch_body)| (binding, unwrap(catch_body, refs))),
+                    finally: finally.map(|finally| unwrap(finally, refs)),
+                }),
+                s => out.push(s),
+            }
+        }
+        out
+    }
+
+    let mut refs: HashMap<usize, usize> = HashMap::new();
+    targets(&stmts, &mut |t| *refs.entry(t).or_default() += 1);
+    unwrap(stmts, &refs)
+}
+
+// The compiler stores `Object(expr)` in a hidden local before the scope; fold
+// that back into `with (expr)` and take out the local's initialization, which
+// is returned.
+fn with_scope_object(before: &mut Vec<Stmt>, local: &str) -> (String, Vec<Stmt>) {
+    let Some(pos) = before.iter().rposition(|s| matches!(s.unannotated(), Stmt::Assign(name, _) if name == local)) else {
+        return (local.to_string(), Vec::new());
+    };
+    let init = before.remove(pos);
+    let Stmt::Assign(_, rhs) = init.unannotated() else { unreachable!() };
+    let obj = match rhs.strip_prefix("Object(").and_then(|r| r.strip_suffix(')')) {
+        Some(inner) => inner.to_string(),
+        None => rhs.clone(),
+    };
+    let tdz = format!("{local} = undefined");
+    match before[..pos].iter().rposition(|s| matches!(s.unannotated(), Stmt::Expr(e) if *e == tdz)) {
+        Some(p) => (obj, vec![before.remove(p), init]),
+        None => (obj, vec![init]),
+    }
+}
+
+/// Wall-clock budget for one function (`DecompileOptions::function_timeout`),
+/// checked by the passes whose cost grows faster than the function.
+#[derive(Debug, Clone, Copy)]
+struct Deadline(Option<Instant>);
+
+#[derive(Debug)]
+struct TimedOut;
+
+impl Deadline {
+    fn after(timeout: Option<Duration>) -> Self {
+        Self(timeout.map(|t| Instant::now() + t))
+    }
+
+    fn check(self) -> Result<(), TimedOut> {
+        match self.0 {
+            Some(at) if Instant::now() >= at => Err(TimedOut),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rebuilds loops from their back-edges. A head-controlled loop is
+///
+/// ```text
+///   Label(loop)
+///   CondGoto(if_false=true, target=end)
+///   ...body...
+///   Goto(loop)
+///   Label(end)
+/// ```
+///
+/// which becomes a `for` when the statement before it initializes a variable
+/// the condition tests and the body ends by updating it. A tail-controlled
+/// loop is a label with a conditional jump back to it, `do { } while`.
+fn try_structure_while(stmts: &[Stmt], deadline: Deadline) -> Result<Vec<Stmt>, TimedOut> {
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i < stmts.len() {
+        deadline.check()?;
+        if let Some((body_end, j, continue_pc, cond)) = do_while_at(stmts, i)
+            && let Stmt::Label(head) = stmts[i]
+        {
+            let exits = labels_after(&stmts[j + 1..]);
+            let mut labeled = false;
+            let body = loop_jumps(try_structure_while(&stmts[i + 1..body_end], deadline)?, continue_pc, &exits, head, &mut labeled);
+            if labeled {
+                out.push(Stmt::Label(head));
+            }
+            out.push(Stmt::DoWhile { body, cond });
+            i = j + 1;
+            continue;
+        }
+        if let (Some(Stmt::Label(loop_pc)), Some(Stmt::CondGoto { cond, if_false, target: end_pc })) =
+            (stmts.get(i), stmts.get(i + 1))
+            && *if_false
+            && let Some(j) = (i + 2..stmts.len().saturating_sub(1))
+                .find(|&j| matches!((&stmts[j], &stmts[j + 1]), (Stmt::Goto(t), Stmt::Label(pc)) if t == loop_pc && pc == end_pc))
+        {
+            let mut body = try_structure_while(&stmts[i + 2..j], deadline)?;
+            let exits = labels_after(&stmts[j + 1..]);
+            // a `continue` that skips the update rules out a `for`
+            let mut targets = HashSet::new();
+            referenced_labels(&body, &mut targets);
+            let parts = if targets.contains(loop_pc) { None } else { for_loop_parts(&mut out, cond, &mut body) };
+            let mut labeled = false;
+            let stmt = match parts {
+                Some((init, update, continue_pc)) => {
+                    let body = loop_jumps(body, continue_pc.unwrap_or(*loop_pc), &exits, *loop_pc, &mut labeled);
+                    Stmt::For { init: Box::new(init), cond: cond.clone(), update, body }
+                }
+                None => Stmt::While { cond: cond.clone(), body: loop_jumps(body, *loop_pc, &exits, *loop_pc, &mut labeled) },
+            };
+            // the head stays labeled while a jump from outside enters there
+            let mut outside = HashSet::new();
+            referenced_labels(&stmts[..i], &mut outside);
+            referenced_labels(&stmts[j + 1..], &mut outside);
+            if labeled || outside.contains(loop_pc) {
+                out.push(Stmt::Label(*loop_pc));
+            }
+            out.push(stmt);
+            i = j + 2;
+            continue;
+        }
+        out.push(match &stmts[i] {
+            Stmt::With { obj, init, body } => Stmt::With { obj: obj.clone(), init: init.clone(), body: try_structure_while(body, deadline)? },
+            s => s.clone(),
+        });
         i += 1;
     }
+    Ok(out)
+}
+
+/// The labels at the start of `stmts`: where control lands on leaving a loop
+/// that ends just before them.
+fn labels_after(stmts: &[Stmt]) -> Vec<usize> {
+    stmts.iter().map_while(|s| if let Stmt::Label(pc) = s { Some(*pc) } else { None }).collect()
+}
+
+/// The `do { } while` whose head label is `stmts[i]`: the end of its body, the
+/// index of its closing conditional jump, where `continue` lands and the loop
+/// condition. The label must dominate that back-edge: no jump from outside
+/// the loop may enter it other than through the head.
+fn do_while_at(stmts: &[Stmt], i: usize) -> Option<(usize, usize, usize, Expr)> {
+    let Stmt::Label(head) = stmts[i] else { return None };
+    let is_jump_to = |s: &Stmt, pc: usize| matches!(s.unannotated(), Stmt::Goto(t) | Stmt::CondGoto { target: t, .. } if *t == pc);
+    let j = (i + 1..stmts.len()).find(|&j| is_jump_to(&stmts[j], head))?;
+    let Stmt::CondGoto { cond, if_false, .. } = stmts[j].unannotated() else { return None };
+    let mut outside = HashSet::new();
+    referenced_labels(&stmts[..i], &mut outside);
+    referenced_labels(&stmts[j + 1..], &mut outside);
+    if stmts[i..j].iter().any(|s| matches!(s, Stmt::Label(pc) if outside.contains(pc))) {
+        return None;
+    }
+    // a label just before the condition is where `continue` lands
+    let (body_end, continue_pc) = match stmts[j - 1] {
+        Stmt::Label(pc) if j - 1 > i => (j - 1, pc),
+        _ => (j, head),
+    };
+    let cond = if *if_false { Expr::new(format!("!{}", cond.at(PREC_UNARY)), PREC_UNARY) } else { cond.clone() };
+    Some((body_end, j, continue_pc, cond))
+}
+
+/// Splits `init; while (cond) { ...body; update }` into a `for` loop's
+/// parts: `init` taken off the end of `before`, the update taken off `body`
+/// and the label before it, where `continue` lands.
+fn for_loop_parts(before: &mut Vec<Stmt>, cond: &Expr, body: &mut Vec<Stmt>) -> Option<(Stmt, String, Option<usize>)> {
+    let var = match before.last().map(Stmt::unannotated) {
+        Some(Stmt::Assign(name, _)) if mentions_ident(&cond.text, name) => name.clone(),
+        _ => return None,
+    };
+    let is_update = |e: &str| {
+        e.strip_prefix("++").or_else(|| e.strip_prefix("--")).is_some_and(|v| v == var)
+            || e.strip_prefix(var.as_str()).is_some_and(|rest| {
+                matches!(rest, "++" | "--")
+                    || rest.strip_prefix(' ').and_then(|r| r.split(' ').next()).is_some_and(|op| {
+                        matches!(op, "+=" | "-=" | "*=" | "/=" | "%=" | "**=" | "<<=" | ">>=" | ">>>=" | "&=" | "|=" | "^=")
+                    })
+            })
+    };
+    let update = match body.last().map(Stmt::unannotated) {
+        Some(Stmt::Expr(e)) if is_update(e) => e.clone(),
+        Some(Stmt::Assign(name, rhs)) if *name == var => format!("{name} = {rhs}"),
+        _ => return None,
+    };
+    body.pop();
+    let continue_pc = match body.last() {
+        Some(Stmt::Label(pc)) => {
+            let pc = *pc;
+            body.pop();
+            Some(pc)
+        }
+        _ => None,
+    };
+    let init = before.pop()?;
+    Some((init.unannotated().clone(), update, continue_pc))
+}
+
+/// Rebuilds `for (x of iterable)` from the loop QuickJS emits:
+///
+/// ```text
+///   <for_of> iterable       for_of_start
+///   Goto(next)
+///   Label(body)
+///   x = <for_of_value>      the store that consumes for_of_next's value
+///   ...body...
+///   Label(next)
+///   CondGoto(if_false=true, cond=<done>, target=body)
+/// ```
+///
+/// Jumps to `next` inside the body become `continue`, and jumps to the labels
+/// right after the loop (its exit and the `iterator_close` a `break` runs)
+/// become `break`.
+fn try_structure_for_of(stmts: &[Stmt]) -> Vec<Stmt> {
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i < stmts.len() {
+        if let Stmt::Expr(e) = stmts[i].unannotated()
+            && let Some(iterable) = e.strip_prefix(FOR_OF_START)
+            && let (Some(Stmt::Goto(next_pc)), Some(Stmt::Label(body_pc))) = (stmts.get(i + 1), stmts.get(i + 2))
+            && let Some(Stmt::Assign(binding, value)) = stmts.get(i + 3).map(Stmt::unannotated)
+            && value == FOR_OF_VALUE
+            && let Some(j) = (i + 4..stmts.len()).find(|&j| matches!(stmts[j], Stmt::Label(pc) if pc == *next_pc))
+            && let Some(Stmt::CondGoto { if_false: true, target, .. }) = stmts.get(j + 1).map(Stmt::unannotated)
+            && target == body_pc
+        {
+            let exits = labels_after(&stmts[j + 2..]);
+            let mut labeled = false;
+            let body = loop_jumps(try_structure_for_of(&stmts[i + 4..j]), *next_pc, &exits, *body_pc, &mut labeled);
+            if labeled {
+                out.push(Stmt::Label(*body_pc));
+            }
+            out.push(Stmt::ForOf { binding: binding.clone(), iterable: iterable.to_string(), body });
+            i = j + 2;
+            continue;
+        }
+        out.push(match &stmts[i] {
+            Stmt::With { obj, init, body } => Stmt::With { obj: obj.clone(), init: init.clone(), body: try_structure_for_of(body) },
+            s => s.clone(),
+        });
+        i += 1;
+    }
+    out
+}
+
+/// Rewrites a structured loop body's jumps to the loop's continue point as
+/// `continue` and to its exit labels as `break`. Inside a nested loop the
+/// bare keywords would mean that loop, so there they name this one as
+/// `break L{label}`, and `labeled` is set for the caller to label the loop.
+fn loop_jumps(body: Vec<Stmt>, continue_pc: usize, break_pcs: &[usize], label: usize, labeled: &mut bool) -> Vec<Stmt> {
+    struct Targets<'a> {
+        continue_pc: usize,
+        break_pcs: &'a [usize],
+        label: usize,
+    }
+
+    fn keyword(pc: usize, t: &Targets, nested: bool, labeled: &mut bool) -> Option<String> {
+        let kw = if pc == t.continue_pc {
+            "continue"
+        } else if t.break_pcs.contains(&pc) {
+            "break"
+        } else {
+            return None;
+        };
+        if nested {
+            *labeled = true;
+            Some(format!("{kw} L{}", t.label))
+        } else {
+            Some(kw.to_string())
+        }
+    }
 
-    let mut out2: Vec<Stmt> = Vec::new();
-    let mut j = 0usize;
-    while j < out.len() {
-        if let (Some(Stmt::Label(a)), Some(Stmt::Label(b))) = (out.get(j), out.get(j + 1)) {
-            if a == b {
-                j += 1;
-                continue;
-            }
-        }
-        out2.push(out[j].clone());
-        j += 1;
+    fn rewrite(body: Vec<Stmt>, t: &Targets, nested: bool, labeled: &mut bool) -> Vec<Stmt> {
+        body.into_iter()
+            .map(|s| match s {
+                Stmt::Goto(pc) => keyword(pc, t, nested, labeled).map_or(Stmt::Goto(pc), Stmt::Expr),
+                Stmt::CondGoto { cond, if_false, target } => match keyword(target, t, nested, labeled) {
+                    Some(kw) if if_false => Stmt::Expr(format!("if (!{}) {kw}", cond.at(PREC_UNARY))),
+                    Some(kw) => Stmt::Expr(format!("if ({}) {kw}", cond.text)),
+                    None => Stmt::CondGoto { cond, if_false, target },
+                },
+                Stmt::IfElse { cond, then_stmts, else_stmts } => Stmt::IfElse {
+                    cond,
+                    then_stmts: rewrite(then_stmts, t, nested, labeled),
+                    else_stmts: rewrite(else_stmts, t, nested, labeled),
+                },
+                Stmt::With { obj, init, body } => Stmt::With { obj, init, body: rewrite(body, t, nested, labeled) },
+                Stmt::While { cond, body } => Stmt::While { cond, body: rewrite(body, t, true, labeled) },
+                Stmt::DoWhile { body, cond } => Stmt::DoWhile { body: rewrite(body, t, true, labeled), cond },
+                Stmt::For { init, cond, update, body } => Stmt::For { init, cond, update, body: rewrite(body, t, true, labeled) },
+                Stmt::ForOf { binding, iterable, body } => Stmt::ForOf { binding, iterable, body: rewrite(body, t, true, labeled) },
+                Stmt::Annotated(inner, source_line, lines) => {
+                    Stmt::Annotated(Box::new(rewrite(vec![*inner], t, nested, labeled).remove(0)), source_line, lines)
+                }
+                s => s,
+            })
+            .collect()
     }
-    out2
+
+    rewrite(body, &Targets { continue_pc, break_pcs, label }, false, labeled)
 }
 
-fn try_structure_while(stmts: &[Stmt]) -> Vec<Stmt> {
-    // Pattern:
-    //   Label(loop)
-    //   CondGoto(if_false=true, target=end)
-    //   ...body...
-    //   Goto(loop)
-    //   Label(end)
+/// Rebuilds `try` statements from the regions `catch` opens. The compiler
+/// lays them out as:
+///
+/// ```text
+///   <try> H                 catch H, around the protected body
+///   ...body...
+///   gosub F                 only with a finally
+///   Goto(end)
+///   Label(H)
+///   e = <exception>         the catch binding (nothing for `catch { }`)
+///   <try> R                 the catch body is itself protected
+///   ...catch body...
+///   gosub F
+///   Goto(end)
+///   Label(R)
+///   gosub F                 only with a finally
+///   throw <exception>       rethrow
+///   Label(F)
+///   ...finally body...
+///   ret
+///   Label(end)
+/// ```
+///
+/// A try without a catch has `gosub F; throw <exception>` right at `H`. The
+/// `gosub F` ahead of each exit (falling out, `return`, `break`) is the
+/// finally block run inline, and goes with the region.
+fn try_structure_try(stmts: &[Stmt]) -> Vec<Stmt> {
+    let try_start = |s: &Stmt| match s.unannotated() {
+        Stmt::Expr(e) => e.strip_prefix(TRY_START).and_then(|pc| pc.parse::<usize>().ok()),
+        _ => None,
+    };
+    let gosub = |s: &Stmt| match s.unannotated() {
+        Stmt::Expr(e) => e.strip_prefix("gosub L").and_then(|pc| pc.parse::<usize>().ok()),
+        _ => None,
+    };
+    let is_rethrow = |s: &Stmt| matches!(s.unannotated(), Stmt::Expr(e) if e.strip_prefix("throw ") == Some(EXCEPTION));
+    let find_label = |from: usize, pc: usize| (from..stmts.len()).find(|&j| matches!(stmts[j], Stmt::Label(l) if l == pc));
+    let skip_labels = |mut k: usize| {
+        while matches!(stmts.get(k), Some(Stmt::Label(_))) {
+            k += 1;
+        }
+        k
+    };
+    // the label run starting at `k` includes `pc`
+    let labels_include = |k: usize, pc: usize| stmts[k.min(stmts.len())..].iter().map_while(|s| if let Stmt::Label(l) = s { Some(*l) } else { None }).any(|l| l == pc);
+    // where a body's control leaves the try normally
+    let exit = |body: &[Stmt]| match body.iter().rev().find(|s| !matches!(s, Stmt::Label(_))).map(Stmt::unannotated) {
+        Some(Stmt::Goto(t)) => Some(*t),
+        _ => None,
+    };
+
     let mut out = Vec::new();
     let mut i = 0usize;
     while i < stmts.len() {
-        if let (Some(Stmt::Label(loop_pc)), Some(Stmt::CondGoto { cond, if_false, target: end_pc })) =
-            (stmts.get(i), stmts.get(i + 1))
+        if let Some(handler) = try_start(&stmts[i])
+            && let Some(h) = find_label(i + 1, handler)
         {
-            if *if_false {
-                    let mut body = Vec::new();
-                    let mut j = i + 2;
-                    while j < stmts.len() {
-                        if let Stmt::Goto(t) = &stmts[j] {
-                            if *t == *loop_pc {
-                                break;
-                            }
-                        }
-                        body.push(stmts[j].clone());
-                        j += 1;
-                    }
-                    if let (Some(Stmt::Goto(t)), Some(Stmt::Label(pc2))) = (stmts.get(j), stmts.get(j + 1)) {
-                        if *t == *loop_pc && *pc2 == *end_pc {
-                            out.push(Stmt::While {
-                                cond: cond.clone(),
-                                body,
-                            });
-                            i = j + 2;
-                            continue;
+            let body = &stmts[i + 1..h];
+            let mut k = h + 1;
+            let mut catch = None;
+            let mut finally_pc = None;
+            let mut matched = false;
+            let binding = match stmts.get(k).map(Stmt::unannotated) {
+                Some(Stmt::Assign(name, value)) if value == EXCEPTION => {
+                    k += 1;
+                    Some(name.clone())
+                }
+                _ => None,
+            };
+            k = skip_labels(k);
+            if let Some(rethrow) = stmts.get(k).and_then(try_start)
+                && let Some(r) = find_label(k + 1, rethrow)
+            {
+                catch = Some((binding, &stmts[k + 1..r]));
+                k = skip_labels(r + 1);
+                if let Some(f) = stmts.get(k).and_then(gosub) {
+                    finally_pc = Some(f);
+                    k = skip_labels(k + 1);
+                }
+                matched = stmts.get(k).is_some_and(is_rethrow);
+            } else if binding.is_none()
+                && let Some(f) = stmts.get(k).and_then(gosub)
+            {
+                finally_pc = Some(f);
+                k = skip_labels(k + 1);
+                matched = stmts.get(k).is_some_and(is_rethrow);
+            }
+            let end = exit(body).or_else(|| catch.as_ref().and_then(|(_, catch_body)| exit(catch_body)));
+            let mut finally = None;
+            if matched {
+                k += 1;
+                if let Some(f) = finally_pc {
+                    // the finally body runs up to the `ret` the region's exit follows
+                    let start = labels_include(k, f).then(|| find_label(k, f)).flatten();
+                    let ret = start.and_then(|start| {
+                        (start + 1..stmts.len()).find(|&j| {
+                            matches!(stmts[j].unannotated(), Stmt::Expr(e) if e.starts_with("ret "))
+                                && end.is_none_or(|end| labels_include(j + 1, end))
+                        })
+                    });
+                    match (start, ret) {
+                        (Some(start), Some(ret)) => {
+                            finally = Some(try_structure_try(&stmts[start + 1..ret]));
+                            k = ret + 1;
                         }
+                        _ => matched = false,
+                    }
+                } else if let Some(end) = end {
+                    matched = labels_include(k, end);
+                }
+            }
+            if matched {
+                let strip = |body: &[Stmt]| {
+                    let mut body: Vec<Stmt> = body.iter().filter(|s| finally_pc.is_none() || gosub(s) != finally_pc).cloned().collect();
+                    if let Some(last) = body.iter().rposition(|s| !matches!(s, Stmt::Label(_)))
+                        && end.is_some()
+                        && exit(&body[..=last]) == end
+                    {
+                        body.remove(last);
                     }
+                    try_structure_try(&body)
+                };
+                out.push(Stmt::Try {
+                    body: strip(body),
+                    catch: catch.map(|(binding, catch_body)| (binding, strip(catch_body))),
+                    finally,
+                });
+                i = k;
+                continue;
             }
         }
-        out.push(stmts[i].clone());
+        out.push(match &stmts[i] {
+            Stmt::IfElse { cond, then_stmts, else_stmts } => Stmt::IfElse {
+                cond: cond.clone(),
+                then_stmts: try_structure_try(then_stmts),
+                else_stmts: try_structure_try(else_stmts),
+            },
+            Stmt::While { cond, body } => Stmt::While { cond: cond.clone(), body: try_structure_try(body) },
+            Stmt::DoWhile { body, cond } => Stmt::DoWhile { body: try_structure_try(body), cond: cond.clone() },
+            Stmt::For { init, cond, update, body } => {
+                Stmt::For { init: init.clone(), cond: cond.clone(), update: update.clone(), body: try_structure_try(body) }
+            }
+            Stmt::With { obj, init, body } => Stmt::With { obj: obj.clone(), init: init.clone(), body: try_structure_try(body) },
+            Stmt::ForOf { binding, iterable, body } => Stmt::ForOf { binding: binding.clone(), iterable: iterable.clone(), body: try_structure_try(body) },
+            s => s.clone(),
+        });
         i += 1;
     }
     out
 }
 
-fn try_structure_if_else(stmts: &[Stmt]) -> Vec<Stmt> {
+fn try_structure_if_else(stmts: &[Stmt], deadline: Deadline) -> Result<Vec<Stmt>, TimedOut> {
     // Pattern:
     //   CondGoto(if_false=true, target=else)
     //   ...then...
@@ -2022,6 +4967,7 @@ fn try_structure_if_else(stmts: &[Stmt]) -> Vec<Stmt> {
     let mut out = Vec::new();
     let mut i = 0usize;
     while i < stmts.len() {
+        deadline.check()?;
         if let (
             Some(Stmt::CondGoto {
                 cond,
@@ -2030,104 +4976,369 @@ fn try_structure_if_else(stmts: &[Stmt]) -> Vec<Stmt> {
             }),
             Some(Stmt::Goto(_end_from_then)),
         ) = (stmts.get(i), stmts.get(i + 1))
+            && *if_false
         {
-            if *if_false {
-                let mut then_stmts = Vec::new();
-                let mut j = i + 2;
-                while j < stmts.len() {
-                    if matches!(stmts[j], Stmt::Goto(_)) {
+            let mut then_stmts = Vec::new();
+            let mut j = i + 2;
+            while j < stmts.len() {
+                if matches!(stmts[j], Stmt::Goto(_)) {
+                    break;
+                }
+                if let Stmt::Label(pc) = stmts[j]
+                    && pc == *else_pc
+                {
+                    break;
+                }
+                then_stmts.push(stmts[j].clone());
+                j += 1;
+            }
+            if let Some(Stmt::Goto(end_pc)) = stmts.get(j)
+                && let Some(Stmt::Label(pc)) = stmts.get(j + 1)
+                && *pc == *else_pc
+            {
+                let mut else_stmts = Vec::new();
+                let mut k = j + 2;
+                while k < stmts.len() {
+                    if let Stmt::Label(pc2) = stmts[k]
+                        && pc2 == *end_pc
+                    {
                         break;
                     }
-                    if let Stmt::Label(pc) = stmts[j] {
-                        if pc == *else_pc {
-                            break;
-                        }
-                    }
-                    then_stmts.push(stmts[j].clone());
-                    j += 1;
-                }
-                if let Some(Stmt::Goto(end_pc)) = stmts.get(j) {
-                    if let Some(Stmt::Label(pc)) = stmts.get(j + 1) {
-                        if *pc == *else_pc {
-                            let mut else_stmts = Vec::new();
-                            let mut k = j + 2;
-                            while k < stmts.len() {
-                                if let Stmt::Label(pc2) = stmts[k] {
-                                    if pc2 == *end_pc {
-                                        break;
-                                    }
-                                }
-                                else_stmts.push(stmts[k].clone());
-                                k += 1;
-                            }
-                            if let Some(Stmt::Label(pc2)) = stmts.get(k) {
-                                if *pc2 == *end_pc {
-                                    out.push(Stmt::IfElse {
-                                        cond: cond.clone(),
-                                        then_stmts,
-                                        else_stmts,
-                                    });
-                                    i = k + 1;
-                                    continue;
-                                }
-                            }
-                        }
-                    }
+                    else_stmts.push(stmts[k].clone());
+                    k += 1;
+                }
+                if let Some(Stmt::Label(pc2)) = stmts.get(k)
+                    && *pc2 == *end_pc
+                {
+                    out.push(Stmt::IfElse {
+                        cond: cond.clone(),
+                        then_stmts,
+                        else_stmts,
+                    });
+                    i = k + 1;
+                    continue;
                 }
             }
         }
-        out.push(stmts[i].clone());
+        out.push(match &stmts[i] {
+            Stmt::With { obj, init, body } => Stmt::With { obj: obj.clone(), init: init.clone(), body: try_structure_if_else(body, deadline)? },
+            s => s.clone(),
+        });
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// Drops the nullish-base check QuickJS emits before converting a computed
+/// key (`dup; is_undefined_or_null; if_true L; swap; to_propkey; swap; L:`).
+/// Both paths leave `obj key` on the stack, so it only splits `a[k] = v`
+/// across blocks without changing what is rendered.
+fn fold_propkey_checks(instrs: &[Instr]) -> Vec<Instr> {
+    let targets: HashSet<usize> = instrs.iter().filter_map(label_target).collect();
+    let mut out = Vec::with_capacity(instrs.len());
+    let mut i = 0;
+    while i < instrs.len() {
+        if let Some(w) = instrs.get(i..i + 7)
+            && w[0].name == "dup"
+            && w[1].name == "is_undefined_or_null"
+            && w[2].name.starts_with("if_true")
+            && w[3].name == "swap"
+            && w[4].name == "to_propkey"
+            && w[5].name == "swap"
+            && label_target(&w[2]) == Some(w[6].pc)
+            && w[1..6].iter().all(|ins| !targets.contains(&ins.pc))
+        {
+            out.push(w[3].clone());
+            out.push(w[4].clone());
+            out.push(w[5].clone());
+            i += 6;
+            continue;
+        }
+        out.push(instrs[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Collapses the nullish guard of each optional-chain link
+/// (`dup; is_undefined_or_null; if_false L; drop..; undefined; goto END|return; L:`)
+/// into a synthetic `optional_chain` instruction. The skip path only yields
+/// `undefined` at the chain's end, which is what `?.` means, so removing it
+/// keeps multi-link chains in a single block.
+fn fold_optional_chains(instrs: &[Instr]) -> Vec<Instr> {
+    let targets: HashSet<usize> = instrs.iter().filter_map(label_target).collect();
+    let mut out = Vec::with_capacity(instrs.len());
+    let mut i = 0;
+    while i < instrs.len() {
+        if let Some(w) = instrs.get(i..i + 3)
+            && w[0].name == "dup"
+            && w[1].name == "is_undefined_or_null"
+            && w[2].name.starts_with("if_false")
+        {
+            let drops = instrs[i + 3..].iter().take_while(|ins| ins.name == "drop").count();
+            let skip = i + 3 + drops;
+            if drops > 0
+                && instrs.get(skip).is_some_and(|ins| ins.name == "undefined")
+                && instrs.get(skip + 1).is_some_and(|ins| ins.name.starts_with("goto") || ins.name == "return")
+                && let Some(cont) = instrs.get(skip + 2)
+                && label_target(&w[2]) == Some(cont.pc)
+                && instrs[i + 1..skip + 2].iter().all(|ins| !targets.contains(&ins.pc))
+            {
+                out.push(Instr { name: "optional_chain", n_pop: 1, n_push: 1, operand: None, ..w[0].clone() });
+                i = skip + 2;
+                continue;
+            }
+        }
+        out.push(instrs[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Whether `region` computes exactly one value from nothing below it on the
+/// stack, without jumps or statements of its own, so that it can be
+/// rendered inline as an operand.
+fn is_value_region(region: &[Instr]) -> bool {
+    const STATEMENTS: &[&str] =
+        &["put_", "set_loc", "set_arg", "set_var_ref", "define_func", "define_var", "define_class", "drop", "nip", "inc_loc", "dec_loc", "add_loc", "return", "throw", "ret", "close_loc"];
+    let mut depth = 0usize;
+    for ins in region {
+        if label_target(ins).is_some() || STATEMENTS.iter().any(|p| ins.name.starts_with(p)) {
+            return false;
+        }
+        let (pop, push) = stack_effect(ins);
+        let Some(d) = depth.checked_sub(pop) else { return false };
+        depth = d + push;
+    }
+    depth == 1
+}
+
+/// Collapses branches that only choose a value into synthetic operators, so
+/// the whole expression stays in one block:
+///
+/// ```text
+///   dup; if_false L; drop; B; L:                        a && b   (logical_and)
+///   dup; if_true L; drop; B; L:                         a || b   (logical_or)
+///   dup; is_undefined_or_null; if_false L; drop; B; L:  a ?? b   (nullish_coalesce)
+///   if_false L1; A; goto L2; L1: B; L2:                 c ? a : b (conditional)
+/// ```
+///
+/// `A` and `B` must be plain values (see `is_value_region`). Nested forms are
+/// folded innermost first, repeating until nothing changes.
+fn fold_conditional_values(instrs: &[Instr]) -> Vec<Instr> {
+    let mut instrs = instrs.to_vec();
+    loop {
+        let targets: Vec<usize> = instrs.iter().filter_map(label_target).collect();
+        let targeted = |ins: &Instr| targets.contains(&ins.pc);
+        let index_of = |pc: usize| instrs.iter().position(|ins| ins.pc == pc);
+        let synthetic = |name: &'static str, n_pop: u8, at: &Instr| Instr { name, n_pop, n_push: 1, operand: None, fmt: tables::OpFmt::NONE, ..at.clone() };
+        let mut out = Vec::with_capacity(instrs.len());
+        let mut i = 0;
+        while i < instrs.len() {
+            let rest = &instrs[i..];
+            // the index of the guard's jump, and the operator's name
+            let logical = match rest {
+                [dup, check, jump, drop, ..] if dup.name == "dup" && check.name == "is_undefined_or_null" && jump.name.starts_with("if_false") && drop.name == "drop" => {
+                    Some((2, "nullish_coalesce"))
+                }
+                [dup, jump, drop, ..] if dup.name == "dup" && jump.name.starts_with("if_false") && drop.name == "drop" => Some((1, "logical_and")),
+                [dup, jump, drop, ..] if dup.name == "dup" && jump.name.starts_with("if_true") && drop.name == "drop" => Some((1, "logical_or")),
+                _ => None,
+            };
+            if let Some((jump_at, name)) = logical
+                && let Some(end) = label_target(&rest[jump_at]).and_then(index_of)
+                && end > i + jump_at + 2
+                && is_value_region(&instrs[i + jump_at + 2..end])
+                && !instrs[i..end].iter().any(targeted)
+            {
+                out.extend_from_slice(&instrs[i + jump_at + 2..end]);
+                out.push(synthetic(name, 2, &instrs[end - 1]));
+                i = end;
+                continue;
+            }
+            if instrs[i].name.starts_with("if_")
+                && let Some(else_at) = label_target(&instrs[i]).and_then(index_of)
+                && else_at > i + 2
+                && let goto = &instrs[else_at - 1]
+                && goto.name.starts_with("goto")
+                && let Some(end) = label_target(goto).and_then(index_of)
+                && end > else_at
+                && is_value_region(&instrs[i + 1..else_at - 1])
+                && is_value_region(&instrs[else_at..end])
+                && !instrs[i + 1..end].iter().any(|ins| targeted(ins) && ins.pc != instrs[else_at].pc)
+                && targets.iter().filter(|&&t| t == instrs[else_at].pc).count() == 1
+            {
+                // if_true jumps to the branch taken when the condition holds
+                let name = if instrs[i].name.starts_with("if_true") { "conditional_inverted" } else { "conditional" };
+                out.extend_from_slice(&instrs[i + 1..else_at - 1]);
+                out.extend_from_slice(&instrs[else_at..end]);
+                out.push(synthetic(name, 3, &instrs[end - 1]));
+                i = end;
+                continue;
+            }
+            out.push(instrs[i].clone());
+            i += 1;
+        }
+        if out.len() == instrs.len() {
+            return out;
+        }
+        instrs = out;
+    }
+}
+
+// Engine-internal class locals, set up by the class definition itself.
+const CLASS_INTERNAL_VARS: &[&str] = &["<home_object>", "<brand>", "<class_fields_init>"];
+
+/// Removes the constructor prologue that runs field initializers
+/// (`get_var_ref_check <class_fields_init>; dup; if_false L; ...; L: drop`),
+/// which `class { x = 1 }` syntax already implies.
+fn fold_class_fields_init(b: &FunctionBytecode, instrs: &[Instr]) -> Vec<Instr> {
+    let is_fields_init = |ins: &Instr| match ins.operand {
+        Some(Operand::U16(idx)) if ins.name == "get_var_ref_check" => {
+            b.closure_vars.get(idx as usize).is_some_and(|cv| cv.name.to_string() == "<class_fields_init>")
+        }
+        _ => false,
+    };
+    let targets: Vec<usize> = instrs.iter().filter_map(label_target).collect();
+    let mut out = Vec::with_capacity(instrs.len());
+    let mut i = 0;
+    while i < instrs.len() {
+        if is_fields_init(&instrs[i])
+            && instrs.get(i + 1).is_some_and(|ins| ins.name == "dup")
+            && let Some(jump) = instrs.get(i + 2)
+            && jump.name.starts_with("if_false")
+            && let Some(end) = label_target(jump)
+            && let Some(k) = instrs.iter().position(|ins| ins.pc == end)
+            && k > i + 2
+            && instrs[k].name == "drop"
+            && targets.iter().filter(|&&t| t > instrs[i].pc && t <= end).all(|&t| t == end)
+            && targets.iter().filter(|&&t| t == end).count() == 1
+        {
+            i = k + 1;
+            continue;
+        }
+        out.push(instrs[i].clone());
         i += 1;
     }
     out
 }
 
+/// Drops stores to (and TDZ markers of) `CLASS_INTERNAL_VARS` locals.
+fn strip_class_internals(b: &FunctionBytecode, stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let internal = |name: &str| local_var(b, name).is_some_and(|v| CLASS_INTERNAL_VARS.contains(&v.name.to_string().as_str()));
+    let stmts = stmts
+        .into_iter()
+        .map(|s| match s {
+            Stmt::With { obj, init, body } => Stmt::With { obj, init, body: strip_class_internals(b, body) },
+            s => s,
+        })
+        .collect();
+    retain_stmts(stmts, |s| match s.unannotated() {
+        Stmt::Assign(name, _) => !internal(name),
+        Stmt::Expr(e) => !e.strip_suffix(" = undefined").is_some_and(internal),
+        _ => true,
+    })
+}
+
 fn pseudo_decompile_from_instrs(
     b: &FunctionBytecode,
     atoms: &AtomTable,
     instrs: &[Instr],
     func_name: &str,
-    optimize: bool,
-    deobfuscate: bool,
+    options: DecompileOptions,
 ) -> Result<String, DeqjsError> {
+    let deadline = Deadline::after(options.function_timeout);
+    let timed_out = || Ok(format!("// structuring timed out\n{}", disassemble_function_with_atoms_and_instrs(b, atoms, instrs, func_name, false)?));
+    let annotate = options.mode == DecompileMode::PseudoAnnotated;
+    let fields_init = is_class_fields_init(b);
+    let raw_instrs = instrs;
+    let instrs = fold_conditional_values(&fold_optional_chains(&fold_propkey_checks(&fold_class_fields_init(b, instrs))));
+    let instrs = if options.deobfuscate { unflatten_dispatchers(instrs) } else { instrs };
+    let instrs = if options.optimize { thread_jumps(&instrs) } else { instrs };
     let blocks = build_cfg(&instrs);
 
     let mut stmts: Vec<Stmt> = Vec::new();
+    let mut with_scopes: Vec<WithScope> = Vec::new();
+    // statement index -> (source line, disassembly lines); attached once
+    // decoding is done so the decoder's look-back at the last statement is
+    // unaffected
+    let mut annotations: HashMap<usize, (Option<u32>, Vec<String>)> = HashMap::new();
+    // the last source line put out
+    let mut last_line: Option<u32> = None;
+    // rendered stack value (`#x`, or the local / closure variable holding
+    // it) -> the private name it stands for; private_symbol creates one, a
+    // put_loc moves it into a local and the private-field opcodes look the
+    // name operand up here
+    let mut private_names: HashMap<String, String> = b
+        .closure_vars
+        .iter()
+        .enumerate()
+        .filter_map(|(i, cv)| match &cv.name {
+            AtomRepr::String(s) if s.starts_with('#') => Some((var_ref_name(b, i as u16), s.clone())),
+            _ => None,
+        })
+        .collect();
+    let mut next_raw = 0usize;
+    // loop bodies of for_of_next's back edge, which start with its value on the stack
+    let for_of_bodies: HashSet<usize> = instrs
+        .windows(2)
+        .filter(|w| w[0].name == "for_of_next" && w[1].name.starts_with("if_false"))
+        .filter_map(|w| label_target(&w[1]))
+        .collect();
+    // exception handlers, which start with the thrown value on the stack
+    let handlers: HashSet<usize> = instrs.iter().filter(|ins| ins.name == "catch").filter_map(label_target).collect();
+    // what a block ending in gosub leaves for the code the finally block returns to
+    let mut after_gosub: Vec<Expr> = Vec::new();
 
     for blk in blocks {
+        if deadline.check().is_err() {
+            return timed_out();
+        }
         stmts.push(Stmt::Label(blk.start_pc));
-        let mut stack: Vec<String> = Vec::new();
+        let mut stack: Vec<Expr> = std::mem::take(&mut after_gosub);
+        if for_of_bodies.contains(&blk.start_pc) {
+            stack.push(FOR_OF_VALUE.into());
+        }
+        if handlers.contains(&blk.start_pc) {
+            stack.push(EXCEPTION.into());
+        }
 
-        for ins in &blk.instrs {
+        for (ii, ins) in blk.instrs.iter().enumerate() {
+            let emitted = stmts.len();
             match ins.name {
                 "push_i8" => {
                     if let Some(Operand::I8(v)) = ins.operand {
-                        stack.push(v.to_string());
+                        stack.push(int_literal(v as i64, ""));
                     }
                 }
                 "push_i16" => {
                     if let Some(Operand::I16(v)) = ins.operand {
-                        stack.push(v.to_string());
+                        stack.push(int_literal(v as i64, ""));
                     }
                 }
                 "push_i32" => {
                     if let Some(Operand::I32(v)) = ins.operand {
-                        stack.push(v.to_string());
+                        stack.push(int_literal(v as i64, ""));
+                    }
+                }
+                "push_bigint_i32" => {
+                    if let Some(Operand::I32(v)) = ins.operand {
+                        stack.push(int_literal(v as i64, "n"));
                     }
                 }
                 "push_u8" => {
                     if let Some(Operand::U8(v)) = ins.operand {
-                        stack.push(v.to_string());
+                        stack.push(v.to_string().into());
                     }
                 }
                 "push_u16" => {
                     if let Some(Operand::U16(v)) = ins.operand {
-                        stack.push(v.to_string());
+                        stack.push(v.to_string().into());
                     }
                 }
                 "push_u32" => {
                     if let Some(Operand::U32(v)) = ins.operand {
-                        stack.push(v.to_string());
+                        stack.push(v.to_string().into());
                     }
                 }
                 n if n == "push_minus1" || (n.starts_with("push_") && n.chars().skip(5).all(|c| c.is_ascii_digit())) => {
@@ -2135,7 +5346,7 @@ fn pseudo_decompile_from_instrs(
                         let idx_str = &n[5..];
                         idx_str.parse::<i32>().unwrap()
                     };
-                    stack.push(n.to_string());
+                    stack.push(int_literal(n as i64, ""));
                 }
                 "push_true" => stack.push("true".into()),
                 "push_false" => stack.push("false".into()),
@@ -2150,61 +5361,94 @@ fn pseudo_decompile_from_instrs(
                         } else {
                             format!("<const:{}>", idx)
                         };
-                        stack.push(expr);
+                        stack.push(expr.into());
                     }
                 }
                 "push_atom_value" => {
                     if let Some(Operand::Atom(idx)) = ins.operand {
                         let a = atoms.resolve_idx(idx)?;
                         match a {
-                            AtomRepr::String(s) => stack.push(format!("\"{}\"", s)),
-                            _ => stack.push(a.to_string()),
+                            AtomRepr::String(s) => stack.push(js_string_literal(&s).into()),
+                            _ => stack.push(a.to_string().into()),
                         }
                     }
                 }
                 "fclosure" | "fclosure8" => {
                     if let Some(Operand::Const(idx)) = ins.operand {
-                        stack.push(closure_name(deobfuscate, b, idx as u16));
+                        stack.push(Expr { closure: Some(idx as u16), ..closure_name(options.deobfuscate, b, idx as u16).into() });
+                    }
+                }
+                // only guards the define_var/define_func that follows against
+                // clashing global lexical declarations
+                "check_define_var" => {}
+                // a global declared by the script: DEFINE_GLOBAL_LEX_VAR (0x80)
+                // marks `let`/`const`, and a `let` is also JS_PROP_WRITABLE
+                // (0x02). The value is stored by a later put_var_init, and a
+                // `const` can't be declared without one.
+                "define_var" => {
+                    if let Some(Operand::AtomU8(idx, flags)) = ins.operand {
+                        let name = atoms.resolve_idx(idx)?.to_string();
+                        stmts.push(Stmt::Expr(if flags & 0x80 == 0 {
+                            format!("var {name}")
+                        } else if flags & 0x02 != 0 {
+                            format!("let {name}")
+                        } else {
+                            format!("let {name} /* const */")
+                        }));
                     }
                 }
+                "define_func" => {
+                    let func = stack.pop().unwrap_or("<func>".into());
+                    let name = match ins.operand {
+                        Some(Operand::AtomU8(idx, _)) => atoms.resolve_idx(idx)?.to_string(),
+                        _ => "<func>".to_string(),
+                    };
+                    let closure = cpool_function(b, &func);
+                    let params = closure.map(|f| param_list(f, atoms)).unwrap_or_default();
+                    let keyword = closure.map(function_keyword).unwrap_or_else(|| "function".into());
+                    stmts.push(Stmt::Decl(format!("{keyword} {name}({params}) {{ /* {func} */ }}")));
+                }
                 "get_loc0_loc1" => {
-                    stack.push(loc_name(b, 0));
-                    stack.push(loc_name(b, 1));
+                    stack.push(loc_name(b, 0).into());
+                    stack.push(loc_name(b, 1).into());
                 }
                 "get_arg" => {
                     if let Some(Operand::U16(idx)) = ins.operand {
-                        stack.push(arg_name(b, idx));
+                        stack.push(arg_name(b, idx).into());
                     }
                 }
-                "get_loc" => {
-                    if let Some(Operand::U16(idx)) = ins.operand {
-                        stack.push(loc_name(b, idx));
-                    }
+                "get_loc" | "get_loc8" => {
+                    let idx = match ins.operand {
+                        Some(Operand::U16(v)) => v,
+                        Some(Operand::U8(v)) => v as u16,
+                        _ => 0,
+                    };
+                    stack.push(loc_name(b, idx).into());
                 }
                 "get_loc_check" => {
                     if let Some(Operand::U16(idx)) = ins.operand {
-                        stack.push(loc_name(b, idx));
+                        stack.push(loc_name(b, idx).into());
                     }
                 }
                 n if n.starts_with("get_arg") && n != "get_arg" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[7..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
-                        stack.push(arg_name(b, idx));
+                        stack.push(arg_name(b, idx).into());
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
-                n if n.starts_with("get_loc") && n != "get_loc" && n != "get_loc0_loc1" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
+                n if n.starts_with("get_loc") && n != "get_loc" && n != "get_loc8" && n != "get_loc0_loc1" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[7..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
-                        stack.push(loc_name(b, idx));
+                        stack.push(loc_name(b, idx).into());
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
                 "get_var_ref" | "get_var_ref_check" => {
                     if let Some(Operand::U16(idx)) = ins.operand {
-                        stack.push(var_ref_name(b, idx));
+                        stack.push(var_ref_name(b, idx).into());
                     } else {
                         stack.push("<get_var_ref>".into());
                     }
@@ -2212,19 +5456,19 @@ fn pseudo_decompile_from_instrs(
                 n if n.starts_with("get_var_ref") && n != "get_var_ref" && n != "get_var_ref_check" && n.chars().skip(11).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[11..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
-                        stack.push(var_ref_name(b, idx));
+                        stack.push(var_ref_name(b, idx).into());
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
                 "set_var_ref" | "set_var_ref_check" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
                     if let Some(Operand::U16(idx)) = ins.operand {
                         let name = var_ref_name(b, idx);
-                        stmts.push(Stmt::Expr(format!("{name} = {rhs}")));
-                        stack.push(rhs);
+                        stmts.push(Stmt::Expr(format!("{name} = {}", rhs.text)));
+                        stack.push(assigned_value(rhs, name));
                     } else {
-                        stmts.push(Stmt::Expr(format!("<set_var_ref> = {rhs}")));
+                        stmts.push(Stmt::Expr(format!("<set_var_ref> = {}", rhs.text)));
                         stack.push(rhs);
                     }
                 }
@@ -2233,19 +5477,19 @@ fn pseudo_decompile_from_instrs(
                     if let Ok(idx) = idx_str.parse::<u16>() {
                         let rhs = stack.pop().unwrap_or("<rhs>".into());
                         let name = var_ref_name(b, idx);
-                        stmts.push(Stmt::Expr(format!("{name} = {rhs}")));
-                        stack.push(rhs);
+                        stmts.push(Stmt::Expr(format!("{name} = {}", rhs.text)));
+                        stack.push(assigned_value(rhs, name));
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
                 "put_var_ref" | "put_var_ref_check" | "put_var_ref_check_init" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
                     if let Some(Operand::U16(idx)) = ins.operand {
                         let name = var_ref_name(b, idx);
-                        stmts.push(Stmt::Expr(format!("{name} = {rhs}")));
+                        stmts.push(Stmt::Expr(format!("{name} = {}", rhs.text)));
                     } else {
-                        stmts.push(Stmt::Expr(format!("<put_var_ref> = {rhs}")));
+                        stmts.push(Stmt::Expr(format!("<put_var_ref> = {}", rhs.text)));
                     }
                 }
                 n if n.starts_with("put_var_ref") && n != "put_var_ref" && n != "put_var_ref_check" && n != "put_var_ref_check_init" && n.chars().skip(11).all(|c| c.is_ascii_digit()) => {
@@ -2253,13 +5497,20 @@ fn pseudo_decompile_from_instrs(
                     if let Ok(idx) = idx_str.parse::<u16>() {
                         let rhs = stack.pop().unwrap_or("<rhs>".into());
                         let name = var_ref_name(b, idx);
-                        stmts.push(Stmt::Expr(format!("{name} = {rhs}")));
+                        stmts.push(Stmt::Expr(format!("{name} = {}", rhs.text)));
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
                 "drop" => {
-                    let _ = stack.pop();
+                    if let Some(v) = stack.pop()
+                        && options.emit_dropped_exprs
+                        && !v.consumed
+                        && has_side_effects(&v.text)
+                        && !last_stmt_evaluates(&stmts, &v)
+                    {
+                        stmts.push(Stmt::Expr(v.text));
+                    }
                 }
                 "dup" => {
                     if let Some(v) = stack.last().cloned() {
@@ -2278,50 +5529,177 @@ fn pseudo_decompile_from_instrs(
                         stack.remove(n - 2);
                     }
                 }
-                "add" | "sub" | "mul" | "div" | "mod" | "and" | "or" | "xor" | "shl" | "sar" | "shr" | "eq" | "neq" | "strict_eq" | "strict_neq" | "lt" | "lte" | "gt" | "gte" => {
+                n if let Some((depth, order)) = stack_shuffle(n) => {
+                    // missing entries (e.g. values live in another block) become placeholders
+                    while stack.len() < depth {
+                        stack.insert(0, "<a>".into());
+                    }
+                    let top = stack.split_off(stack.len() - depth);
+                    stack.extend(order.iter().map(|&i| top[i].clone()));
+                }
+                "add" | "sub" | "mul" | "div" | "mod" | "pow" | "and" | "or" | "xor" | "shl" | "sar" | "shr" | "eq" | "neq" | "strict_eq" | "strict_neq" | "lt" | "lte" | "gt" | "gte" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
                     let lhs = stack.pop().unwrap_or("<lhs>".into());
-                    let op = match ins.name {
-                        "add" => "+",
-                        "sub" => "-",
-                        "mul" => "*",
-                        "div" => "/",
-                        "mod" => "%",
-                        "and" => "&",
-                        "or" => "|",
-                        "xor" => "^",
-                        "shl" => "<<",
-                        "sar" => ">>",
-                        "shr" => ">>>",
-                        "eq" => "==",
-                        "neq" => "!=",
-                        "strict_eq" => "===",
-                        "strict_neq" => "!==",
-                        "lt" => "<",
-                        "lte" => "<=",
-                        "gt" => ">",
-                        _ => ">=",
+                    let (op, prec) = match ins.name {
+                        "add" => ("+", PREC_ADDITIVE),
+                        "sub" => ("-", PREC_ADDITIVE),
+                        "mul" => ("*", PREC_MULTIPLICATIVE),
+                        "div" => ("/", PREC_MULTIPLICATIVE),
+                        "mod" => ("%", PREC_MULTIPLICATIVE),
+                        "pow" => ("**", PREC_EXPONENT),
+                        "and" => ("&", PREC_BIT_AND),
+                        "or" => ("|", PREC_BIT_OR),
+                        "xor" => ("^", PREC_BIT_XOR),
+                        "shl" => ("<<", PREC_SHIFT),
+                        "sar" => (">>", PREC_SHIFT),
+                        "shr" => (">>>", PREC_SHIFT),
+                        "eq" => ("==", PREC_EQUALITY),
+                        "neq" => ("!=", PREC_EQUALITY),
+                        "strict_eq" => ("===", PREC_EQUALITY),
+                        "strict_neq" => ("!==", PREC_EQUALITY),
+                        "lt" => ("<", PREC_RELATIONAL),
+                        "lte" => ("<=", PREC_RELATIONAL),
+                        "gt" => (">", PREC_RELATIONAL),
+                        _ => (">=", PREC_RELATIONAL),
+                    };
+                    let folded = if options.optimize {
+                        fold_binary(ins.name, &lhs, &rhs).or_else(|| concat_template(ins.name, &lhs, &rhs))
+                    } else {
+                        None
                     };
-                    stack.push(format!("({lhs} {op} {rhs})"));
+                    stack.push(folded.unwrap_or_else(|| binary_op(op, prec, &lhs, &rhs)));
                 }
                 "post_inc" => {
                     let value = stack.pop().unwrap_or("<value>".into());
                     stack.push(value.clone());
-                    stack.push(format!("{} + 1", value));
+                    stack.push(binary_op("+", PREC_ADDITIVE, &value, &"1".into()));
                 }
                 "is_undefined" => {
                     let val = stack.pop().unwrap_or("<val>".into());
-                    stack.push(format!("{} === undefined", val));
+                    stack.push(binary_op("===", PREC_EQUALITY, &val, &"undefined".into()));
                 }
                 "to_object" => {
                     let val = stack.pop().unwrap_or("<val>".into());
-                    stack.push(format!("Object({})", val));
+                    stack.push(format!("Object({})", val.at(PREC_ASSIGN)).into());
+                }
+                // key conversion (and the nullish base check of to_propkey2)
+                // is implicit in the rendered `obj[key]`
+                "to_propkey" | "to_propkey2" => {}
+                // class plumbing: the home object link and private brand are
+                // implied by the reconstructed class syntax
+                "set_home_object" => {}
+                // a private method call checks `this` against the method's
+                // brand between pushing the two, so the callee is `obj.#m`
+                "check_brand" => {
+                    if let [.., obj, func] = stack.as_mut_slice()
+                        && let Some(method) = private_names.get(&func.text)
+                    {
+                        *func = format!("{obj}{}", obj.access(&format!(".{method}"))).into();
+                    }
+                }
+                // the home object's prototype, for `super.x`
+                "get_super" => {
+                    stack.pop();
+                    stack.push("super".into());
                 }
-                "to_propkey2" => {
-                    let val2 = stack.pop().unwrap_or("<val2>".into());
-                    let val1 = stack.pop().unwrap_or("<val1>".into());
-                    stack.push(format!("String({})", val1));
-                    stack.push(format!("String({})", val2));
+                // `this`, the home object's prototype and the key: the
+                // receiver stays implicit in `super.x`
+                "get_super_value" => {
+                    let prop = stack.pop().unwrap_or("<prop>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    stack.pop();
+                    stack.push(format!("{obj}{}", element_suffix(&prop)).into());
+                }
+                "put_super_value" => {
+                    let rhs = stack.pop().unwrap_or("<rhs>".into());
+                    let prop = stack.pop().unwrap_or("<prop>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    stack.pop();
+                    let target = format!("{obj}{}", element_suffix(&prop));
+                    reuse_stored(&mut stack, &target, &rhs);
+                    stmts.push(Stmt::Expr(format!("{target} = {}", rhs.text)));
+                }
+                "add_brand" => {
+                    stack.pop();
+                    stack.pop();
+                }
+                "private_symbol" => {
+                    if let Some(Operand::Atom(idx)) = ins.operand {
+                        let name = atoms.resolve_idx(idx)?.to_string();
+                        private_names.insert(name.clone(), name.clone());
+                        stack.push(name.into());
+                    }
+                }
+                // the private name lives on in the local; `loc = #x` isn't JS
+                _ if put_loc_slot(ins).is_some() && stack.last().is_some_and(|v| private_names.contains_key(&v.text)) => {
+                    if let (Some(v), Some(slot)) = (stack.pop(), put_loc_slot(ins)) {
+                        let name = private_names[&v.text].clone();
+                        private_names.insert(loc_name(b, slot), name);
+                    }
+                }
+                "get_private_field" => {
+                    let name = stack.pop().unwrap_or("<name>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    let suffix = match private_names.get(&name.text) {
+                        Some(field) => format!(".{field}"),
+                        None => format!("[{name}]"),
+                    };
+                    stack.push(format!("{obj}{}", obj.access(&suffix)).into());
+                }
+                "put_private_field" => {
+                    let name = stack.pop().unwrap_or("<name>".into());
+                    let rhs = stack.pop().unwrap_or("<rhs>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    let field = private_names.get(&name.text).map_or_else(|| format!("[{name}]"), |field| format!(".{field}"));
+                    let target = format!("{obj}{field}");
+                    reuse_stored(&mut stack, &target, &rhs);
+                    stmts.push(Stmt::Expr(format!("{target} = {}", rhs.text)));
+                }
+                // a private field's initialization, which like define_field
+                // leaves the object for the next one
+                "define_private_field" => {
+                    let value = stack.pop().unwrap_or("<value>".into());
+                    let name = stack.pop().unwrap_or("<name>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    stmts.push(Stmt::Expr(match private_names.get(&name.text) {
+                        // a field declaration in the class body
+                        Some(field) if fields_init => format!("{field} = {}", value.text),
+                        Some(field) => format!("{obj}.{field} = {}", value.text),
+                        None => format!("{obj}[{name}] = {}", value.text),
+                    }));
+                    stack.push(obj);
+                }
+                "private_in" => {
+                    let name = stack.pop().unwrap_or("<name>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    let field: Expr = private_names.get(&name.text).cloned().map_or(name, Expr::from);
+                    stack.push(binary_op("in", PREC_RELATIONAL, &field, &obj));
+                }
+                "logical_and" | "logical_or" | "nullish_coalesce" => {
+                    let rhs = stack.pop().unwrap_or("<rhs>".into());
+                    let lhs = stack.pop().unwrap_or("<lhs>".into());
+                    // `&&` and `||` are associative, so a right-nested chain needs no parentheses
+                    stack.push(match ins.name {
+                        "logical_and" => Expr::new(format!("{} && {}", lhs.at(PREC_LOGICAL_AND), rhs.at(PREC_LOGICAL_AND)), PREC_LOGICAL_AND),
+                        "logical_or" => Expr::new(format!("{} || {}", lhs.at(PREC_LOGICAL_OR), rhs.at(PREC_LOGICAL_OR)), PREC_LOGICAL_OR),
+                        // `??` doesn't mix with unparenthesized `&&` / `||`
+                        _ => Expr::new(format!("{} ?? {}", lhs.at(PREC_BIT_OR), rhs.at(PREC_BIT_OR)), PREC_NULLISH),
+                    });
+                }
+                "conditional" | "conditional_inverted" => {
+                    let second = stack.pop().unwrap_or("<value>".into());
+                    let first = stack.pop().unwrap_or("<value>".into());
+                    let cond = stack.pop().unwrap_or("<cond>".into());
+                    let (then, other) = if ins.name == "conditional" { (first, second) } else { (second, first) };
+                    stack.push(Expr::new(
+                        format!("{} ? {} : {}", cond.at(PREC_CONDITIONAL + 1), then.at(PREC_ASSIGN), other.at(PREC_ASSIGN)),
+                        PREC_CONDITIONAL,
+                    ));
+                }
+                "optional_chain" => {
+                    if let Some(top) = stack.last_mut() {
+                        top.optional = true;
+                    }
                 }
                 "inc_loc" => {
                     if let Some(Operand::U8(idx)) = ins.operand {
@@ -2331,22 +5709,38 @@ fn pseudo_decompile_from_instrs(
                 "regexp" => {
                     let flags = stack.pop().unwrap_or("<flags>".into());
                     let pattern = stack.pop().unwrap_or("<pattern>".into());
-                    if flags.starts_with('"') && flags.ends_with('"') && flags.len() < 20 && !flags.contains("\\u") {
-                        stack.push(format!("new RegExp({}, {})", pattern, flags));
+                    // a literal pushes its source and compiled bytecode from the constant pool
+                    let cpool_string = |at: Option<usize>| match at.and_then(|i| blk.instrs.get(i))?.operand {
+                        Some(Operand::Const(idx)) => match b.cpool.get(idx as usize) {
+                            Some(Value::String(s)) => Some(s),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let (Some(source), Some(bytecode)) = (cpool_string(ii.checked_sub(2)), cpool_string(ii.checked_sub(1))) {
+                        stack.push(format!("/{source}/{}", regexp_flags(&latin1_bytes(bytecode))).into());
+                    } else if flags.text.starts_with('"') && flags.text.ends_with('"') && flags.text.len() < 20 && !flags.text.contains("\\u") {
+                        stack.push(format!("new RegExp({}, {})", pattern.at(PREC_ASSIGN), flags.text).into());
                     } else {
-                        stack.push(format!("new RegExp({})", pattern));
+                        stack.push(format!("new RegExp({})", pattern.at(PREC_ASSIGN)).into());
                     }
                 }
                 "in" => {
                     let prop = stack.pop().unwrap_or("<prop>".into());
                     let obj = stack.pop().unwrap_or("<obj>".into());
-                    stack.push(format!("({} in {})", prop, obj));
+                    stack.push(binary_op("in", PREC_RELATIONAL, &prop, &obj));
                 }
                 "object" => stack.push("{}".into()),
-                // TODO: find corresponding object kinds
+                // OP_SPECIAL_OBJECT_*; the legacy format shares kinds 0-6
                 "special_object" => {
                     if let Some(Operand::U8(kind)) = ins.operand {
-                        stack.push(format!("<special_object_{}>", kind));
+                        match special_object_name(kind) {
+                            // the running function, as seen by a named function
+                            // expression's own name; an anonymous one has none
+                            _ if kind == OP_SPECIAL_OBJECT_THIS_FUNC && !matches!(b.func_name, AtomRepr::Null) => stack.push(func_name.into()),
+                            Some(name) => stack.push(name.into()),
+                            None => stack.push(format!("<special_object:{}>", kind).into()),
+                        }
                     } else {
                         stack.push("<special_object>".into());
                     }
@@ -2354,11 +5748,15 @@ fn pseudo_decompile_from_instrs(
                 "instanceof" => {
                     let constructor = stack.pop().unwrap_or("<constructor>".into());
                     let obj = stack.pop().unwrap_or("<obj>".into());
-                    stack.push(format!("({} instanceof {})", obj, constructor));
+                    stack.push(binary_op("instanceof", PREC_RELATIONAL, &obj, &constructor));
                 }
                 "typeof" => {
                     let value = stack.pop().unwrap_or("<value>".into());
-                    stack.push(format!("typeof {}", value));
+                    stack.push(Expr::new(format!("typeof {}", value.at(PREC_UNARY)), PREC_UNARY));
+                }
+                "import" => {
+                    let specifier = stack.pop().unwrap_or("<specifier>".into());
+                    stack.push(format!("import({})", join_exprs(&[specifier])).into());
                 }
                 "define_field" => {
                     let value = stack.pop().unwrap_or("<value>".into());
@@ -2371,13 +5769,65 @@ fn pseudo_decompile_from_instrs(
                                 "<invalid_atom>".to_string()
                             }
                         };
-                        stmts.push(Stmt::Expr(format!("{obj}.{} = {value}", prop)));
-                        stack.push(obj);
+                        let index = prop.parse::<usize>().ok();
+                        let parts = array_literal_parts(&obj.text).filter(|parts| !parts.iter().any(|p| p.starts_with("...")));
+                        if is_object_literal(&obj.text) {
+                            stack.push(object_literal_push(&obj.text, &format!("{}: {}", prop_key(&prop), value.at(PREC_ASSIGN))).into());
+                        } else if let (Some(index), Some(mut parts)) = (index, parts)
+                            && index >= parts.len()
+                        {
+                            // an element after holes: `[1, , 3]`
+                            let value = value.at(PREC_ASSIGN);
+                            parts.resize(index, "");
+                            parts.push(&value);
+                            stack.push(array_literal(&parts).into());
+                        } else if fields_init {
+                            // a field declaration; put_field stays an assignment
+                            stmts.push(Stmt::Expr(format!("{} = {}", prop_key(&prop), value.text)));
+                            stack.push(obj);
+                        } else {
+                            stmts.push(Stmt::Expr(format!("{obj}{} = {}", member_suffix(&prop), value.text)));
+                            stack.push(obj);
+                        }
                     } else {
                         stmts.push(Stmt::Expr(format!("<define_field> {obj} {value}")));
                         stack.push("<define_field>".into());
                     }
                 }
+                "define_array_el" if fields_init => {
+                    let value = stack.pop().unwrap_or("<value>".into());
+                    let key = stack.pop().unwrap_or("<key>".into());
+                    stmts.push(Stmt::Expr(format!("[{}] = {}", key.at(PREC_ASSIGN), value.text)));
+                    stack.push("<define_array_el>".into());
+                    stack.push(key);
+                }
+                "copy_data_properties" => {
+                    // mask packs the stack offsets of target (2 bits), source (3 bits) and
+                    // the excluded-keys object (3 bits); the stack itself is left unchanged.
+                    if let Some(Operand::U8(mask)) = ins.operand {
+                        let n = stack.len();
+                        let slot = |off: u8| n.checked_sub(1 + off as usize);
+                        let target = slot(mask & 3);
+                        let source_slot = slot((mask >> 2) & 7);
+                        let source = source_slot.map(|i| stack[i].at(PREC_ASSIGN)).unwrap_or("<source>".into());
+                        let exclude = slot((mask >> 5) & 7).map(|i| stack[i].text.clone()).unwrap_or("<exclude>".into());
+                        match target {
+                            Some(t) if exclude == "null" && is_object_literal(&stack[t].text) => {
+                                stack[t] = object_literal_push(&stack[t].text, &format!("...{source}")).into();
+                            }
+                            Some(t) if exclude == "null" => {
+                                stmts.push(Stmt::Expr(format!("Object.assign({}, {source})", stack[t])));
+                            }
+                            t => {
+                                let target = t.map(|i| stack[i].clone()).unwrap_or("<target>".into());
+                                stmts.push(Stmt::Expr(format!("Object.assign({target}, {source}) /* excluding {exclude} */")));
+                            }
+                        }
+                        if let Some(i) = source_slot {
+                            stack[i].consumed = true;
+                        }
+                    }
+                }
                 "set_name" => {
                     let obj = stack.pop().unwrap_or("<obj>".into());
                     if let Some(Operand::Atom(idx)) = ins.operand {
@@ -2388,7 +5838,7 @@ fn pseudo_decompile_from_instrs(
                                 "<invalid_atom>".to_string()
                             }
                         };
-                        stmts.push(Stmt::Expr(format!("{}.name = \"{}\"", obj, name)));
+                        stmts.push(Stmt::Expr(format!("{}.name = {}", obj, js_string_literal(&name))));
                         stack.push(obj);
                     } else {
                         stack.push("<set_name>".into());
@@ -2397,7 +5847,7 @@ fn pseudo_decompile_from_instrs(
                 "define_class" => {
                     let parent_ctor = stack.pop().unwrap_or("<parent_ctor>".into());
                     if let Some(Operand::AtomU8(idx, _flags)) = ins.operand {
-                        let name: String = match atoms.resolve_idx(idx as u32) {
+                        let name: String = match atoms.resolve_idx(idx) {
                             Ok(n) => n.to_string(),
                             Err(e) => {
                                 stmts.push(Stmt::Expr(format!("// Atom resolution error: {}", e)));
@@ -2414,20 +5864,36 @@ fn pseudo_decompile_from_instrs(
                 "define_method" => {
                     let method = stack.pop().unwrap_or("<method>".into());
                     let obj = stack.pop().unwrap_or("<obj>".into());
-                    if let Some(Operand::AtomU8(idx, _flags)) = ins.operand {
-                        let name: String = match atoms.resolve_idx(idx as u32) {
+                    if let Some(Operand::AtomU8(idx, flags)) = ins.operand {
+                        let name: String = match atoms.resolve_idx(idx) {
                             Ok(n) => n.to_string(),
                             Err(e) => {
                                 stmts.push(Stmt::Expr(format!("// Atom resolution error: {}", e)));
                                 "<invalid_atom>".to_string()
                             }
                         };
-                        stmts.push(Stmt::Expr(format!("{}.{} = {}", obj, name, method)));
-                        stack.push(obj);
+                        let closure = cpool_function(b, &method);
+                        let prefix = closure.map(func_kind_prefix).unwrap_or_default();
+                        let params = closure.map(|f| param_list(f, atoms));
+                        stack.push(define_method_on(&mut stmts, obj, &name, &js_string_literal(&name), &method.text, flags, params, prefix));
                     } else {
                         stack.push("<define_method>".into());
                     }
                 }
+                "define_method_computed" => {
+                    let method = stack.pop().unwrap_or("<method>".into());
+                    let key = stack.pop().unwrap_or("<key>".into());
+                    let obj = stack.pop().unwrap_or("<obj>".into());
+                    if let Some(Operand::U8(flags)) = ins.operand {
+                        let closure = cpool_function(b, &method);
+                        let prefix = closure.map(func_kind_prefix).unwrap_or_default();
+                        let name = format!("[{}]", key.at(PREC_ASSIGN));
+                        let params = closure.map(|f| param_list(f, atoms));
+                        stack.push(define_method_on(&mut stmts, obj, &name, &key.text, &method.text, flags, params, prefix));
+                    } else {
+                        stack.push("<define_method_computed>".into());
+                    }
+                }
                 "close_loc" => {
                     if let Some(Operand::U16(idx)) = ins.operand {
                         stmts.push(Stmt::Expr(format!("close {}", loc_name(b, idx))));
@@ -2439,9 +5905,132 @@ fn pseudo_decompile_from_instrs(
                 "not" | "lnot" => {
                     let v = stack.pop().unwrap_or("<v>".into());
                     let op = if ins.name == "not" { "~" } else { "!" };
-                    stack.push(format!("({op}{v})"));
+                    stack.push(Expr::new(format!("{op}{}", v.at(PREC_UNARY)), PREC_UNARY));
+                }
+                "neg" | "plus" => {
+                    let v = stack.pop().unwrap_or("<v>".into());
+                    let op = if ins.name == "neg" { "-" } else { "+" };
+                    let operand = v.at(PREC_UNARY);
+                    // `-(-a)` and `+(+a)`, not the `--a`/`++a` updates
+                    let operand = if operand.starts_with(op) { format!("({operand})") } else { operand };
+                    stack.push(Expr::new(format!("{op}{operand}"), PREC_UNARY));
+                }
+                "array_from" => {
+                    if let Some(Operand::NPop(argc)) = ins.operand {
+                        let at = stack.len().saturating_sub(argc as usize);
+                        let elems = stack.split_off(at);
+                        stack.push(format!("[{}]", join_exprs(&elems)).into());
+                    }
+                }
+                "append" => {
+                    // array pos enumobj -> array pos; past a spread the
+                    // position is only known at run time
+                    let obj = stack.pop().unwrap_or("<iterable>".into());
+                    stack.pop();
+                    let arr = stack.pop().unwrap_or("<array>".into());
+                    let spread = format!("...{}", obj.at(PREC_ASSIGN));
+                    match array_literal_push(&arr.text, Some(&spread)) {
+                        Some(lit) => stack.push(lit.into()),
+                        // the array grows in place
+                        None if !has_side_effects(&arr.text) => {
+                            stmts.push(Stmt::Expr(format!("{arr}.push({spread})")));
+                            stack.push(arr);
+                        }
+                        None => stack.push("<append>".into()),
+                    }
+                    stack.push(ARRAY_END.into());
                 }
-                "call" | "tail_call" | "call_method" | "tail_call_method" | "call_constructor" | "array_from" => {
+                // array pos value -> array pos
+                "define_array_el" => {
+                    let value = stack.pop().unwrap_or("<value>".into());
+                    let pos = stack.pop().unwrap_or("<pos>".into());
+                    let arr = stack.pop().unwrap_or("<array>".into());
+                    match array_literal_push(&arr.text, Some(&value.at(PREC_ASSIGN))) {
+                        Some(lit) if pos.text == ARRAY_END => {
+                            stack.push(lit.into());
+                            stack.push(ARRAY_ELEM.into());
+                        }
+                        // after an `append` onto an array that isn't a literal
+                        _ if pos.text == ARRAY_END && !has_side_effects(&arr.text) => {
+                            stmts.push(Stmt::Expr(format!("{arr}.push({})", value.at(PREC_ASSIGN))));
+                            stack.push(arr);
+                            stack.push(ARRAY_ELEM.into());
+                        }
+                        // a computed key in an object literal, `{ [k]: v }`
+                        _ if arr.text.starts_with('{') => {
+                            let entry = format!("[{}]: {}", pos.at(PREC_ASSIGN), value.at(PREC_ASSIGN));
+                            stack.push(object_literal_push(&arr.text, &entry).into());
+                            stack.push(pos);
+                        }
+                        _ => {
+                            stmts.push(Stmt::Expr(format!("{arr}[{}] = {}", pos.text, value.text)));
+                            stack.push(arr);
+                            stack.push(pos);
+                        }
+                    }
+                }
+                "inc" => {
+                    let v = stack.pop().unwrap_or("<v>".into());
+                    match v.text.as_str() {
+                        ARRAY_ELEM => stack.push(ARRAY_END.into()),
+                        // stepping over a slot nothing was defined in: a hole
+                        ARRAY_END if stack.last().and_then(|arr| array_literal_push(&arr.text, None)).is_some() => {
+                            let arr = stack.pop().unwrap_or("<array>".into());
+                            stack.push(array_literal_push(&arr.text, None).unwrap_or(arr.text).into());
+                            stack.push(v);
+                        }
+                        _ => stack.push(binary_op("+", PREC_ADDITIVE, &v, &"1".into())),
+                    }
+                }
+                "apply" => {
+                    // func this_or_new_target array; flag 1 means constructor
+                    // call, which is `super(...)` unless the compiler's perm3
+                    // reordering for `new f(...)` precedes it
+                    let args = stack.pop().unwrap_or("<args>".into());
+                    let this = stack.pop().unwrap_or("<this>".into());
+                    let func = stack.pop().unwrap_or("<func>".into());
+                    let ctor = matches!(ins.operand, Some(Operand::U16(v)) if v & 1 != 0);
+                    let after_perm3 = ii > 0 && blk.instrs[ii - 1].name == "perm3";
+                    let call = if ctor && !after_perm3 {
+                        format!("super({})", spread_args(&args))
+                    } else if ctor {
+                        format!("new {}({})", func.at(PREC_POSTFIX), spread_args(&args))
+                    } else if this.text == "undefined"
+                        || func.text.starts_with(&format!("{}.", this.text))
+                        || func.text.starts_with(&format!("{}[", this.text))
+                    {
+                        format!("{func}({})", spread_args(&args))
+                    } else {
+                        format!("{func}.apply({}, {})", this.at(PREC_ASSIGN), args.at(PREC_ASSIGN))
+                    };
+                    stack.push(call.into());
+                }
+                "apply_eval" => {
+                    let args = stack.pop().unwrap_or("<args>".into());
+                    let func = stack.pop().unwrap_or("<func>".into());
+                    stack.push(format!("{func}({})", spread_args(&args)).into());
+                }
+                // ctor new_target args... -> new Ctor(args)
+                "call_constructor" => {
+                    if let Some(Operand::NPop(argc)) = ins.operand {
+                        let mut args = Vec::with_capacity(argc as usize);
+                        for _ in 0..argc {
+                            args.push(stack.pop().unwrap_or("<arg>".into()));
+                        }
+                        args.reverse();
+                        stack.pop();
+                        let ctor = stack.pop().unwrap_or("<ctor>".into());
+                        if ctor.text == "super" {
+                            // a derived constructor's `super(...)` call
+                            stack.push(format!("super({})", join_exprs(&args)).into());
+                        } else {
+                            // a call inside the callee would take the `new`'s arguments
+                            let ctor = if ctor.text.contains('(') { format!("({})", ctor.text) } else { ctor.at(PREC_PRIMARY) };
+                            stack.push(format!("new {ctor}({})", join_exprs(&args)).into());
+                        }
+                    }
+                }
+                "call" | "tail_call" | "call_method" | "tail_call_method" => {
                     if let Some(Operand::NPop(argc)) = ins.operand {
                         let mut args = Vec::with_capacity(argc as usize);
                         for _ in 0..argc {
@@ -2449,7 +6038,15 @@ fn pseudo_decompile_from_instrs(
                         }
                         args.reverse();
                         let func = stack.pop().unwrap_or("<func>".into());
-                        stack.push(format!("{func}({})", args.join(", ")));
+                        if ins.name == "call_method" || ins.name == "tail_call_method" {
+                            stack.pop();
+                        }
+                        let call = format!("{func}{}{})", func.access("("), join_exprs(&args));
+                        if ins.name.starts_with("tail_") {
+                            stmts.push(Stmt::Return(Some(call)));
+                        } else {
+                            stack.push(call.into());
+                        }
                     }
                 }
                 n if n.starts_with("call") && n.chars().skip(4).all(|c| c.is_ascii_digit()) => {
@@ -2461,7 +6058,7 @@ fn pseudo_decompile_from_instrs(
                     }
                     args.reverse();
                     let func = stack.pop().unwrap_or("<func>".into());
-                    stack.push(format!("{func}({})", args.join(", ")));
+                    stack.push(format!("{func}{}{})", func.access("("), join_exprs(&args)).into());
                 }
                 "put_loc" | "put_loc8" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
@@ -2471,32 +6068,34 @@ fn pseudo_decompile_from_instrs(
                         _ => 0,
                     };
                     let name = loc_name(b, idx);
-                    stmts.push(Stmt::Assign(name, rhs));
+                    stmts.push(Stmt::Assign(name, rhs.text));
                 }
                 n if n.starts_with("put_loc") && n != "put_loc" && n != "put_loc8" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[7..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
                         let rhs = stack.pop().unwrap_or("<rhs>".into());
                         let name = loc_name(b, idx);
-                        stmts.push(Stmt::Assign(name, rhs));
+                        stmts.push(Stmt::Assign(name, rhs.text));
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
-                "put_loc_check" => {
+                "put_loc_check" | "put_loc_check_init" => {
                     if let Some(Operand::U16(idx)) = ins.operand {
                         let rhs = stack.pop().unwrap_or("<rhs>".into());
-                        stmts.push(Stmt::Assign(loc_name(b, idx), rhs));
+                        stmts.push(Stmt::Assign(loc_name(b, idx), rhs.text));
                     }
                 }
                 "set_loc" | "set_loc8" => {
-                    let rhs = stack.last().cloned().unwrap_or("<rhs>".into());
+                    let rhs = stack.pop().unwrap_or("<rhs>".into());
                     let idx = match ins.operand {
                         Some(Operand::U16(v)) => v,
                         Some(Operand::U8(v)) => v as u16,
                         _ => 0,
                     };
-                    stmts.push(Stmt::Assign(loc_name(b, idx), rhs));
+                    let name = loc_name(b, idx);
+                    stmts.push(Stmt::Assign(name.clone(), rhs.text.clone()));
+                    stack.push(assigned_value(rhs, name));
                 }
                 "set_loc_uninitialized" => {
                     if let Some(Operand::U16(idx)) = ins.operand {
@@ -2506,12 +6105,23 @@ fn pseudo_decompile_from_instrs(
                 n if n.starts_with("set_loc") && n != "set_loc" && n != "set_loc8" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[7..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
-                        let rhs = stack.last().cloned().unwrap_or("<rhs>".into());
-                        stmts.push(Stmt::Assign(loc_name(b, idx), rhs));
+                        let rhs = stack.pop().unwrap_or("<rhs>".into());
+                        let name = loc_name(b, idx);
+                        stmts.push(Stmt::Assign(name.clone(), rhs.text.clone()));
+                        stack.push(assigned_value(rhs, name));
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
+                // Only the rest parameter itself is stored from here, which
+                // the signature already declares.
+                "rest" => {
+                    let first = match ins.operand {
+                        Some(Operand::U16(v)) => v,
+                        _ => 0,
+                    };
+                    stack.push(Expr { rest: true, ..arg_name(b, first).into() });
+                }
                 "put_arg" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
                     let idx = match ins.operand {
@@ -2519,53 +6129,127 @@ fn pseudo_decompile_from_instrs(
                         _ => 0,
                     };
                     let name = arg_name(b, idx);
-                    stmts.push(Stmt::Assign(name, rhs));
+                    if !rhs.rest {
+                        stmts.push(Stmt::Assign(name, rhs.text));
+                    }
                 }
                 n if n.starts_with("put_arg") && n != "put_arg" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[7..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
                         let rhs = stack.pop().unwrap_or("<rhs>".into());
                         let name = arg_name(b, idx);
-                        stmts.push(Stmt::Assign(name, rhs));
+                        if !rhs.rest {
+                            stmts.push(Stmt::Assign(name, rhs.text));
+                        }
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
                     }
                 }
                 "set_arg" => {
-                    let rhs = stack.last().cloned().unwrap_or("<rhs>".into());
+                    let rhs = stack.pop().unwrap_or("<rhs>".into());
                     let idx = match ins.operand {
                         Some(Operand::U16(v)) => v,
                         _ => 0,
                     };
-                    stmts.push(Stmt::Assign(arg_name(b, idx), rhs));
+                    let name = arg_name(b, idx);
+                    if rhs.rest {
+                        stack.push(Expr { rest: false, ..rhs });
+                    } else {
+                        stmts.push(Stmt::Assign(name.clone(), rhs.text.clone()));
+                        stack.push(assigned_value(rhs, name));
+                    }
                 }
                 n if n.starts_with("set_arg") && n != "set_arg" && n.chars().skip(7).all(|c| c.is_ascii_digit()) => {
                     let idx_str = &n[7..];
                     if let Ok(idx) = idx_str.parse::<u16>() {
-                        let rhs = stack.last().cloned().unwrap_or("<rhs>".into());
-                        stmts.push(Stmt::Assign(arg_name(b, idx), rhs));
+                        let rhs = stack.pop().unwrap_or("<rhs>".into());
+                        let name = arg_name(b, idx);
+                        if rhs.rest {
+                            stack.push(Expr { rest: false, ..rhs });
+                        } else {
+                            stmts.push(Stmt::Assign(name.clone(), rhs.text.clone()));
+                            stack.push(assigned_value(rhs, name));
+                        }
                     } else {
-                        stack.push(format!("<{}>", n));
+                        stack.push(format!("<{}>", n).into());
+                    }
+                }
+                // The scope object is tried first; the lookup that follows is the
+                // outer-scope fallback and already renders the plain name.
+                "with_get_var" | "with_put_var" | "with_delete_var" | "with_make_ref" | "with_get_ref" | "with_get_ref_undef" => {
+                    stack.pop();
+                    // the scope object is the hidden local just read (or stored)
+                    let slot = ii
+                        .checked_sub(1)
+                        .map(|p| &blk.instrs[p])
+                        .filter(|p| p.name.starts_with("get_loc") || p.name.starts_with("set_loc"))
+                        .and_then(|p| loc_slots(p).last().copied());
+                    if let Some(slot) = slot {
+                        let at = stmts.len();
+                        let name = loc_name(b, slot);
+                        // a scope starts once the local is stored, and storing it
+                        // again starts another
+                        let init = stmts.iter().rposition(|s| matches!(s.unannotated(), Stmt::Assign(n, _) if *n == name));
+                        match with_scopes.iter_mut().rev().find(|w| w.slot == slot).filter(|w| init.is_none_or(|p| p < w.start)) {
+                            Some(w) => w.end = at,
+                            None => with_scopes.push(WithScope { start: init.map_or(at, |p| p + 1), end: at, slot }),
+                        }
+                    }
+                }
+                "make_loc_ref" | "make_arg_ref" | "make_var_ref_ref" => {
+                    if let Some(Operand::AtomU16(_, idx)) = ins.operand {
+                        let name = match ins.name {
+                            "make_loc_ref" => loc_name(b, idx),
+                            "make_arg_ref" => arg_name(b, idx),
+                            _ => var_ref_name(b, idx),
+                        };
+                        stack.push("<ref>".into());
+                        stack.push(name.into());
+                    }
+                }
+                "make_var_ref" => {
+                    if let Some(Operand::Atom(idx)) = ins.operand {
+                        let name = atoms.resolve_idx(idx)?.to_string();
+                        stack.push("<ref>".into());
+                        stack.push(name.into());
                     }
                 }
+                "get_ref_value" => {
+                    let name = stack.last().cloned().unwrap_or("<ref>".into());
+                    stack.push(name);
+                }
+                "put_ref_value" => {
+                    let rhs = stack.pop().unwrap_or("<rhs>".into());
+                    let name = stack.pop().unwrap_or("<ref>".into());
+                    let _obj = stack.pop();
+                    reuse_stored(&mut stack, &name.text, &rhs);
+                    stmts.push(Stmt::Assign(name.text, rhs.text));
+                }
                 "get_var" | "get_var_undef" => {
                     if let Some(Operand::Atom(idx)) = ins.operand {
                         let a = atoms.resolve_idx(idx)?;
-                        stack.push(a.to_string());
+                        stack.push(a.to_string().into());
                     }
                 }
                 "put_var" | "put_var_init" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
                     if let Some(Operand::Atom(idx)) = ins.operand {
-                        let a = atoms.resolve_idx(idx)?;
-                        stmts.push(Stmt::Assign(a.to_string(), rhs));
+                        let a = atoms.resolve_idx(idx)?.to_string();
+                        reuse_stored(&mut stack, &a, &rhs);
+                        stmts.push(Stmt::Assign(a, rhs.text));
                     }
                 }
                 "get_field" | "get_field2" => {
                     if let Some(Operand::Atom(idx)) = ins.operand {
                         let prop = atoms.resolve_idx(idx)?;
                         let obj = stack.pop().unwrap_or("<obj>".into());
-                        stack.push(format!("{obj}.{}", prop));
+                        let value = format!("{obj}{}", obj.access(&member_suffix(&prop.to_string())));
+                        // get_field2 keeps the receiver for a following call_method,
+                        // which consumes it as `this` without rendering it again.
+                        if ins.name == "get_field2" {
+                            stack.push(Expr { optional: false, ..obj });
+                        }
+                        stack.push(value.into());
                     }
                 }
                 "put_field" => {
@@ -2573,44 +6257,68 @@ fn pseudo_decompile_from_instrs(
                     let obj = stack.pop().unwrap_or("<obj>".into());
                     if let Some(Operand::Atom(idx)) = ins.operand {
                         let prop = atoms.resolve_idx(idx)?;
-                        stmts.push(Stmt::Expr(format!("{obj}.{} = {rhs}", prop)));
+                        // trailing holes: `dup; push N; put_field length` on the literal
+                        if prop.to_string() == "length"
+                            && let Some(mut parts) = array_literal_parts(&obj.text).filter(|parts| !parts.iter().any(|p| p.starts_with("...")))
+                            && let Ok(len) = rhs.text.parse::<usize>()
+                            && len >= parts.len()
+                            && stack.last().is_some_and(|top| top.text == obj.text)
+                        {
+                            parts.resize(len, "");
+                            let lit = array_literal(&parts);
+                            stack.pop();
+                            stack.push(lit.into());
+                        } else {
+                            let target = format!("{obj}{}", member_suffix(&prop.to_string()));
+                            reuse_stored(&mut stack, &target, &rhs);
+                            stmts.push(Stmt::Expr(format!("{target} = {}", rhs.text)));
+                        }
                     }
                 }
                 "get_array_el" | "get_array_el2" => {
                     let prop = stack.pop().unwrap_or("<prop>".into());
                     let obj = stack.pop().unwrap_or("<obj>".into());
-                    let value = format!("{obj}[{prop}]");
+                    let value = format!("{obj}{}", obj.access(&element_suffix(&prop)));
                     if ins.name == "get_array_el" {
-                        stack.push(value);
+                        stack.push(value.into());
                     } else {
-                        stack.push(obj);
-                        stack.push(value);
+                        stack.push(Expr { optional: false, ..obj });
+                        stack.push(value.into());
                     }
                 }
                 "put_array_el" => {
                     let rhs = stack.pop().unwrap_or("<rhs>".into());
                     let index = stack.pop().unwrap_or("<index>".into());
                     let obj = stack.pop().unwrap_or("<obj>".into());
-                    stmts.push(Stmt::Expr(format!("{obj}[{index}] = {rhs}")));
+                    let target = format!("{obj}{}", element_suffix(&index));
+                    reuse_stored(&mut stack, &target, &rhs);
+                    stmts.push(Stmt::Expr(format!("{target} = {}", rhs.text)));
                 }
                 "get_length" => {
                     let obj = stack.pop().unwrap_or("<obj>".into());
-                    stack.push(format!("{obj}.length"));
+                    stack.push(format!("{obj}{}length", obj.access(".")).into());
                 }
                 "return" => {
                     let v = stack.pop().unwrap_or("undefined".into());
-                    stmts.push(Stmt::Return(Some(v)));
+                    stmts.push(Stmt::Return(Some(v.text)));
                 }
                 "return_undef" => {
+                    // the compiler drops the `drop` of a last expression
+                    // statement right before an implicit return
+                    if let Some(v) = stack.pop()
+                        && has_side_effects(&v.text)
+                    {
+                        stmts.push(Stmt::Expr(v.text));
+                    }
                     stmts.push(Stmt::Return(None));
                 }
                 "ret" => {
                     let v = stack.pop().unwrap_or("undefined".into());
-                    stmts.push(Stmt::Expr(format!("ret {}", v)));
+                    stmts.push(Stmt::Expr(format!("ret {}", v.text)));
                 }
                 "throw" => {
                     let v = stack.pop().unwrap_or("<value>".into());
-                    stmts.push(Stmt::Expr(format!("throw {}", v)));
+                    stmts.push(Stmt::Expr(format!("throw {}", v.text)));
                 }
                 "if_false" | "if_true" | "if_false8" | "if_true8" => {
                     let cond = stack.pop().unwrap_or("<cond>".into());
@@ -2638,10 +6346,23 @@ fn pseudo_decompile_from_instrs(
                     stmts.push(Stmt::Expr(format!("gosub L{}", target)));
                 }
                 "catch" => {
-                    stack.push("<exception>".into());
+                    // the catch offset, dropped again where the region ends
+                    stack.push("<catch>".into());
+                    stmts.push(Stmt::Expr(format!("{TRY_START}{}", label_target(ins).unwrap_or(0))));
+                }
+                // a return from inside a try: the catch offset goes, the value stays
+                "nip_catch" => {
+                    if stack.len() >= 2 {
+                        let v = stack.pop().unwrap();
+                        stack.pop();
+                        stack.push(v);
+                    }
                 }
-                "for_of_start" => {
-                    let _iterable = stack.pop();
+                "for_of_start" | "for_await_of_start" => {
+                    let iterable = stack.pop().unwrap_or("<iterable>".into());
+                    if ins.name == "for_of_start" {
+                        stmts.push(Stmt::Expr(format!("{FOR_OF_START}{iterable}")));
+                    }
                     stack.push("<iterator>".into());
                     stack.push("<method>".into());
                     stack.push("<done>".into());
@@ -2661,129 +6382,931 @@ fn pseudo_decompile_from_instrs(
                     let _method = stack.pop();
                     let _iterator = stack.pop();
                 }
-                "insert2" => {
-                    let a = stack.pop().unwrap_or("<a>".into());
-                    let obj = stack.pop().unwrap_or("<obj>".into());
-                    stack.push(a.clone());
-                    stack.push(obj);
-                    stack.push(a);
+                "iterator_check_object" => {}
+                "iterator_get_value_done" => {
+                    let result = stack.pop().unwrap_or("<result>".into());
+                    stack.push(format!("{result}.value").into());
+                    stack.push(format!("{result}.done").into());
                 }
-                "insert3" => {
-                    let a = stack.pop().unwrap_or("<a>".into());
-                    let prop = stack.pop().unwrap_or("<prop>".into());
-                    let obj = stack.pop().unwrap_or("<obj>".into());
-                    stack.push(a.clone());
-                    stack.push(obj);
-                    stack.push(prop);
-                    stack.push(a);
+                // The iterator record (iterator, next method, catch offset) stays on
+                // the stack across yield* delegation; only the top slot changes.
+                "iterator_next" => {
+                    let value = stack.pop().unwrap_or("<value>".into());
+                    let n = stack.len();
+                    let iterator = n.checked_sub(3).map(|i| stack[i].clone()).unwrap_or("<iterator>".into());
+                    stack.push(format!("{iterator}.next({})", value.at(PREC_ASSIGN)).into());
                 }
-                "insert4" => {
-                    let a = stack.pop().unwrap_or("<a>".into());
-                    let prop = stack.pop().unwrap_or("<prop>".into());
-                    let obj = stack.pop().unwrap_or("<obj>".into());
-                    let this = stack.pop().unwrap_or("<this>".into());
-                    stack.push(a.clone());
-                    stack.push(this);
-                    stack.push(obj);
-                    stack.push(prop);
-                    stack.push(a);
+                "iterator_call" => {
+                    // flags: bit 0 selects `throw` over `return`, bit 1 calls without an argument
+                    let flags = match ins.operand {
+                        Some(Operand::U8(v)) => v,
+                        _ => 0,
+                    };
+                    let value = stack.pop().unwrap_or("<value>".into());
+                    let n = stack.len();
+                    let iterator = n.checked_sub(3).map(|i| stack[i].clone()).unwrap_or("<iterator>".into());
+                    let method = if flags & 1 != 0 { "throw" } else { "return" };
+                    let arg = if flags & 2 != 0 { String::new() } else { value.text };
+                    stack.push(format!("{iterator}.{method}({arg})").into());
+                    stack.push(Expr::new(format!("{iterator}.{method} == null"), PREC_EQUALITY));
+                }
+                "iterator_close_return" => {
+                    // moves the return value below the iterator record
+                    let ret = stack.pop().unwrap_or("<ret>".into());
+                    let at = stack.len().saturating_sub(3);
+                    stack.insert(at, ret);
                 }
                 _ => {
                     // generic stack-effect-based fallback
-                    let (npop, npush) = opcode_stack_effect(ins.op).unwrap_or((ins.n_pop, ins.n_push));
-                    for _ in 0..npop {
-                        let _ = stack.pop();
+                    let (npop, npush) = stack_effect(ins);
+                    if npop > stack.len() {
+                        stmts.push(Stmt::Expr(format!("// stack underflow: {} pops {npop} of {}", ins.name, stack.len())));
                     }
+                    stack.truncate(stack.len().saturating_sub(npop));
                     for _ in 0..npush {
-                        stack.push(format!("<{}>", ins.name));
+                        stack.push(format!("<{}>", ins.name).into());
                     }
                     stmts.push(Stmt::Expr(format!("<{}>", ins.name)));
                 }
             }
+            // Instructions behind jumps and labels carry over to the next
+            // plain statement, which keeps the structuring patterns intact.
+            let plain = (emitted..stmts.len()).find(|&i| matches!(stmts[i], Stmt::Expr(_) | Stmt::Decl(_) | Stmt::Assign(..) | Stmt::Return(_)));
+            // the line of the instruction completing the statement: QuickJS
+            // records positions at the operations that can throw, so the
+            // ones feeding it may still carry the previous line
+            if let Some(idx) = plain
+                && let Some(debug) = &b.debug
+                && last_line != Some(debug.line_at(ins.pc))
+            {
+                last_line = Some(debug.line_at(ins.pc));
+                annotations.entry(idx).or_default().0 = last_line;
+            }
+            if annotate
+                && let Some(idx) = plain
+            {
+                // raw instructions up to this one, including any folded away
+                let end = raw_instrs.iter().position(|raw| raw.pc > ins.pc).unwrap_or(raw_instrs.len()).max(next_raw);
+                // atom operands may hold raw newlines, which would end the comment
+                let lines = raw_instrs[next_raw..end]
+                    .iter()
+                    .map(|raw| disasm_line(atoms, raw).trim_end().replace('\n', "\\n"))
+                    .collect();
+                annotations.entry(idx).or_default().1 = lines;
+                next_raw = end;
+            }
         }
+        if blk.instrs.last().is_some_and(|ins| ins.name == "gosub") {
+            after_gosub = stack;
+        }
+    }
+    for (idx, (source_line, lines)) in annotations {
+        let s = std::mem::replace(&mut stmts[idx], Stmt::Label(0));
+        stmts[idx] = Stmt::Annotated(Box::new(s), source_line, lines);
     }
 
-    let stmts = try_structure_while(&stmts);
-    let stmts = try_structure_if_else(&stmts);
+    let stmts = wrap_with_scopes(b, stmts, with_scopes);
+    let stmts = strip_class_internals(b, stmts);
+    let (stmts, defaults) = take_param_defaults(b, stmts);
+    let params = param_list_with_defaults(b, atoms, &defaults);
+    let Ok(stmts) = try_structure_while(&stmts, deadline).and_then(|stmts| try_structure_if_else(&stmts, deadline)) else {
+        return timed_out();
+    };
+    let stmts = try_structure_for_of(&stmts);
+    let stmts = try_structure_try(&stmts);
+    let stmts = unwrap_crossed_withs(stmts);
+
+    let stmts = if options.optimize {
+        optimize_stmts(b, &instrs, &stmts)
+    } else {
+        stmts
+    };
 
-    let stmts = if optimize {
-        optimize_stmts(&stmts)
+    let stmts = if options.declare_locals {
+        declare_locals(b, &stmts)
     } else {
         stmts
     };
 
-    if optimize {
+    let arrow = is_arrow_function(b);
+    // a module's entry function is async only so it can use top-level await
+    let keyword = if b.func_name.to_string() == "<eval>" { "function".to_string() } else { function_keyword(b) };
+    if options.optimize {
         let has_any_real = stmts.iter().any(|s| !matches!(s, Stmt::Label(_)));
         if !has_any_real {
             return Ok(String::new());
         }
-        if let [Stmt::Label(_), Stmt::Return(ret)] = stmts.as_slice() {
-            if let Some(expr) = ret {
-                return Ok(format!("function {func_name}() {{ return {expr}; }}\n"));
+        if let [Stmt::Label(_), only] | [only] = stmts.as_slice()
+            && let Stmt::Return(ret) = only.unannotated()
+        {
+            if arrow {
+                return Ok(match ret {
+                    // an object literal body would parse as a block
+                    Some(expr) if expr.starts_with('{') => format!("const {func_name} = ({params}) => ({expr});\n"),
+                    Some(expr) => format!("const {func_name} = ({params}) => {expr};\n"),
+                    None => format!("const {func_name} = ({params}) => {{}};\n"),
+                });
             }
-            return Ok(format!("function {func_name}() {{ return; }}\n"));
-        }
-        if let [Stmt::Return(ret)] = stmts.as_slice() {
             if let Some(expr) = ret {
-                return Ok(format!("function {func_name}() {{ return {expr}; }}\n"));
+                return Ok(format!("{keyword} {func_name}({params}) {{ return {expr}; }}\n"));
             }
-            return Ok(format!("function {func_name}() {{ return; }}\n"));
+            return Ok(format!("{keyword} {func_name}({params}) {{ return; }}\n"));
         }
     }
 
     let mut out = String::new();
-    out.push_str(&format!("function {}() {{\n", func_name));
+    if arrow {
+        out.push_str(&format!("const {func_name} = ({params}) => {{\n"));
+    } else {
+        out.push_str(&format!("{keyword} {func_name}({params}) {{\n"));
+    }
     out.push_str(&stmts_to_string(&stmts, 2));
-    out.push_str("}\n");
+    out.push_str(if arrow { "};\n" } else { "}\n" });
     Ok(out)
 }
 
+fn with_module_imports(v: &Value, options: DecompileOptions, out: String) -> String {
+    if matches!(options.mode, DecompileMode::Disasm | DecompileMode::Json | DecompileMode::Cfg) || options.function_index.is_some() {
+        return out;
+    }
+    let imports = module_import_decls(v);
+    if imports.is_empty() {
+        out
+    } else {
+        format!("{imports}\n{out}")
+    }
+}
+
 pub fn decompile_with_mode(bytecode: &[u8], mode: DecompileMode) -> Result<String, DeqjsError> {
     decompile_with_options(
         bytecode,
         DecompileOptions {
             mode,
-            version: DecompileVersion::Auto,
-            deobfuscate: false,
-            optimize: false,
+            ..Default::default()
         },
     )
 }
 
-pub fn decompile_with_options(bytecode: &[u8], options: DecompileOptions) -> Result<String, DeqjsError> {
-    let mut r = Reader::new(bytecode);
-    let version = match options.version {
+fn detect_version(r: &Reader<'_>, version: DecompileVersion) -> DecompileVersion {
+    match version {
         DecompileVersion::Auto => match r.peek_u8() {
             Some(BC_VERSION_V1) => DecompileVersion::Legacy,
             _ => DecompileVersion::Current,
         },
+        DecompileVersion::Forced(BC_VERSION_V1) => DecompileVersion::Legacy,
+        DecompileVersion::Forced(_) => DecompileVersion::Current,
         v => v,
-    };
+    }
+}
 
-    match version {
+fn check_functions(
+    funcs: &[&FunctionBytecode],
+    mut decode: impl FnMut(&FunctionBytecode) -> Result<Vec<Instr>, DeqjsError>,
+) -> Result<usize, DeqjsError> {
+    for (index, b) in funcs.iter().copied().enumerate() {
+        decode(b).map_err(|e| DeqjsError::InFunction { index, name: b.func_name.to_string(), source: Box::new(e) })?;
+    }
+    Ok(funcs.len())
+}
+
+/// Parses the file and decodes every function's instructions without
+/// rendering anything. Returns the number of functions checked.
+pub fn check(bytecode: &[u8], version: DecompileVersion, lenient_version: bool) -> Result<usize, DeqjsError> {
+    let (version, v, _) = read_container(bytecode, version, lenient_version)?;
+    let decode = if version == DecompileVersion::Legacy { decode_instructions_v1 } else { decode_instructions };
+    check_functions(&collect_functions_entry_first(&v), decode)
+}
+
+/// One function's header fields, as listed by [`list_functions`].
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionSummary {
+    pub index: usize,
+    /// As shown in decompiled output.
+    pub name: String,
+    pub arg_count: u16,
+    pub var_count: u16,
+    pub stack_size: u16,
+    pub byte_code_len: u32,
+}
+
+/// Parses the file and summarizes every function, in traversal order with
+/// the entry function first, so the indices match
+/// [`DecompileOptions::function_index`].
+pub fn list_functions(bytecode: &[u8], version: DecompileVersion, lenient_version: bool) -> Result<Vec<FunctionSummary>, DeqjsError> {
+    let (_, v, _) = read_container(bytecode, version, lenient_version)?;
+    let options = DecompileOptions::default();
+    Ok(collect_functions_entry_first(&v)
+        .into_iter()
+        .enumerate()
+        .map(|(index, b)| FunctionSummary {
+            index,
+            name: display_func_name(options, b, index),
+            arg_count: b.arg_count,
+            var_count: b.var_count,
+            stack_size: b.stack_size,
+            byte_code_len: b.byte_code_len,
+        })
+        .collect())
+}
+
+/// One function's constant pool, as listed by [`constant_pools`].
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionConstants {
+    pub index: usize,
+    pub name: String,
+    /// Each entry's `Display` form, so a nested function is only labeled
+    /// `<function:name>`; it gets an entry of its own.
+    pub constants: Vec<String>,
+}
+
+/// Parses the file and lists every function's constant pool, indexed as in
+/// [`list_functions`], to match `push_const`/`fclosure` operands against.
+pub fn constant_pools(bytecode: &[u8], version: DecompileVersion, lenient_version: bool) -> Result<Vec<FunctionConstants>, DeqjsError> {
+    let (_, v, _) = read_container(bytecode, version, lenient_version)?;
+    let options = DecompileOptions::default();
+    Ok(collect_functions_entry_first(&v)
+        .into_iter()
+        .enumerate()
+        .map(|(index, b)| FunctionConstants {
+            index,
+            name: display_func_name(options, b, index),
+            constants: b.cpool.iter().map(Value::to_string).collect(),
+        })
+        .collect())
+}
+
+/// An entry of a file's atom table, as listed by [`atoms`].
+#[derive(Debug, Clone, Serialize)]
+pub struct AtomEntry {
+    /// The atom's ID as bytecode operands refer to it.
+    pub index: u32,
+    /// `String`, `Symbol` or `Raw`.
+    pub atom: AtomRepr,
+}
+
+/// Parses the file's atom table, leaving out the builtin atoms (including
+/// the legacy layout's, which it stores alongside the file's own).
+pub fn atoms(bytecode: &[u8], version: DecompileVersion, lenient_version: bool) -> Result<Vec<AtomEntry>, DeqjsError> {
+    let (resolved, _, atoms) = read_container(bytecode, version, lenient_version)?;
+    let builtins = if resolved == DecompileVersion::Legacy { LEGACY_V1_ATOMS.len() } else { 0 };
+    Ok(atoms
+        .idx_to_atom
+        .iter()
+        .enumerate()
+        .skip(builtins)
+        .map(|(off, atom)| AtomEntry { index: atoms.first_atom + off as u32, atom: atom.clone() })
+        .collect())
+}
+
+/// Resolves a function selector to its index in traversal order: a
+/// zero-based index, or else the function's name as shown in output under
+/// `options` (so `closure_N` works with `deobfuscate`). The name must match
+/// exactly one function.
+pub fn select_function(bytecode: &[u8], options: DecompileOptions, selector: &str) -> Result<usize, DeqjsError> {
+    let (_, v, _) = read_container(bytecode, options.version, options.lenient_version)?;
+    let funcs = collect_functions_entry_first(&v);
+    if let Ok(index) = selector.parse::<usize>() {
+        if index >= funcs.len() {
+            return Err(DeqjsError::FunctionIndexOutOfRange { index, count: funcs.len() });
+        }
+        return Ok(index);
+    }
+    let indices: Vec<usize> = funcs
+        .iter()
+        .enumerate()
+        .filter(|(index, b)| display_func_name(options, b, *index) == selector)
+        .map(|(index, _)| index)
+        .collect();
+    match indices[..] {
+        [index] => Ok(index),
+        [] => Err(DeqjsError::FunctionNotFound { name: selector.to_string() }),
+        _ => Err(DeqjsError::AmbiguousFunction { name: selector.to_string(), indices }),
+    }
+}
+
+/// Detects (or applies the forced) layout and reads the atom table and the
+/// top-level value. Returns the resolved version, `Legacy` or `Current`.
+/// Every public entry point goes through here, so they agree on the version.
+fn read_container(bytecode: &[u8], version: DecompileVersion, lenient_version: bool) -> Result<(DecompileVersion, Value, AtomTable), DeqjsError> {
+    let mut r = Reader::new(bytecode);
+    let resolved = detect_version(&r, version);
+    let (value, atoms) = match resolved {
         DecompileVersion::Legacy => {
-            let atoms = read_atom_table_v1(&mut r)?;
-            let atoms_adapted = atoms.to_atom_table();
-            let v = read_value_v1(&mut r, &atoms)?;
-            let funcs = collect_functions_entry_first(&v);
-            if funcs.is_empty() {
-                return Ok(format!("{}", v));
-            }
-            decompile_functions_with(&funcs, options, &atoms_adapted, decode_instructions_v1)
+            let atoms = read_atom_table_v1(&mut r, version.forced(), lenient_version)?;
+            (read_value_v1(&mut r, &atoms)?, atoms.to_atom_table())
         }
         DecompileVersion::Current => {
-            let atoms = read_atom_table(&mut r)?;
-            let v = read_value(&mut r, &atoms)?;
-            let funcs = collect_functions_entry_first(&v);
-            if funcs.is_empty() {
-                return Ok(format!("{}", v));
+            let atoms = read_atom_table(&mut r, version.forced(), lenient_version)?;
+            (read_value(&mut r, &atoms)?, atoms)
+        }
+        DecompileVersion::Auto | DecompileVersion::Forced(_) => unreachable!(),
+    };
+    Ok((resolved, value, atoms))
+}
+
+/// A parsed bytecode file, as returned by [`parse`].
+#[derive(Debug, Clone)]
+pub struct ParsedProgram {
+    /// The layout the file was read with: `Legacy` or `Current`.
+    pub version: DecompileVersion,
+    pub value: Value,
+}
+
+impl ParsedProgram {
+    /// Every function, in traversal order with the entry function first.
+    pub fn functions(&self) -> Vec<&FunctionBytecode> {
+        collect_functions_entry_first(&self.value)
+    }
+}
+
+/// Parses the file into its [`Value`] tree without decoding or rendering
+/// any function, auto-detecting the version as [`decompile`] does.
+pub fn parse(bytecode: &[u8]) -> Result<ParsedProgram, DeqjsError> {
+    let (version, value, _) = read_container(bytecode, DecompileVersion::Auto, false)?;
+    Ok(ParsedProgram { version, value })
+}
+
+fn dump_value(out: &mut String, v: &Value, depth: usize, funcs: &[&FunctionBytecode]) {
+    let pad = "  ".repeat(depth);
+    match v {
+        Value::Array(items) => {
+            out.push_str(&format!("array ({})\n", items.len()));
+            for (i, it) in items.iter().enumerate() {
+                out.push_str(&format!("{pad}  [{i}] "));
+                dump_value(out, it, depth + 1, funcs);
+            }
+        }
+        Value::Object(props) => {
+            out.push_str(&format!("object ({})\n", props.len()));
+            for (k, val) in props {
+                out.push_str(&format!("{pad}  {}: ", prop_key(&k.to_string())));
+                dump_value(out, val, depth + 1, funcs);
+            }
+        }
+        Value::Map(entries) => {
+            out.push_str(&format!("map ({})\n", entries.len()));
+            for (i, (k, val)) in entries.iter().enumerate() {
+                out.push_str(&format!("{pad}  key[{i}] "));
+                dump_value(out, k, depth + 1, funcs);
+                out.push_str(&format!("{pad}  value[{i}] "));
+                dump_value(out, val, depth + 1, funcs);
+            }
+        }
+        Value::Set(items) => {
+            out.push_str(&format!("set ({})\n", items.len()));
+            for (i, it) in items.iter().enumerate() {
+                out.push_str(&format!("{pad}  [{i}] "));
+                dump_value(out, it, depth + 1, funcs);
+            }
+        }
+        Value::Module { name, req_modules, imports, func_obj } => {
+            out.push_str(&format!("module {}\n", js_string_literal(&name.to_string())));
+            for (i, m) in req_modules.iter().enumerate() {
+                out.push_str(&format!("{pad}  requires[{i}] {}\n", js_string_literal(&m.module_name.to_string())));
+            }
+            for (i, imp) in imports.iter().enumerate() {
+                out.push_str(&format!("{pad}  imports[{i}] {} from requires[{}] into var {}\n", imp.import_name, imp.req_module_idx, imp.var_idx));
+            }
+            out.push_str(&format!("{pad}  body: "));
+            dump_value(out, func_obj, depth + 1, funcs);
+        }
+        Value::TypedArray { kind, len, offset, buffer } => {
+            out.push_str(&format!("typedarray kind={kind} len={len} offset={offset}\n{pad}  buffer: "));
+            dump_value(out, buffer, depth + 1, funcs);
+        }
+        Value::Date { value } => {
+            out.push_str("date ");
+            dump_value(out, value, depth, funcs);
+        }
+        Value::Function(b) => {
+            let index = funcs.iter().position(|f| std::ptr::eq(*f, b)).unwrap_or(0);
+            out.push_str(&format!("function {index} {}\n", b.func_name));
+            out.push_str(&format!(
+                "{pad}  flags=0x{:04x} strict={} args={} defined_args={} vars={} stack_size={} var_refs={} bytecode_len={}\n",
+                b.flags, b.is_strict_mode, b.arg_count, b.defined_arg_count, b.var_count, b.stack_size, b.var_ref_count, b.byte_code_len
+            ));
+            if !b.locals.is_empty() {
+                out.push_str(&format!("{pad}  locals ({}):\n", b.locals.len()));
+                for (i, l) in b.locals.iter().enumerate() {
+                    let kind = if i < b.arg_count as usize { "arg" } else { "var" };
+                    out.push_str(&format!("{pad}    [{i}] {kind} {} scope={} next={} flags=0x{:02x}", l.name, l.scope_level, l.scope_next, l.flags));
+                    if let Some(r) = l.var_ref_idx {
+                        out.push_str(&format!(" var_ref={r}"));
+                    }
+                    out.push('\n');
+                }
+            }
+            if !b.closure_vars.is_empty() {
+                out.push_str(&format!("{pad}  closure vars ({}):\n", b.closure_vars.len()));
+                for (i, cv) in b.closure_vars.iter().enumerate() {
+                    out.push_str(&format!("{pad}    [{i}] {} var_idx={} flags=0x{:x}\n", cv.name, cv.var_idx, cv.flags));
+                }
+            }
+            if !b.cpool.is_empty() {
+                out.push_str(&format!("{pad}  cpool ({}):\n", b.cpool.len()));
+                for (i, c) in b.cpool.iter().enumerate() {
+                    out.push_str(&format!("{pad}    [{i}] "));
+                    dump_value(out, c, depth + 2, funcs);
+                }
+            }
+        }
+        _ => out.push_str(&format!("{v}\n")),
+    }
+}
+
+/// The parsed container as an indented tree: the atom table, then the
+/// top-level value with every function's header, locals, closure variables
+/// and constant pool. Nothing is decompiled; this shows what the file holds
+/// before any of it is interpreted.
+pub fn dump_program(bytecode: &[u8], version: DecompileVersion) -> Result<String, DeqjsError> {
+    let program = DecodedProgram::parse(bytecode, version, false)?;
+    let atoms = &program.atoms;
+    let mut out = String::new();
+    out.push_str(&format!("version {}\n", program.version_byte.unwrap_or(0)));
+    out.push_str(&format!("atoms ({}):\n", atoms.idx_to_atom.len()));
+    for (off, a) in atoms.idx_to_atom.iter().enumerate() {
+        let id = atoms.first_atom as usize + off;
+        match a {
+            AtomRepr::String(s) => out.push_str(&format!("  {id} {}\n", js_string_literal(s))),
+            _ => out.push_str(&format!("  {id} {a}\n")),
+        }
+    }
+    out.push_str("value: ");
+    dump_value(&mut out, &program.value, 0, &program.functions());
+    Ok(out)
+}
+
+fn decompile_parts(
+    bytecode: &[u8],
+    options: DecompileOptions,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Value, Vec<DecompiledFunction>), DeqjsError> {
+    let (version, v, atoms) = read_container(bytecode, options.version, options.lenient_version)?;
+    let decode = if version == DecompileVersion::Legacy { decode_instructions_v1 } else { decode_instructions };
+    let decode_one = if version == DecompileVersion::Legacy { decode_instr_v1 } else { decode_instr };
+    let recover = options.recover && options.mode == DecompileMode::Disasm;
+    let decode = |_, b: &FunctionBytecode| if recover { Ok(decode_instructions_recovering(b, decode_one)) } else { decode(b) };
+    let mut out = decompile_functions_with(&collect_functions_entry_first(&v), options, &atoms, decode, progress)?;
+    finish_first_function(&v, options, version, bytecode.first().copied(), &mut out);
+    Ok((v, out))
+}
+
+/// Attaches module imports and, for a version byte that was only accepted
+/// leniently, a warning to the first function's text. JSON output gets
+/// neither, since it has to stay parseable.
+fn finish_first_function(v: &Value, options: DecompileOptions, version: DecompileVersion, version_byte: Option<u8>, out: &mut [DecompiledFunction]) {
+    if let Some(first) = out.first_mut() {
+        first.text = with_module_imports(v, options, std::mem::take(&mut first.text));
+        // only reachable with `lenient_version`; a forced version is deliberate
+        let expected = if version == DecompileVersion::Legacy { BC_VERSION_V1 } else { BC_VERSION };
+        if options.mode != DecompileMode::Json
+            && options.version.forced().is_none()
+            && let Some(actual) = version_byte
+            && actual != expected
+        {
+            first.text = format!(
+                "// warning: bytecode version {actual} differs from the supported version {expected}; output may be inaccurate\n{}",
+                first.text
+            );
+        }
+    }
+}
+
+/// A string embedded in a bytecode file, as listed by
+/// [`DecodedProgram::strings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedString {
+    /// `atom N` for an atom table entry, otherwise the value's path, such as
+    /// `function 2 (load) cpool[3]` or `value[1].name`.
+    pub location: String,
+    pub text: String,
+}
+
+/// `skip` holds (function index, cpool index) pairs to leave out.
+fn collect_strings(v: &Value, path: &str, funcs: &[&FunctionBytecode], skip: &HashSet<(usize, usize)>, out: &mut Vec<EmbeddedString>) {
+    let mut push = |text: &str| out.push(EmbeddedString { location: path.to_string(), text: text.to_string() });
+    match v {
+        Value::String(s) => push(s),
+        Value::RegExp { pattern, .. } => push(pattern),
+        Value::Array(items) => {
+            for (i, it) in items.iter().enumerate() {
+                collect_strings(it, &format!("{path}[{i}]"), funcs, skip, out);
+            }
+        }
+        Value::Object(props) => {
+            for (k, val) in props {
+                collect_strings(val, &format!("{path}{}", member_suffix(&k.to_string())), funcs, skip, out);
+            }
+        }
+        Value::Map(entries) => {
+            for (i, (k, val)) in entries.iter().enumerate() {
+                collect_strings(k, &format!("{path} key[{i}]"), funcs, skip, out);
+                collect_strings(val, &format!("{path} value[{i}]"), funcs, skip, out);
+            }
+        }
+        Value::Set(items) => {
+            for (i, it) in items.iter().enumerate() {
+                collect_strings(it, &format!("{path}[{i}]"), funcs, skip, out);
+            }
+        }
+        Value::Function(b) => {
+            let index = funcs.iter().position(|f| std::ptr::eq(*f, b)).unwrap_or(0);
+            for (i, c) in b.cpool.iter().enumerate().filter(|(i, _)| !skip.contains(&(index, *i))) {
+                collect_strings(c, &format!("function {index} ({}) cpool[{i}]", b.func_name), funcs, skip, out);
+            }
+        }
+        Value::Module { func_obj, .. } => collect_strings(func_obj, path, funcs, skip, out),
+        Value::TypedArray { buffer, .. } => collect_strings(buffer, path, funcs, skip, out),
+        Value::Date { value } | Value::BoxedPrimitive(value) => collect_strings(value, path, funcs, skip, out),
+        _ => {}
+    }
+}
+
+/// A parsed bytecode file with every function's instructions decoded up
+/// front, for rendering it several ways without parsing or decoding again.
+/// Functions are indexed in traversal order (entry first), as in
+/// [`decompile_functions`].
+pub struct DecodedProgram {
+    value: Value,
+    atoms: AtomTable,
+    /// As passed to `parse`, and as resolved to `Legacy` or `Current`.
+    requested: DecompileVersion,
+    resolved: DecompileVersion,
+    version_byte: Option<u8>,
+    instrs: Vec<Vec<Instr>>,
+}
+
+impl DecodedProgram {
+    pub fn parse(bytecode: &[u8], version: DecompileVersion, lenient_version: bool) -> Result<Self, DeqjsError> {
+        let (resolved, value, atoms) = read_container(bytecode, version, lenient_version)?;
+        let decode = if resolved == DecompileVersion::Legacy { decode_instructions_v1 } else { decode_instructions };
+        let instrs = collect_functions_entry_first(&value)
+            .into_iter()
+            .enumerate()
+            .map(|(index, b)| decode(b).map_err(|e| DeqjsError::InFunction { index, name: b.func_name.to_string(), source: Box::new(e) }))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { value, atoms, requested: version, resolved, version_byte: bytecode.first().copied(), instrs })
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn functions(&self) -> Vec<&FunctionBytecode> {
+        collect_functions_entry_first(&self.value)
+    }
+
+    pub fn instructions(&self, index: usize) -> Option<&[Instr]> {
+        self.instrs.get(index).map(Vec::as_slice)
+    }
+
+    /// Every string atom, then every string value (constant pool entries
+    /// included) in traversal order. Unlike scanning the raw bytes, this
+    /// never picks up opcode or operand data; the compiled form of a regexp
+    /// literal, a cpool string pushed just before `regexp`, is left out too.
+    pub fn strings(&self) -> Vec<EmbeddedString> {
+        let mut out: Vec<EmbeddedString> = self
+            .atoms
+            .idx_to_atom
+            .iter()
+            .enumerate()
+            .filter_map(|(off, a)| match a {
+                AtomRepr::String(s) => Some(EmbeddedString { location: format!("atom {}", self.atoms.first_atom as usize + off), text: s.clone() }),
+                _ => None,
+            })
+            .collect();
+        let mut regexp_bytecode = HashSet::new();
+        for (index, instrs) in self.instrs.iter().enumerate() {
+            for w in instrs.windows(2) {
+                if w[1].name == "regexp"
+                    && let Some(Operand::Const(idx)) = w[0].operand
+                {
+                    regexp_bytecode.insert((index, idx as usize));
+                }
+            }
+        }
+        collect_strings(&self.value, "value", &self.functions(), &regexp_bytecode, &mut out);
+        out
+    }
+
+    /// Like [`decompile_functions`]; `options.version` and
+    /// `options.lenient_version` were already applied by [`Self::parse`].
+    pub fn decompile_functions(&self, options: DecompileOptions) -> Result<Vec<DecompiledFunction>, DeqjsError> {
+        let funcs = self.functions();
+        // `funcs` and `instrs` are both in traversal order
+        let decode = |index: usize, _: &FunctionBytecode| Ok(self.instrs[index].clone());
+        let options = DecompileOptions { version: self.requested, ..options };
+        let mut out = decompile_functions_with(&funcs, options, &self.atoms, decode, &mut |_, _| {})?;
+        finish_first_function(&self.value, options, self.resolved, self.version_byte, &mut out);
+        Ok(out)
+    }
+
+    /// Like [`decompile_with_options`].
+    pub fn decompile(&self, options: DecompileOptions) -> Result<String, DeqjsError> {
+        let funcs = self.decompile_functions(options)?;
+        Ok(join_output(&self.value, &funcs, options))
+    }
+}
+
+/// Decompiles each function separately, in traversal order (entry first).
+/// Module import declarations are attached to the first function's text.
+pub fn decompile_functions(bytecode: &[u8], options: DecompileOptions) -> Result<Vec<DecompiledFunction>, DeqjsError> {
+    Ok(decompile_parts(bytecode, options, &mut |_, _| {})?.1)
+}
+
+pub fn decompile_with_options(bytecode: &[u8], options: DecompileOptions) -> Result<String, DeqjsError> {
+    decompile_with_progress(bytecode, options, &mut |_, _| {})
+}
+
+/// Decompiles the first byte array in C source, such as the
+/// `const uint8_t qjsc_name[] = { 0x.., ... };` that `qjsc -c` writes.
+pub fn decompile_from_c_array(src: &str, options: DecompileOptions) -> Result<String, DeqjsError> {
+    decompile_with_options(&c_array_bytes(src, None)?, options)
+}
+
+/// The contents of a byte array defined in C source: the one named `symbol`,
+/// or the first. Elements are integer literals (hex, octal or decimal).
+pub fn c_array_bytes(src: &str, symbol: Option<&str>) -> Result<Vec<u8>, DeqjsError> {
+    let src = strip_c_comments(src);
+    let not_found = || DeqjsError::CArrayNotFound { symbol: symbol.map(str::to_string) };
+    // each `name[...] = {`, found from its brace
+    let (name, body) = src
+        .match_indices('{')
+        .find_map(|(open, _)| {
+            let decl = src[..open].trim_end().strip_suffix('=')?.trim_end().strip_suffix(']')?;
+            let decl = &decl[..decl.rfind('[')?];
+            let name = &decl[decl.trim_end_matches(|c: char| c == '_' || c.is_ascii_alphanumeric()).len()..];
+            let close = open + src[open..].find('}')?;
+            (!name.is_empty() && symbol.is_none_or(|s| s == name)).then(|| (name, &src[open + 1..close]))
+        })
+        .ok_or_else(not_found)?;
+    body.split(',')
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(|text| {
+            let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+            let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+                u8::from_str_radix(hex, 16)
+            } else if digits.len() > 1
+                && let Some(oct) = digits.strip_prefix('0')
+            {
+                u8::from_str_radix(oct, 8)
+            } else {
+                digits.parse()
+            };
+            value.map_err(|_| DeqjsError::CArrayElement { symbol: name.to_string(), text: text.to_string() })
+        })
+        .collect()
+}
+
+/// `src` with its `/* */` and `//` comments blanked out.
+fn strip_c_comments(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some(at) = rest.find('/') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map_or("", |end| &after[end + 2..]);
+            out.push(' ');
+        } else if let Some(after) = rest.strip_prefix("//") {
+            rest = after.find('\n').map_or("", |end| &after[end..]);
+        } else {
+            out.push('/');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Like [`decompile_with_options`], but calls `progress(current, total)` after
+/// each function is decompiled. `total` is the number of functions selected
+/// for output (1 when `function_index` is set).
+pub fn decompile_with_progress(
+    bytecode: &[u8],
+    options: DecompileOptions,
+    progress: &mut impl FnMut(usize, usize),
+) -> Result<String, DeqjsError> {
+    let (v, funcs) = decompile_parts(bytecode, options, progress)?;
+    Ok(join_output(&v, &funcs, options))
+}
+
+fn join_output(v: &Value, funcs: &[DecompiledFunction], options: DecompileOptions) -> String {
+    if collect_functions_entry_first(v).is_empty() {
+        return format!("{}\n", value_literal(v, options.normalize_property_order));
+    }
+    if options.mode == DecompileMode::Json {
+        return format!("[{}]\n", funcs.iter().map(|f| f.text.as_str()).collect::<Vec<_>>().join(",\n"));
+    }
+    if options.emit_runnable && !matches!(options.mode, DecompileMode::Disasm | DecompileMode::Cfg) {
+        return runnable_output(funcs);
+    }
+    funcs.iter().map(|f| f.text.as_str()).collect::<Vec<_>>().join("\n")
+}
+
+/// Joins decompiled functions into a script that parses, though it is
+/// semantically incomplete: the entry function runs as an IIFE, functions
+/// get unique valid names, labels and pseudo statements (`goto`, `close`,
+/// ...) are commented out, and `<...>` placeholders become members of a
+/// `__deqjs` stub object.
+fn runnable_output(funcs: &[DecompiledFunction]) -> String {
+    let mut out = String::new();
+    let mut body = String::from("var __deqjs = {};\n");
+    let mut used = HashSet::new();
+    for f in funcs {
+        let entry = f.index == 0;
+        let mut name = sanitize_ident(&f.name);
+        if name != f.name {
+            name = format!("func_{}", f.index);
+        }
+        if !entry && !used.insert(name.clone()) {
+            name = format!("{name}_{}", f.index);
+            used.insert(name.clone());
+        }
+        let mut in_header = true;
+        let mut iife = false;
+        let mut lines = Vec::new();
+        for line in f.text.lines() {
+            if in_header {
+                let header = [("function ", "("), ("const ", " = ")].into_iter().find_map(|(kw, sep)| {
+                    let rest = line.strip_prefix(kw)?.strip_prefix(f.name.as_str())?;
+                    rest.starts_with(sep).then_some((kw, rest))
+                });
+                match header {
+                    Some((kw, rest)) => {
+                        in_header = false;
+                        if entry && kw == "function " {
+                            iife = true;
+                            lines.push(runnable_line(&format!("(function {rest}")));
+                        } else {
+                            lines.push(runnable_line(&format!("{kw}{name}{rest}")));
+                        }
+                    }
+                    // module imports and warnings ahead of the entry function
+                    None => out.push_str(&format!("{line}\n")),
+                }
+                continue;
+            }
+            lines.push(runnable_line(line));
+        }
+        if iife && let Some(last) = lines.last_mut() {
+            *last = format!("{})();", last.trim_end_matches(';'));
+        }
+        for line in lines {
+            body.push_str(&line);
+            body.push('\n');
+        }
+        body.push('\n');
+    }
+    out.push_str(&body);
+    out
+}
+
+fn runnable_line(line: &str) -> String {
+    let stmt = line.trim_start();
+    let indent = &line[..line.len() - stmt.len()];
+    let is_label = stmt.strip_prefix('L').and_then(|s| s.strip_suffix(':')).is_some_and(|n| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()));
+    if is_label || ["goto ", "gosub ", "close ", "ret ", "check_ctor;", "<define_field> "].iter().any(|p| stmt.starts_with(p)) {
+        return format!("{indent}// {stmt}");
+    }
+    let mut stmt = stub_placeholders(stmt);
+    if let Some((head, target)) = stmt.rsplit_once(" goto L")
+        && target.strip_suffix(';').is_some_and(|n| n.bytes().all(|c| c.is_ascii_digit()))
+    {
+        stmt = format!("{head} /* goto L{} */;", &target[..target.len() - 1]);
+    }
+    // `class C extends B` records only the heritage; the members follow
+    if stmt.starts_with("class ") && let Some(head) = stmt.strip_suffix(';') {
+        stmt = format!("{head} {{}}");
+    }
+    format!("{indent}{stmt}")
+}
+
+/// Replaces each `<...>` placeholder outside strings and comments with a
+/// `__deqjs["..."]` lookup, which is valid in any expression position,
+/// including as an assignment target. `super` and `import.meta` get the same
+/// treatment, since the functions using them are printed standalone.
+fn stub_placeholders(s: &str) -> String {
+    let is_ident = |c: &char| c.is_alphanumeric() || *c == '_' || *c == '$';
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::new();
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            out.push(c);
+            if c == '\\' && let Some(&next) = chars.get(i + 1) {
+                out.push(next);
+                i += 1;
+            } else if c == q {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+        if !(i > 0 && (is_ident(&chars[i - 1]) || chars[i - 1] == '.'))
+            && let Some(word) = ["super", "import.meta"].into_iter().find(|w| {
+                chars[i..].starts_with(&w.chars().collect::<Vec<_>>()) && !chars.get(i + w.len()).is_some_and(is_ident)
+            })
+        {
+            out.push_str(&format!("__deqjs[{}]", js_string_literal(word)));
+            i += word.len();
+            continue;
+        }
+        match c {
+            '"' | '\'' | '`' => quote = Some(c),
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                out.extend(&chars[i..]);
+                break;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let end = (i + 2..chars.len()).find(|&j| chars[j] == '*' && chars.get(j + 1) == Some(&'/')).map_or(chars.len(), |j| j + 2);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '<' if chars.get(i + 1).is_some_and(|n| n.is_ascii_alphabetic() || *n == '_') => {
+                let mut depth = 0;
+                let end = (i..chars.len()).find(|&j| {
+                    match chars[j] {
+                        '<' => depth += 1,
+                        '>' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                });
+                if let Some(end) = end {
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    out.push_str(&format!("__deqjs[{}]", js_string_literal(&inner)));
+                    i = end + 1;
+                    continue;
+                }
             }
-            decompile_functions_with(&funcs, options, &atoms, decode_instructions)
+            _ => {}
         }
-        DecompileVersion::Auto => unreachable!(),
+        out.push(c);
+        i += 1;
     }
+    out
 }
 
+
 pub fn decompile(bytecode: &[u8]) -> Result<String, DeqjsError> {
     decompile_with_options(bytecode, DecompileOptions::default())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Immediates are fixed-width little-endian in every bytecode version, so
+    // an opcode's size is one byte plus its operand's width. A drifted
+    // opcode header would otherwise shift every later pc without an error.
+    fn operand_width(fmt: tables::OpFmt) -> usize {
+        use tables::OpFmt::*;
+        match fmt {
+            NONE | NONE_INT | NONE_LOC | NONE_ARG | NONE_VAR_REF | NPOPX => 0,
+            U8 | I8 | LABEL8 | CONST8 | LOC8 => 1,
+            U16 | I16 | LOC | ARG | VAR_REF | NPOP | LABEL16 => 2,
+            NPOP_U16 | I32 | U32 | LABEL | CONST | ATOM => 4,
+            ATOM_U8 => 5,
+            LABEL_U16 | ATOM_U16 => 6,
+            U32X2 => 8,
+            ATOM_LABEL_U8 => 9,
+            ATOM_LABEL_U16 => 10,
+        }
+    }
+
+    #[test]
+    fn opcode_sizes_match_their_operand_format() {
+        let current = tables::OPCODE_INFO.iter().map(|info| (info.name, info.fmt, info.size));
+        let legacy = OPCODE_INFO_V1.iter().map(|info| (info.name, v1_fmt_to_current(info.fmt), info.size));
+        for (name, fmt, size) in current.chain(legacy) {
+            assert_eq!(size as usize, 1 + operand_width(fmt), "{name} ({})", fmt_name(fmt));
+        }
+    }
+
+    #[test]
+    fn npopx_opcodes_name_their_argument_count() {
+        let current = (0..=u8::MAX).filter_map(opcode_by_value).map(|op| (op.name, op.fmt, op.n_pop));
+        let legacy = OPCODE_INFO_V1.iter().map(|info| (info.name, v1_fmt_to_current(info.fmt), info.n_pop));
+        for (name, _, n_pop) in current.chain(legacy).filter(|(_, fmt, _)| *fmt == tables::OpFmt::NPOPX) {
+            assert!(npopx_argc(name).is_some(), "{name} has no argument count in its name");
+            assert!(n_pop >= 1, "{name} doesn't pop the callee");
+        }
+    }
+
+    #[test]
+    fn pop_count_operand_must_match_the_format() {
+        let call = decode_instr(&[opcode_by_name("call").unwrap().value, 2, 0], 0).unwrap();
+        assert_eq!(call.operand, Some(Operand::NPop(2)));
+        assert!(check_stack_effect(&call).is_ok());
+
+        let err = check_stack_effect(&Instr { operand: Some(Operand::U16(2)), ..call.clone() }).unwrap_err();
+        assert!(matches!(err, DeqjsError::StackEffectMismatch { pc: 0, name: "call", .. }), "{err:?}");
+        let err = check_stack_effect(&Instr { fmt: tables::OpFmt::U16, ..call }).unwrap_err();
+        assert!(matches!(err, DeqjsError::StackEffectMismatch { name: "call", .. }), "{err:?}");
+    }
+}
@@ -0,0 +1,123 @@
+//! The public analysis API: instruction decoding, the control-flow graph and
+//! the opcode table, on fixtures from `fixtures/`.
+
+use deqjs_lib::{DecompileMode, DecompileOptions, Instr, OpFmt, ParsedProgram, build_cfg, c_array_bytes, cfg_to_dot, decompile_from_c_array, decompile_with_options, disassemble_function, opcode_by_name, opcode_by_value, parse};
+
+/// `f(a) { var r; if (a) r = g(); else r = h(); return r; }`: a branch, its
+/// two arms and the block where they join.
+fn if_else() -> ParsedProgram {
+    parse(include_bytes!("fixtures/if_else.jsc")).unwrap()
+}
+
+fn instrs_of(program: &ParsedProgram, name: &str) -> Vec<Instr> {
+    let f = program.functions().into_iter().find(|f| f.func_name.to_string() == name).unwrap();
+    disassemble_function(f, program.version).unwrap()
+}
+
+#[test]
+fn disassembled_instructions_cover_the_bytecode() {
+    let program = if_else();
+    for f in program.functions() {
+        let instrs = disassemble_function(f, program.version).unwrap();
+        let mut pc = 0;
+        for ins in &instrs {
+            assert_eq!(ins.pc, pc, "{} at {}", ins.name, ins.pc);
+            pc += ins.size as usize;
+        }
+        assert_eq!(pc, f.bytecode.len());
+    }
+}
+
+#[test]
+fn dot_has_a_node_per_block_and_an_edge_per_successor() {
+    let dot = cfg_to_dot(&instrs_of(&if_else(), "f"));
+    let nodes = dot.lines().filter(|l| l.contains(" [label=")).count();
+    let edges: Vec<_> = dot.lines().filter(|l| l.contains(" -> ")).collect();
+    assert_eq!(nodes, 4, "{dot}");
+    assert_eq!(edges.len(), 4, "{dot}");
+    // only the branch's two edges are colored
+    assert_eq!(edges.iter().filter(|l| l.contains("color=")).count(), 2, "{dot}");
+}
+
+#[test]
+fn join_block_has_both_arms_as_predecessors() {
+    let blocks = build_cfg(&instrs_of(&if_else(), "f"));
+    let [branch, then, otherwise, join] = blocks.as_slice() else { panic!("{blocks:#?}") };
+    assert!(branch.preds.is_empty());
+    assert_eq!(then.preds, [branch.start_pc]);
+    assert_eq!(otherwise.preds, [branch.start_pc]);
+    assert_eq!(join.preds, [then.start_pc, otherwise.start_pc]);
+}
+
+#[test]
+fn debug_info_maps_pcs_to_source_lines() {
+    let bytecode = include_bytes!("fixtures/debug_lines.jsc");
+    let program = parse(bytecode).unwrap();
+    let f = program.functions().into_iter().find(|f| f.func_name.to_string() == "f").unwrap();
+    let debug = f.debug.as_ref().unwrap();
+    assert_eq!(debug.filename.to_string(), "debug_lines.js");
+    assert_eq!(debug.line_num, 1);
+    // `get_arg0` is charged to the header, the rest to `var b = a + 1`
+    assert_eq!(debug.line_at(0), 1);
+    assert_eq!((1..f.bytecode.len()).map(|pc| debug.line_at(pc)).collect::<Vec<_>>(), [2; 4]);
+
+    let options = |mode| DecompileOptions { mode, ..Default::default() };
+    let disasm = decompile_with_options(bytecode, options(DecompileMode::Disasm)).unwrap();
+    assert!(disasm.contains("// line 1\n00000 get_arg0 "), "{disasm}");
+    assert!(disasm.contains("// line 2\n00001 push_1 "), "{disasm}");
+    let pseudo = decompile_with_options(bytecode, options(DecompileMode::Pseudo)).unwrap();
+    assert!(pseudo.contains("  // line 2\n  b = a + 1;\n"), "{pseudo}");
+}
+
+#[test]
+fn c_array_elements_parse_in_every_base() {
+    let src = "static const unsigned char a[] = { 0x17, 010, 9, };\nconst uint8_t b[2] = { 0xFF, 0 };";
+    assert_eq!(c_array_bytes(src, None).unwrap(), [0x17, 8, 9]);
+    assert_eq!(c_array_bytes(src, Some("b")).unwrap(), [0xff, 0]);
+
+    let c = include_str!("fixtures/new_call.c");
+    let bytes = c_array_bytes(c, Some("qjsc_new_call")).unwrap();
+    assert_eq!(bytes, include_bytes!("fixtures/new_call.jsc"));
+    // `padding` comes first and isn't bytecode
+    assert!(decompile_from_c_array(c, DecompileOptions::default()).is_err());
+}
+
+#[test]
+fn stack_depth_returns_to_zero() {
+    let options = DecompileOptions { mode: DecompileMode::Disasm, show_stack: true, ..Default::default() };
+    let out = decompile_with_options(include_bytes!("fixtures/if_else.jsc"), options).unwrap();
+    assert!(!out.contains("underflow") && !out.contains("unreachable"), "{out}");
+    // both arms leave `r` in the local, so the join starts empty again
+    assert!(out.contains("00019 get_loc0                 <fmt:none_loc>  // stack 0 -> 1\n"), "{out}");
+    for function in out.split("\n\n") {
+        assert!(function.trim_end().ends_with("// stack 1 -> 0"), "{function}");
+    }
+}
+
+#[test]
+fn opcodes_look_up_both_ways() {
+    let get_loc = opcode_by_name("get_loc").unwrap();
+    assert_eq!((get_loc.size, get_loc.n_pop, get_loc.n_push, get_loc.fmt), (3, 0, 1, OpFmt::LOC));
+    assert_eq!(opcode_by_value(get_loc.value), Some(get_loc));
+
+    let push_i32 = opcode_by_value(opcode_by_name("push_i32").unwrap().value).unwrap();
+    assert_eq!((push_i32.name, push_i32.size, push_i32.fmt), ("push_i32", 5, OpFmt::I32));
+    assert_eq!(opcode_by_name("no_such_op"), None);
+}
+
+#[test]
+fn json_stays_parseable_when_truncated_or_lenient() {
+    // one version below the supported one, which only `lenient_version` reads
+    let mut bytecode = include_bytes!("fixtures/params.jsc").to_vec();
+    bytecode[0] -= 1;
+    let json = |max_output_bytes| {
+        let options = DecompileOptions { mode: DecompileMode::Json, lenient_version: true, max_output_bytes, ..Default::default() };
+        let out = decompile_with_options(&bytecode, options).unwrap();
+        serde_json::from_str::<serde_json::Value>(&out).unwrap_or_else(|e| panic!("{e}: {out}"))
+    };
+    let names = |json: serde_json::Value| json.as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+    assert_eq!(names(json(None)), ["<eval>", "f", "g"]);
+    // `g` doesn't fit after the other two, and isn't cut short
+    assert_eq!(names(json(Some(1300))), ["<eval>", "f"]);
+    assert_eq!(names(json(Some(300))), Vec::<String>::new());
+}
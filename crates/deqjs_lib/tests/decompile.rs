@@ -0,0 +1,199 @@
+//! Pseudo code for small scripts. Each fixture in `fixtures/` is the bytecode
+//! of the `.js` file of the same name, compiled with its debug info.
+
+use deqjs_lib::{DecompileOptions, FunctionKind, VarKind, decompile_with_options, opcode_by_name, parse};
+
+fn decompile(bytecode: &[u8], options: DecompileOptions) -> String {
+    decompile_with_options(bytecode, options).unwrap()
+}
+
+/// The statements of the function whose header is `header`, one per line,
+/// without the labels and `// line N` comments around them.
+fn body(out: &str, header: &str) -> Vec<String> {
+    let start = out.find(&format!("{header} {{\n")).unwrap_or_else(|| panic!("no `{header}` in:\n{out}"));
+    out[start..]
+        .lines()
+        .skip(1)
+        .take_while(|line| *line != "}")
+        .map(str::trim)
+        .filter(|line| !line.starts_with("// line "))
+        .filter(|line| line.strip_prefix('L').and_then(|l| l.strip_suffix(':')).is_none_or(|pc| pc.parse::<usize>().is_err()))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn fused_length_evaluates_its_object_once() {
+    let out = decompile(include_bytes!("fixtures/get_length.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(foo)"), ["return foo().length;"]);
+}
+
+#[test]
+fn computed_member_assignment_keeps_its_grouping() {
+    let out = decompile(include_bytes!("fixtures/member_assign.jsc"), DecompileOptions::default());
+    // `a[b = c] = d`: the key's assignment runs first, then the store
+    assert_eq!(body(&out, "function f(a, b, c, d)"), ["b = c;", "a[c] = d;", "return;"]);
+    assert_eq!(body(&out, "function g(x, y, i, v)"), ["(x || y)[i] = v;", "return;"]);
+}
+
+#[test]
+fn method_chain_keeps_each_receiver() {
+    let out = decompile(include_bytes!("fixtures/method_chain.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(a)"), ["return a.b().c().d();"]);
+    // the copy `insert2` leaves is the stored value, not another call
+    assert_eq!(body(&out, "function g(o)"), ["o.x = h();", "return o.x;"]);
+    assert_eq!(body(&out, "function k(o)"), ["o.n = o.n + 1;", "return o.n;"]);
+}
+
+#[test]
+fn async_and_generator_functions_keep_their_keywords() {
+    let bytecode = include_bytes!("fixtures/async_generator.jsc");
+    let kinds: Vec<_> = parse(bytecode).unwrap().functions().iter().map(|f| (f.func_name.to_string(), f.func_flags.unwrap().kind)).collect();
+    assert!(kinds.contains(&("fetchAll".to_string(), FunctionKind::Async)), "{kinds:?}");
+    assert!(kinds.contains(&("count".to_string(), FunctionKind::Generator)), "{kinds:?}");
+
+    let out = decompile(bytecode, DecompileOptions::default());
+    assert!(out.contains("\nasync function fetchAll(x) {\n"), "{out}");
+    assert!(out.contains("\nfunction* count() {\n"), "{out}");
+}
+
+#[test]
+fn parameters_are_named_in_the_signature() {
+    let out = decompile(include_bytes!("fixtures/params.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(a, b)"), ["return a + b;"]);
+    // the default is past `defined_arg_count`
+    assert!(out.contains("\nfunction g(a, b = 2) {\n"), "{out}");
+}
+
+#[test]
+fn method_call_renders_its_receiver_once() {
+    let out = decompile(include_bytes!("fixtures/method_call.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(o)"), ["o.foo(1, 2);", "return;"]);
+}
+
+#[test]
+fn constructor_call_keeps_new() {
+    let out = decompile(include_bytes!("fixtures/new_call.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f()"), ["return new Date(0);"]);
+}
+
+#[test]
+fn object_definitions_collapse_into_a_literal() {
+    let out = decompile(include_bytes!("fixtures/object_literal.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f()"), ["return { a: 1, b: 2 };"]);
+    assert_eq!(body(&out, "function g(x)"), ["return { a: x, m() { /* <null> */ } };"]);
+}
+
+#[test]
+fn loop_exits_become_break() {
+    let out = decompile(include_bytes!("fixtures/loop_break.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(n)"), ["for (i = 0; i < n; i++) {", "if (i == 3) break;", "g(i);", "}", "return;"]);
+    // leaving the outer loop from the inner one names it
+    let k = body(&out, "function k(a)");
+    assert_eq!(k[..3], ["while (a.length) {", "while (g(a)) {", "if (a[0]) break L0;"]);
+    assert!(out.contains("  L0:\n  while (a.length) {\n"), "{out}");
+}
+
+#[test]
+fn value_diamonds_become_operators() {
+    let out = decompile(include_bytes!("fixtures/conditional.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function t(c, a, b)"), ["return g(c ? a : b);"]);
+    assert_eq!(body(&out, "function and(a, b)"), ["return a && b;"]);
+    assert_eq!(body(&out, "function or(a, b)"), ["return a || b;"]);
+}
+
+#[test]
+fn locals_keep_their_source_names() {
+    let out = decompile(include_bytes!("fixtures/locals.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(x)"), ["total = 0;", "count = 1;", "total = total + x * count;", "return total;"]);
+    // past loc3 the index is an operand
+    assert_eq!(body(&out, "function g(a)").last().unwrap(), "return p + q + r + s + t + u;");
+    // the block's `v` shadows the function's
+    assert_eq!(body(&out, "function h()"), ["v = 1;", "v_1 = undefined;", "v_1 = 2;", "k(v_1);", "return v;"]);
+}
+
+#[test]
+fn declared_locals_use_their_binding_keyword() {
+    let bytecode = include_bytes!("fixtures/lexical.jsc");
+    let program = parse(bytecode).unwrap();
+    let f = program.functions().into_iter().find(|f| f.func_name.to_string() == "f").unwrap();
+    let kinds: Vec<_> = f.locals.iter().map(|local| (local.name.to_string(), local.kind())).collect();
+    for expected in [("k", VarKind::Const), ("v", VarKind::Let), ("w", VarKind::Var)] {
+        assert!(kinds.contains(&(expected.0.to_string(), expected.1)), "{kinds:?}");
+    }
+
+    let out = decompile(bytecode, DecompileOptions { declare_locals: true, ..Default::default() });
+    assert_eq!(body(&out, "function f()"), ["const k = 1;", "let v = 2;", "var w = 3;", "v = v + 1;", "return k + v + w;"]);
+}
+
+#[test]
+fn global_definitions_become_declarations() {
+    let out = decompile(include_bytes!("fixtures/vars.jsc"), DecompileOptions::default());
+    let script = body(&out, "function <eval>()");
+    assert_eq!(script[..3], ["var a;", "var b;", "function g() { /* g */ }"]);
+    assert_eq!(script[3..5], ["a = 1;", "b = 2;"]);
+}
+
+#[test]
+fn super_method_call_keeps_super() {
+    let out = decompile(include_bytes!("fixtures/super_call.jsc"), DecompileOptions::default());
+    // `B.prototype.foo`; class methods don't carry a name of their own
+    assert!(out.contains("  // line 2\n  loc0 = this;\n  return super.foo();\n}"), "{out}");
+}
+
+#[test]
+fn private_fields_keep_their_hash() {
+    let out = decompile(include_bytes!("fixtures/private_field.jsc"), DecompileOptions::default());
+    assert!(out.contains("  loc0 = this;\n  loc0.#count = loc0.#count + 1;\n  return loc0.#count;\n}"), "{out}");
+    // the field initializer
+    assert!(out.contains("\n  #count = 0;\n"), "{out}");
+}
+
+#[test]
+fn special_objects_render_as_keywords() {
+    let out = decompile(include_bytes!("fixtures/new_target.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function F()"), ["new_target = new.target;", "return new_target;"]);
+    assert_eq!(body(&out, "function G(a)"), ["loc0 = arguments;", "return loc0.length;"]);
+}
+
+#[test]
+fn parentheses_follow_precedence() {
+    let out = decompile(include_bytes!("fixtures/precedence.jsc"), DecompileOptions::default());
+    assert_eq!(body(&out, "function f(a, b, c)"), ["return [a + b * c, (a + b) * c, a - (b - c), a - b - c, -(a + b)];"]);
+}
+
+#[test]
+fn optimize_folds_constant_arithmetic() {
+    let bytecode = include_bytes!("fixtures/const_fold.jsc");
+    let out = decompile(bytecode, DecompileOptions { optimize: true, ..Default::default() });
+    assert!(out.contains("\nfunction f() { return [5, 1, 16]; }\n"), "{out}");
+    let out = decompile(bytecode, DecompileOptions::default());
+    assert_eq!(body(&out, "function f()"), ["return [2 + 3, 10 % 3, 1 << 4];"]);
+}
+
+#[test]
+fn optimize_turns_string_concatenation_into_a_template() {
+    let bytecode = include_bytes!("fixtures/template.jsc");
+    let out = decompile(bytecode, DecompileOptions { optimize: true, ..Default::default() });
+    assert!(out.contains("\nfunction greet(name) { return `Hello, ${name}!`; }\n"), "{out}");
+    let out = decompile(bytecode, DecompileOptions::default());
+    assert_eq!(body(&out, "function greet(name)"), ["return \"Hello, \" + name + \"!\";"]);
+}
+
+#[test]
+fn spread_elements_append_to_the_array() {
+    let op = |name| opcode_by_name(name).unwrap().value;
+    let bytecode = include_bytes!("fixtures/spread.jsc");
+    let out = decompile(bytecode, DecompileOptions::default());
+    assert_eq!(body(&out, "function f(a, b)"), ["return [1, ...a, 2, ...b];"]);
+    assert_eq!(body(&out, "function g(a)"), ["return h(...a);"]);
+
+    // `f` building onto `b` instead of a fresh `[1]`: `append` grows it in place
+    let mut bytecode = bytecode.to_vec();
+    let head = [op("push_1"), op("array_from"), 1, 0];
+    let at = bytecode.windows(head.len()).position(|w| w == head).unwrap();
+    bytecode[at..at + 4].copy_from_slice(&[op("get_arg1"), op("nop"), op("nop"), op("nop")]);
+    let out = decompile(&bytecode, DecompileOptions::default());
+    let f = body(&out, "function f(a, b)");
+    assert_eq!(f[f.len() - 4..], ["b.push(...a);", "b.push(2);", "b.push(...b);", "return b;"]);
+}
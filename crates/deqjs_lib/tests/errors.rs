@@ -0,0 +1,63 @@
+//! Malformed input: each reader has to fail with an error, not a panic or a
+//! runaway allocation.
+
+use deqjs_lib::{DecompileMode, DecompileOptions, DeqjsError, decompile_with_options, opcode_by_name, opcode_by_value, parse};
+
+/// The version byte the `qjsc` behind `fixtures/` writes.
+const VERSION: u8 = 23;
+
+#[test]
+fn huge_count_in_a_truncated_header_is_eof() {
+    // an atom table claiming 0xFFFF_FFFF entries, with nothing after it
+    let err = parse(&[VERSION, 0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap_err();
+    assert!(matches!(err, DeqjsError::Eof { pos: 6, needed: 0xFFFF_FFFF }), "{err:?}");
+    // no atoms, then an array of as many items
+    let err = parse(&[VERSION, 0, 9, 0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap_err();
+    assert!(matches!(err, DeqjsError::Eof { .. }), "{err:?}");
+}
+
+#[test]
+fn overlong_leb128_is_not_eof() {
+    // six bytes of continuation cannot fit in 32 bits
+    let err = parse(&[VERSION, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]).unwrap_err();
+    assert!(matches!(err, DeqjsError::InvalidLeb128 { pos: 1 }), "{err:?}");
+    // a continuation bit on the last byte of the file
+    let err = parse(&[VERSION, 0x80]).unwrap_err();
+    assert!(matches!(err, DeqjsError::Eof { pos: 2, needed: 1 }), "{err:?}");
+}
+
+#[test]
+fn recover_skips_one_bad_byte() {
+    let op = |name| opcode_by_name(name).unwrap().value;
+    let bad = (0..=u8::MAX).rev().find(|&value| opcode_by_value(value).is_none()).unwrap();
+    // `f`'s `return a + b` with the `add` overwritten
+    let mut bytecode = include_bytes!("fixtures/params.jsc").to_vec();
+    let body = [op("get_arg0"), op("get_arg1"), op("add"), op("return")];
+    let at = bytecode.windows(body.len()).position(|w| w == body).unwrap();
+    bytecode[at + 2] = bad;
+
+    let disasm = |recover| decompile_with_options(&bytecode, DecompileOptions { mode: DecompileMode::Disasm, recover, ..Default::default() });
+    let err = disasm(false).unwrap_err();
+    assert!(matches!(err, DeqjsError::InvalidOpcode(op) if op == bad), "{err:?}");
+    let out = disasm(true).unwrap();
+    let f = out.find("function f ").unwrap();
+    let ops: Vec<_> = out[f..].lines().skip(3).take(4).map(|line| line.split_whitespace().nth(1).unwrap()).collect();
+    assert_eq!(ops, ["get_arg0", "get_arg1", ".byte", "return"], "{out}");
+    assert!(out.contains(&format!("00002 .byte 0x{bad:02x}\n")), "{out}");
+    // `g` has the same tail and is untouched
+    assert!(out.contains("00019 add"), "{out}");
+}
+
+#[test]
+fn fallback_reports_stack_underflow() {
+    let op = |name| opcode_by_name(name).unwrap().value;
+    // `f`'s `return a + b` with the operands gone and `set_proto`, which the
+    // generic fallback renders, popping two values that were never pushed
+    let mut bytecode = include_bytes!("fixtures/params.jsc").to_vec();
+    let body = [op("get_arg0"), op("get_arg1"), op("add"), op("return")];
+    let at = bytecode.windows(body.len()).position(|w| w == body).unwrap();
+    bytecode[at..at + 3].copy_from_slice(&[op("nop"), op("nop"), op("set_proto")]);
+
+    let out = decompile_with_options(&bytecode, DecompileOptions::default()).unwrap();
+    assert!(out.contains("// stack underflow: set_proto pops 2 of 0;"), "{out}");
+}
@@ -0,0 +1,2 @@
+async function fetchAll(x) { await x; }
+function* count() { yield 1; }
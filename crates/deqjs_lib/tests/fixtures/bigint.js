@@ -0,0 +1 @@
+function f() { return [0n, 42n, -42n, 123456789012345678901234567890n]; }
@@ -0,0 +1,3 @@
+function t(c, a, b) { return g(c ? a : b); }
+function and(a, b) { return a && b; }
+function or(a, b) { return a || b; }
@@ -0,0 +1 @@
+function f() { return [2 + 3, 10 % 3, 1 << 4]; }
@@ -0,0 +1,4 @@
+function f(a) {
+  var b = a + 1;
+  return b;
+}
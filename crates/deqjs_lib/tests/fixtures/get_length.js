@@ -0,0 +1 @@
+function f(foo) { return foo().length; }
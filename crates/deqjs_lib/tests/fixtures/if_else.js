@@ -0,0 +1 @@
+function f(a) { var r; if (a) r = g(); else r = h(); return r; }
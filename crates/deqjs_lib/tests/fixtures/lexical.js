@@ -0,0 +1 @@
+function f() { const k = 1; let v = 2; var w = 3; v++; return k + v + w; }
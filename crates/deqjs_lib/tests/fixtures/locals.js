@@ -0,0 +1,3 @@
+function f(x) { var total = 0; var count = 1; total = total + x * count; return total; }
+function g(a) { var p = a, q = a, r = a, s = a, t = a, u = a; return p + q + r + s + t + u; }
+function h() { var v = 1; { let v = 2; k(v); } return v; }
@@ -0,0 +1,2 @@
+function f(n) { for (var i = 0; i < n; i++) { if (i == 3) break; g(i); } }
+function k(a) { outer: while (a.length) { while (g(a)) { if (a[0]) break outer; a.pop(); } a.shift(); } return 1; }
@@ -0,0 +1,2 @@
+function f(a, b, c, d) { a[b = c] = d; }
+function g(x, y, i, v) { (x || y)[i] = v; }
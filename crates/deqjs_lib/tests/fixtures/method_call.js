@@ -0,0 +1 @@
+function f(o) { o.foo(1, 2); }
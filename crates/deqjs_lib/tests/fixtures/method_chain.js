@@ -0,0 +1,3 @@
+function f(a) { return a.b().c().d(); }
+function g(o) { return o.x = h(); }
+function k(o) { return ++o.n; }
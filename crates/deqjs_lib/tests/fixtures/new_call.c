@@ -0,0 +1,21 @@
+#include <inttypes.h>
+
+/* not bytecode, so `--symbol` has to pick the other one */
+static const uint8_t padding[4] = { 1, 2, 3, 4 };
+
+const uint32_t qjsc_new_call_size = 130;
+
+const uint8_t qjsc_new_call[130] = {
+  0x17, 0x02, 0x01, 0x02, 0x66, 0x01, 0x16, 0x6e, 0x65, 0x77, 0x5f, 0x63,
+  0x61, 0x6c, 0x6c, 0x2e, 0x6a, 0x73, 0x0c, 0x00, 0x0a, 0x00, 0xa2, 0x01,
+  0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x10, 0x01, 0xa4, 0x01, 0x00,
+  0x00, 0x00, 0x0c, 0x43, 0x0a, 0x00, 0xc6, 0x03, 0x00, 0x00, 0x00, 0x03,
+  0x00, 0x00, 0x00, 0x0b, 0x00, 0x39, 0x9e, 0x00, 0x00, 0x00, 0x11, 0xb6,
+  0x21, 0x01, 0x00, 0x28, 0xc8, 0x03, 0x01, 0x01, 0x02, 0x02, 0x32, 0x24,
+  0x66, 0x75, 0x6e, 0x63, 0x74, 0x69, 0x6f, 0x6e, 0x20, 0x66, 0x28, 0x29,
+  0x20, 0x7b, 0x20, 0x72, 0x65, 0x74, 0x75, 0x72, 0x6e, 0x20, 0x6e, 0x65,
+  0x77, 0x20, 0x44, 0x61, 0x74, 0x65, 0x28, 0x30, 0x29, 0x3b, 0x20, 0x7d,
+  0x40, 0xe3, 0x00, 0x00, 0x00, 0x40, 0xc1, 0x00, 0x41, 0xe3, 0x00, 0x00,
+  0x00, 0x00, 0xc7, 0x28, 0xc8, 0x03, 0x01, 0x01, 0x00, 0x00,
+
+};
@@ -0,0 +1 @@
+function f() { return new Date(0); }
@@ -0,0 +1,2 @@
+function F() { return new.target; }
+function G(a) { return arguments.length; }
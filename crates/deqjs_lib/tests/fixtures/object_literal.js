@@ -0,0 +1,2 @@
+function f() { return ({a: 1, b: 2}); }
+function g(x) { return {a: x, m() { return 2; }}; }
@@ -0,0 +1,2 @@
+function f(a, b) { return a + b; }
+function g(a, b = 2) { return a + b; }
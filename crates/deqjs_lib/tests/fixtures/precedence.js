@@ -0,0 +1 @@
+function f(a, b, c) { return [a + b * c, (a + b) * c, a - (b - c), a - b - c, -(a + b)]; }
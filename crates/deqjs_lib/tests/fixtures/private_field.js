@@ -0,0 +1 @@
+class Counter { #count = 0; inc() { return ++this.#count; } }
@@ -0,0 +1,2 @@
+// written with JS_WriteObject(ctx, &len, v, JS_WRITE_OBJ_BYTECODE | JS_WRITE_OBJ_REFERENCE), v being
+({ b: 1, 2: [3, "x"], a: {}, 1: null })
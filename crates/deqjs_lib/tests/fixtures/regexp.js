@@ -0,0 +1 @@
+function f() { return /ab+c/gi; }
@@ -0,0 +1,2 @@
+// written with JS_WriteObject(ctx, &len, v, JS_WRITE_OBJ_BYTECODE | JS_WRITE_OBJ_REFERENCE), v being
+(() => { const o = { name: "self" }; o.self = o; return o; })()
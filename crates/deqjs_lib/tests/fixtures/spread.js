@@ -0,0 +1,2 @@
+function f(a, b) { return [1, ...a, 2, ...b]; }
+function g(a) { return h(...a); }
@@ -0,0 +1,2 @@
+class A { foo() { return 1; } }
+class B extends A { foo() { return super.foo(); } }
@@ -0,0 +1 @@
+function greet(name) { return "Hello, " + name + "!"; }
@@ -0,0 +1,110 @@
+//! Strings, atoms and constant values as the readers decode and `Display`
+//! renders them.
+
+use deqjs_lib::{AtomRepr, DecompileOptions, DecompileVersion, Value, atoms, bigint_to_decimal, decompile_with_options, regexp_flags, typed_array_name};
+
+#[test]
+fn surrogate_pair_decodes_to_one_char() {
+    let atoms = atoms(include_bytes!("fixtures/emoji.jsc"), DecompileVersion::Auto, false).unwrap();
+    let emoji = atoms
+        .iter()
+        .find_map(|a| match &a.atom {
+            AtomRepr::String(s) if !s.is_ascii() => Some(s.as_str()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(emoji, "😀");
+    assert_eq!(emoji.chars().count(), 1);
+}
+
+#[test]
+fn typed_array_kinds_name_their_constructors() {
+    let names = [
+        "Uint8ClampedArray",
+        "Int8Array",
+        "Uint8Array",
+        "Int16Array",
+        "Uint16Array",
+        "Int32Array",
+        "Uint32Array",
+        "BigInt64Array",
+        "BigUint64Array",
+        "Float16Array",
+        "Float32Array",
+        "Float64Array",
+    ];
+    for (kind, name) in names.iter().enumerate() {
+        assert_eq!(typed_array_name(kind as u8), *name);
+    }
+    assert_eq!(typed_array_name(names.len() as u8), "");
+
+    let typed_array = |kind| Value::TypedArray { kind, len: 16, offset: 0, buffer: Box::new(Value::ArrayBuffer { bytes: vec![0; 16] }) };
+    assert_eq!(typed_array(2).to_string(), "<Uint8Array len=16>");
+    assert_eq!(typed_array(200).to_string(), "<unknown-typedarray:200 len=16>");
+}
+
+#[test]
+fn date_renders_as_iso_timestamp() {
+    let date = |value| Value::Date { value: Box::new(value) };
+    assert_eq!(date(Value::Float64(1_620_043_200_000.0)).to_string(), "Date(2021-05-03T12:00:00.000Z)");
+    assert_eq!(date(Value::Float64(0.0)).to_string(), "Date(1970-01-01T00:00:00.000Z)");
+    assert_eq!(date(Value::Float64(f64::NAN)).to_string(), "Date(Invalid Date)");
+    assert_eq!(date(Value::Int32(0)).to_string(), "<date>");
+}
+
+#[test]
+fn self_reference_resolves_to_the_enclosing_object() {
+    let program = deqjs_lib::parse(include_bytes!("fixtures/self_ref.jsc")).unwrap();
+    let Value::Object(props) = &program.value else { panic!("not an object: {:?}", program.value) };
+    let props: Vec<_> = props.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    assert_eq!(props, [("name".to_string(), "\"self\"".to_string()), ("self".to_string(), "<ref:0>".to_string())]);
+}
+
+#[test]
+fn regexp_renders_its_flags() {
+    let out = decompile_with_options(include_bytes!("fixtures/regexp.jsc"), DecompileOptions::default()).unwrap();
+    assert!(out.contains("return /ab+c/gi;"), "{out}");
+    // LRE_FLAG_GLOBAL | LRE_FLAG_IGNORECASE, then every bit in flags order
+    assert_eq!(regexp_flags(&[0b11]), "gi");
+    assert_eq!(regexp_flags(&[0x7f]), "dgimsuy");
+    assert_eq!(regexp_flags(&[]), "");
+}
+
+#[test]
+fn bigint_renders_in_decimal() {
+    let out = decompile_with_options(include_bytes!("fixtures/bigint.jsc"), DecompileOptions::default()).unwrap();
+    assert!(out.contains("return [0n, 42n, -42n, 123456789012345678901234567890n];"), "{out}");
+    // little-endian two's complement, `0n` has no limbs at all
+    assert_eq!(bigint_to_decimal(&[]), "0");
+    assert_eq!(bigint_to_decimal(&[42]), "42");
+    assert_eq!(bigint_to_decimal(&[0x7f, 0xff]), "-129");
+    assert_eq!(Value::BigInt { bytes: vec![0xd6] }.to_string(), "-42n");
+}
+
+#[test]
+fn well_known_symbols_render_by_name() {
+    let symbol = |atom| Value::Symbol { atom }.to_string();
+    let iterator = (1..1000).map(AtomRepr::Builtin).find(|atom| atom.to_string() == "Symbol.iterator").unwrap();
+    assert_eq!(symbol(iterator), "Symbol.iterator");
+    assert_eq!(symbol(AtomRepr::Symbol { typ: 3, desc: "tag".to_string() }), "Symbol(\"tag\")");
+    assert_eq!(symbol(AtomRepr::Symbol { typ: 2, desc: "app.key".to_string() }), "Symbol.for(\"app.key\")");
+}
+
+#[test]
+fn int_past_32_bits_is_int64() {
+    // version 23, no atoms, then BC_TAG_INT32 and a zigzag sleb128
+    let int = |sleb: &[u8]| deqjs_lib::parse(&[&[23, 0, 5], sleb].concat()).unwrap().value;
+    assert!(matches!(int(&[0x80, 0x80, 0x80, 0x80, 0x20]), Value::Int64(0x1_0000_0000)));
+    assert!(matches!(int(&[0xff, 0xff, 0xff, 0xff, 0x1f]), Value::Int64(-0x1_0000_0000)));
+    assert!(matches!(int(&[0xfe, 0xff, 0xff, 0xff, 0x0f]), Value::Int32(i32::MAX)));
+    assert!(matches!(int(&[0x0a]), Value::Int32(5)));
+}
+
+#[test]
+fn data_objects_keep_or_normalize_their_property_order() {
+    let bytecode = include_bytes!("fixtures/prop_order.jsc");
+    let out = |normalize_property_order| decompile_with_options(bytecode, DecompileOptions { normalize_property_order, ..Default::default() }).unwrap();
+    // QuickJS serializes in creation order, integer keys included
+    assert_eq!(out(false), "{ b: 1, 2: [3, \"x\"], a: {}, 1: null }\n");
+    assert_eq!(out(true), "{ 1: null, 2: [3, \"x\"], b: 1, a: {} }\n");
+}